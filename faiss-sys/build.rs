@@ -2,12 +2,114 @@ fn main() {
     #[cfg(feature = "static")]
     static_link_faiss();
     #[cfg(not(feature = "static"))]
-    println!("cargo:rustc-link-lib=faiss_c");
+    prebuilt_link_faiss();
+}
+
+/// Whether the environment variable with the given name is set to a
+/// value other than an empty string, `0`, `false`, `no` or `off`.
+#[cfg(not(feature = "static"))]
+fn env_flag(name: &str) -> bool {
+    println!("cargo:rerun-if-env-changed={}", name);
+    match std::env::var(name) {
+        Ok(s) => !matches!(
+            s.trim().to_ascii_lowercase().as_str(),
+            "" | "0" | "false" | "no" | "off"
+        ),
+        Err(_) => false,
+    }
+}
+
+/// A BLAS implementation to link against.
+#[derive(Debug, Clone, PartialEq)]
+enum Blas {
+    /// Intel MKL, through its single dynamic library
+    Mkl,
+    /// OpenBLAS, which also provides LAPACK
+    OpenBlas,
+    /// Whichever `blas` and `lapack` libraries the system provides
+    System,
+    /// An explicit list of libraries
+    Custom(Vec<String>),
+}
+
+impl Blas {
+    /// Determine the BLAS implementation from the `FAISS_BLAS_LIB`
+    /// environment variable, then from the `mkl` and `openblas` Cargo
+    /// features. Returns `None` if none was requested.
+    fn requested() -> Option<Blas> {
+        println!("cargo:rerun-if-env-changed=FAISS_BLAS_LIB");
+        match std::env::var("FAISS_BLAS_LIB") {
+            Ok(s) => match s.trim() {
+                "" => None,
+                "mkl" => Some(Blas::Mkl),
+                "openblas" => Some(Blas::OpenBlas),
+                "system" => Some(Blas::System),
+                libs => Some(Blas::Custom(
+                    libs.split(',')
+                        .map(|l| l.trim().to_string())
+                        .filter(|l| !l.is_empty())
+                        .collect(),
+                )),
+            },
+            Err(_) if cfg!(feature = "mkl") => Some(Blas::Mkl),
+            Err(_) if cfg!(feature = "openblas") => Some(Blas::OpenBlas),
+            Err(_) => None,
+        }
+    }
+
+    /// The names of the libraries to link.
+    fn libs(&self) -> Vec<String> {
+        match self {
+            Blas::Mkl => vec!["mkl_rt".to_string()],
+            Blas::OpenBlas => vec!["openblas".to_string()],
+            Blas::System => vec!["blas".to_string(), "lapack".to_string()],
+            Blas::Custom(libs) => libs.clone(),
+        }
+    }
+
+    fn link(&self) {
+        for lib in self.libs() {
+            println!("cargo:rustc-link-lib={}", lib);
+        }
+    }
+}
+
+/// Link against an existing installation of Faiss, either dynamically
+/// or statically if `FAISS_STATIC` is set. `FAISS_LIB_DIR` may point to
+/// the directory containing the libraries.
+#[cfg(not(feature = "static"))]
+fn prebuilt_link_faiss() {
+    println!("cargo:rerun-if-env-changed=FAISS_LIB_DIR");
+    if let Ok(dir) = std::env::var("FAISS_LIB_DIR") {
+        if !dir.is_empty() {
+            println!("cargo:rustc-link-search=native={}", dir);
+        }
+    }
+    if env_flag("FAISS_STATIC") {
+        link_static_deps(&Blas::requested().unwrap_or(Blas::System));
+    } else {
+        println!("cargo:rustc-link-lib=faiss_c");
+        // the shared library normally carries its own BLAS dependency,
+        // so only link one when explicitly requested
+        if let Some(blas) = Blas::requested() {
+            blas.link();
+        }
+    }
 }
 
 #[cfg(feature = "static")]
 fn static_link_faiss() {
+    let blas = Blas::requested().unwrap_or(Blas::System);
     let mut cfg = cmake::Config::new("faiss");
+    match blas {
+        Blas::Mkl => {
+            cfg.define("BLA_VENDOR", "Intel10_64_dyn");
+        }
+        Blas::OpenBlas => {
+            cfg.define("BLA_VENDOR", "OpenBLAS");
+        }
+        Blas::System | Blas::Custom(_) => {}
+    }
     cfg.define("FAISS_ENABLE_C_API", "ON")
         .define("BUILD_SHARED_LIBS", "OFF")
         .define("CMAKE_BUILD_TYPE", "Release")
@@ -30,12 +132,16 @@ fn static_link_faiss() {
         "cargo:rustc-link-search=native={}",
         faiss_c_location.display()
     );
+    link_static_deps(&blas);
+}
+
+/// Link the static Faiss libraries and everything they depend on.
+fn link_static_deps(blas: &Blas) {
     println!("cargo:rustc-link-lib=static=faiss_c");
     println!("cargo:rustc-link-lib=static=faiss");
     link_cxx();
     println!("cargo:rustc-link-lib=gomp");
-    println!("cargo:rustc-link-lib=blas");
-    println!("cargo:rustc-link-lib=lapack");
+    blas.link();
     if cfg!(feature = "gpu") {
         let cuda_path = cuda_lib_path();
         println!("cargo:rustc-link-search=native={}/lib64", cuda_path);
@@ -44,7 +150,6 @@ fn static_link_faiss() {
     }
 }
 
-#[cfg(feature = "static")]
 fn link_cxx() {
     let cxx = match std::env::var("CXXSTDLIB") {
         Ok(s) if s.is_empty() => None,
@@ -68,7 +173,6 @@ fn link_cxx() {
     }
 }
 
-#[cfg(feature = "static")]
 fn cuda_lib_path() -> String {
     // look for CUDA_PATH in environment,
     // then CUDA_LIB_PATH,
@@ -1,7 +1,8 @@
 #!/usr/bin/env sh
 # Generate Rust bindings to the Faiss C API
 #
-# Ensure that the submodule is updated and checked out in the intended revision
+# Ensure that the submodule is updated and checked out in the intended revision,
+# and keep the `FAISS_VERSION_*` constants in src/lib.rs in sync with `repo_rev`
 if ! which bindgen > /dev/null; then
     echo "ERROR: `bindgen` not found. Please install using cargo:"
     echo "    cargo install bindgen-cli --version=^0.69"
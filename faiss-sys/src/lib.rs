@@ -12,6 +12,13 @@ mod bindings;
 #[cfg(not(feature = "gpu"))]
 pub use bindings::*;
 
+/// Major version of the Faiss C API which these bindings were generated for.
+pub const FAISS_VERSION_MAJOR: u32 = 1;
+/// Minor version of the Faiss C API which these bindings were generated for.
+pub const FAISS_VERSION_MINOR: u32 = 8;
+/// Patch version of the Faiss C API which these bindings were generated for.
+pub const FAISS_VERSION_PATCH: u32 = 0;
+
 #[cfg(test)]
 mod tests {
     use super::*;
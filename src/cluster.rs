@@ -2,7 +2,7 @@
 
 use crate::error::Result;
 use crate::faiss_try;
-use crate::index::NativeIndex;
+use crate::index::{checked_idx_t, NativeIndex};
 use faiss_sys::*;
 use std::os::raw::c_int;
 use std::{mem, ptr};
@@ -48,6 +48,8 @@ impl ClusteringParameters {
         self.inner.frozen_centroids != 0
     }
 
+    /// Getter for the `spherical` property.
+    /// Normalize the centroids after each iteration (useful for inner product clustering)
     pub fn spherical(&self) -> bool {
         self.inner.spherical != 0
     }
@@ -100,6 +102,8 @@ impl ClusteringParameters {
         self.inner.update_index = if update_index { 1 } else { 0 };
     }
 
+    /// Setter for the `spherical` property.
+    /// Normalize the centroids after each iteration (useful for inner product clustering)
     pub fn set_spherical(&mut self, spherical: bool) {
         self.inner.spherical = if spherical { 1 } else { 0 };
     }
@@ -235,7 +239,7 @@ impl Clustering {
             let n = x.len() / self.d() as usize;
             faiss_try(faiss_Clustering_train(
                 self.inner,
-                n as idx_t,
+                checked_idx_t(n)?,
                 x.as_ptr(),
                 index.inner_ptr(),
             ))?;
@@ -410,6 +414,7 @@ pub fn kmeans_clustering(d: u32, k: u32, x: &[f32]) -> Result<KMeansResult> {
 mod tests {
     use super::{kmeans_clustering, Clustering, ClusteringParameters};
     use crate::index::index_factory;
+    use crate::utils::fvec_renorm_l2;
     use crate::MetricType;
 
     #[test]
@@ -445,6 +450,37 @@ mod tests {
         assert_eq!(stats.len(), NITER as usize);
     }
 
+    #[test]
+    fn test_spherical_clustering() {
+        const D: u32 = 8;
+        const K: u32 = 3;
+        let mut params = ClusteringParameters::default();
+        params.set_spherical(true);
+        params.set_min_points_per_centroid(1);
+        assert!(params.spherical());
+
+        let mut some_data = [
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 1., 1., 1., -1., 0., 0.,
+            0., 1., 1., 0., 0., -1., 100., 100., 100., 100., -100., 100., 100., 100., -7., 1., 4.,
+            1., 2., 1., 3., -1., 120., 100., 100., 120., -100., 100., 100., 120., 0., 0., -12., 1.,
+            1., 0., 6., -1., 0., 0., -0.25, 1., 16., 24., 0., -1., 100., 10., 100., 100., 10.,
+            100., 50., 10., 20., 22., 4.5, -2., -100., 0., 0., 100.,
+        ];
+        fvec_renorm_l2(D as usize, some_data.len() / D as usize, &mut some_data);
+
+        let mut clustering = Clustering::new_with_params(D, K, &params).unwrap();
+        assert!(clustering.spherical());
+        let mut index = index_factory(D, "Flat", MetricType::InnerProduct).unwrap();
+        clustering.train(&some_data, &mut index).unwrap();
+
+        let centroids = clustering.centroids().unwrap();
+        assert_eq!(centroids.len(), K as usize);
+        for c in centroids {
+            let norm = c.iter().map(|x| x * x).sum::<f32>().sqrt();
+            assert!((norm - 1.).abs() < 1e-4, "centroid norm is {}", norm);
+        }
+    }
+
     #[test]
     fn test_simple_clustering() {
         const D: u32 = 8;
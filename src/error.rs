@@ -4,6 +4,7 @@ use faiss_sys::*;
 use std::error::Error as StdError;
 use std::ffi::CStr;
 use std::fmt;
+use std::io;
 use std::os::raw::c_int;
 
 /// Type alias for results of functions in this crate.
@@ -24,6 +25,27 @@ pub enum Error {
     ParameterName,
     /// The number of GPU resources and devices do not match.
     GpuResourcesMatch,
+    /// The input data does not match the dimensionality of the index.
+    BadDimension,
+    /// The input array is not in standard (row-major) layout.
+    BadArrayLayout,
+    /// The encoded data does not match the code size of the index.
+    BadCodeSize,
+    /// A vector count or result size is too large for the native index type.
+    Overflow,
+    /// Reading or writing data failed with the given kind of I/O error.
+    Io(io::ErrorKind),
+    /// The indexes cannot be merged, for the given reason.
+    IncompatibleMerge(&'static str),
+    /// The quantizer is not trained with the expected number of centroids.
+    BadQuantizer,
+    /// The operation is not supported by this index, either by the native
+    /// library or by the restrictions of its Rust wrapper.
+    Unsupported(&'static str),
+    /// A search found fewer than the requested number of neighbors.
+    NotEnoughResults,
+    /// A worker thread spawned by a parallel operation panicked.
+    WorkerPanicked,
 }
 
 impl fmt::Display for Error {
@@ -37,6 +59,18 @@ impl fmt::Display for Error {
             Error::GpuResourcesMatch => {
                 fmt.write_str("Number of GPU resources and devices do not match")
             }
+            Error::BadDimension => fmt.write_str("Input data does not match the index dimension"),
+            Error::BadArrayLayout => fmt.write_str("Input array is not in row-major layout"),
+            Error::BadCodeSize => fmt.write_str("Encoded data does not match the index code size"),
+            Error::Overflow => fmt.write_str("Vector count or result size overflow"),
+            Error::Io(kind) => write!(fmt, "I/O error: {:?}", kind),
+            Error::IncompatibleMerge(reason) => write!(fmt, "Indexes cannot be merged: {}", reason),
+            Error::BadQuantizer => {
+                fmt.write_str("Quantizer is not trained with the expected number of centroids")
+            }
+            Error::Unsupported(what) => write!(fmt, "Unsupported operation: {}", what),
+            Error::NotEnoughResults => fmt.write_str("Search found fewer results than requested"),
+            Error::WorkerPanicked => fmt.write_str("Worker thread panicked"),
         }
     }
 }
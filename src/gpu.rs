@@ -3,6 +3,7 @@
 use crate::error::Result;
 use crate::faiss_try;
 use faiss_sys::*;
+use std::os::raw::c_int;
 use std::ptr;
 
 /// Common interface for GPU resources used by Faiss.
@@ -108,6 +109,80 @@ impl StandardGpuResources {
             Ok(StandardGpuResources { inner: ptr })
         }
     }
+
+    /// Obtain a builder for configuring the GPU resources before they
+    /// are created.
+    pub fn builder() -> StandardGpuResourcesBuilder {
+        StandardGpuResourcesBuilder::default()
+    }
+}
+
+/// Temporary memory configuration of a `StandardGpuResourcesBuilder`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum TempMemory {
+    Fixed(usize),
+    Disabled,
+}
+
+/// Builder for [`StandardGpuResources`], for setting up memory options
+/// before the resources are created.
+///
+/// # Examples
+///
+/// ```
+/// # fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// use faiss::StandardGpuResources;
+///
+/// let gpu = StandardGpuResources::builder()
+///     .temp_memory(64 << 20)
+///     .pinned_memory(16 << 20)
+///     .build()?;
+/// # Ok(())
+/// # }
+/// # run().unwrap();
+/// ```
+///
+/// [`StandardGpuResources`]: struct.StandardGpuResources.html
+#[derive(Debug, Clone, Default)]
+pub struct StandardGpuResourcesBuilder {
+    temp_memory: Option<TempMemory>,
+    pinned_memory: Option<usize>,
+}
+
+impl StandardGpuResourcesBuilder {
+    /// Use a fixed size of memory on all devices as temporary memory.
+    pub fn temp_memory(mut self, size: usize) -> Self {
+        self.temp_memory = Some(TempMemory::Fixed(size));
+        self
+    }
+
+    /// Disable allocation of temporary memory; all temporary memory
+    /// requests will call `cudaMalloc` / `cudaFree` at the point of use.
+    pub fn no_temp_memory(mut self) -> Self {
+        self.temp_memory = Some(TempMemory::Disabled);
+        self
+    }
+
+    /// Set the amount of pinned (page-locked) host memory to allocate,
+    /// for async GPU <-> CPU transfers.
+    pub fn pinned_memory(mut self, size: usize) -> Self {
+        self.pinned_memory = Some(size);
+        self
+    }
+
+    /// Create the GPU resources with the configured options.
+    pub fn build(self) -> Result<StandardGpuResources> {
+        let mut res = StandardGpuResources::new()?;
+        match self.temp_memory {
+            Some(TempMemory::Fixed(size)) => res.set_temp_memory(size)?,
+            Some(TempMemory::Disabled) => res.no_temp_memory()?,
+            None => {}
+        }
+        if let Some(size) = self.pinned_memory {
+            res.set_pinned_memory(size)?;
+        }
+        Ok(res)
+    }
 }
 
 impl Drop for StandardGpuResources {
@@ -169,15 +244,227 @@ impl<'g> GpuResources for &'g mut StandardGpuResources {
     }
 }
 
+/// Options for cloning a CPU index onto a single GPU.
+///
+/// These options are passed to [`to_gpu_with_options`] or
+/// [`into_gpu_with_options`].
+///
+/// [`to_gpu_with_options`]: ../index/struct.IndexImpl.html#method.to_gpu_with_options
+/// [`into_gpu_with_options`]: ../index/struct.IndexImpl.html#method.into_gpu_with_options
+#[derive(Debug)]
+pub struct GpuClonerOptions {
+    inner: *mut FaissGpuClonerOptions,
+}
+
+unsafe impl Send for GpuClonerOptions {}
+unsafe impl Sync for GpuClonerOptions {}
+
+impl GpuClonerOptions {
+    /// Create the default cloner options, which keep all data in 32-bit
+    /// floating point.
+    pub fn new() -> Result<Self> {
+        unsafe {
+            let mut inner = ptr::null_mut();
+            faiss_try(faiss_GpuClonerOptions_new(&mut inner))?;
+            Ok(GpuClonerOptions { inner })
+        }
+    }
+
+    /// Obtain a raw pointer to the native cloner options object.
+    pub fn inner_ptr(&self) -> *mut FaissGpuClonerOptions {
+        self.inner
+    }
+
+    /// Whether to use 16-bit floating point on the GPU. For IVFPQ indexes,
+    /// this enables float16 lookup tables, which halves the shared memory
+    /// needed per query and allows for larger code sizes. Flat and IVF
+    /// flat indexes store their vectors in float16 instead.
+    ///
+    /// The reduced precision of the distances slightly lowers recall,
+    /// usually by much less than the quantization error of the codes.
+    pub fn use_float16(self, value: bool) -> Self {
+        unsafe {
+            faiss_GpuClonerOptions_set_useFloat16(self.inner, c_int::from(value));
+        }
+        self
+    }
+
+    /// Whether the coarse quantizer of an IVF index should store its
+    /// centroids in 16-bit floating point.
+    pub fn use_float16_coarse_quantizer(self, value: bool) -> Self {
+        unsafe {
+            faiss_GpuClonerOptions_set_useFloat16CoarseQuantizer(self.inner, c_int::from(value));
+        }
+        self
+    }
+
+    /// Whether to use precomputed tables for IVFPQ indexes.
+    pub fn use_precomputed(self, value: bool) -> Self {
+        unsafe {
+            faiss_GpuClonerOptions_set_usePrecomputed(self.inner, c_int::from(value));
+        }
+        self
+    }
+
+    /// Getter for the float16 option
+    pub fn get_use_float16(&self) -> bool {
+        unsafe { faiss_GpuClonerOptions_useFloat16(self.inner) != 0 }
+    }
+
+    /// Getter for the float16 coarse quantizer option
+    pub fn get_use_float16_coarse_quantizer(&self) -> bool {
+        unsafe { faiss_GpuClonerOptions_useFloat16CoarseQuantizer(self.inner) != 0 }
+    }
+
+    /// Getter for the precomputed tables option
+    pub fn get_use_precomputed(&self) -> bool {
+        unsafe { faiss_GpuClonerOptions_usePrecomputed(self.inner) != 0 }
+    }
+}
+
+impl Drop for GpuClonerOptions {
+    fn drop(&mut self) {
+        unsafe {
+            faiss_GpuClonerOptions_free(self.inner);
+        }
+    }
+}
+
+/// Options for cloning a CPU index onto multiple GPUs.
+///
+/// By default, the index is replicated on each device, so that throughput
+/// scales with the number of GPUs. With sharding enabled, the indexed
+/// vectors are split across the devices instead, so that the available
+/// memory scales with the number of GPUs.
+///
+/// These options are passed to [`to_gpu_multiple_with_options`].
+///
+/// [`to_gpu_multiple_with_options`]: ../index/struct.IndexImpl.html#method.to_gpu_multiple_with_options
+#[derive(Debug)]
+pub struct GpuMultipleClonerOptions {
+    inner: *mut FaissGpuMultipleClonerOptions,
+}
+
+unsafe impl Send for GpuMultipleClonerOptions {}
+unsafe impl Sync for GpuMultipleClonerOptions {}
+
+impl GpuMultipleClonerOptions {
+    /// Create the default cloner options, which replicate the index.
+    pub fn new() -> Result<Self> {
+        unsafe {
+            let mut inner = ptr::null_mut();
+            faiss_try(faiss_GpuMultipleClonerOptions_new(&mut inner))?;
+            Ok(GpuMultipleClonerOptions { inner })
+        }
+    }
+
+    /// Obtain a raw pointer to the native cloner options object.
+    pub fn inner_ptr(&self) -> *mut FaissGpuMultipleClonerOptions {
+        self.inner
+    }
+
+    /// Whether to shard the index across the devices (`true`), or to
+    /// replicate it on each of them (`false`).
+    pub fn shard(self, value: bool) -> Self {
+        unsafe {
+            faiss_GpuMultipleClonerOptions_set_shard(self.inner, c_int::from(value));
+        }
+        self
+    }
+
+    /// Set how the vectors of an IVF index are distributed among shards:
+    /// 0 for contiguous ranges of IDs, 1 (the default) for IDs taken modulo
+    /// the number of shards, and 2 for keeping whole inverted lists on the
+    /// same shard.
+    pub fn shard_type(self, value: i32) -> Self {
+        unsafe {
+            faiss_GpuMultipleClonerOptions_set_shard_type(self.inner, value);
+        }
+        self
+    }
+
+    /// Getter for the sharding option
+    pub fn get_shard(&self) -> bool {
+        unsafe { faiss_GpuMultipleClonerOptions_shard(self.inner) != 0 }
+    }
+
+    /// Getter for the shard type
+    pub fn get_shard_type(&self) -> i32 {
+        unsafe { faiss_GpuMultipleClonerOptions_shard_type(self.inner) }
+    }
+}
+
+impl Drop for GpuMultipleClonerOptions {
+    fn drop(&mut self) {
+        unsafe {
+            faiss_GpuMultipleClonerOptions_free(self.inner);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::StandardGpuResources;
+    use super::{GpuClonerOptions, GpuMultipleClonerOptions, StandardGpuResources};
 
     #[test]
     fn smoke_detector() {
         StandardGpuResources::new().unwrap();
     }
 
+    #[test]
+    fn cloner_options() {
+        let options = GpuClonerOptions::new().unwrap();
+        assert!(!options.get_use_float16());
+        assert!(!options.get_use_float16_coarse_quantizer());
+        let options = options.use_float16(true).use_precomputed(false);
+        assert!(options.get_use_float16());
+        assert!(!options.get_use_precomputed());
+    }
+
+    #[test]
+    fn multiple_cloner_options() {
+        let options = GpuMultipleClonerOptions::new().unwrap();
+        assert!(!options.get_shard());
+        assert_eq!(options.get_shard_type(), 1);
+        let options = options.shard(true).shard_type(2);
+        assert!(options.get_shard());
+        assert_eq!(options.get_shard_type(), 2);
+    }
+
+    #[test]
+    fn builder_pinned_memory() {
+        use crate::{index_factory, Idx, Index, MetricType};
+
+        let res = StandardGpuResources::builder()
+            .temp_memory(1 << 20)
+            .pinned_memory(1 << 16)
+            .build()
+            .unwrap();
+        let mut index = index_factory(8, "Flat", MetricType::L2)
+            .unwrap()
+            .into_gpu(&res, 0)
+            .unwrap();
+        let some_data = &[
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 1., 1., 1., -1., 0., 0.,
+            0., 1., 1., 0., 0., -1., 100., 100., 100., 100., -100., 100., 100., 100., 120., 100.,
+            100., 105., -100., 100., 100., 105.,
+        ];
+        index.add(some_data).unwrap();
+        let result = index.search(&[0.; 8], 5).unwrap();
+        assert_eq!(
+            result.labels,
+            vec![2, 1, 0, 3, 4]
+                .into_iter()
+                .map(Idx::new)
+                .collect::<Vec<_>>()
+        );
+
+        StandardGpuResources::builder()
+            .no_temp_memory()
+            .build()
+            .unwrap();
+    }
+
     // The test marked as ignored because it takes a significant amount of time.
     #[ignore]
     #[test]
@@ -0,0 +1,147 @@
+//! A type-safe view over the index types known to this crate.
+
+use super::flat::FlatIndexImpl;
+use super::id_map::IdMap;
+use super::ivf_flat::IVFFlatIndexImpl;
+use super::lsh::LshIndex;
+use super::pretransform::PreTransformIndexImpl;
+use super::refine_flat::RefineFlatIndexImpl;
+use super::scalar_quantizer::{IVFScalarQuantizerIndexImpl, ScalarQuantizerIndexImpl};
+use super::*;
+
+/// An index of any native type, resolved to the most specific index type
+/// known to this crate. This allows matching on the kind of index obtained
+/// from `index_factory` or from a file, so as to access the methods
+/// specific to that index type. Index types without a dedicated wrapper in
+/// this crate, such as HNSW, are kept as `AnyIndex::Other`.
+#[derive(Debug)]
+pub enum AnyIndex {
+    /// A flat index
+    Flat(FlatIndexImpl),
+    /// An IVF flat index
+    IvfFlat(IVFFlatIndexImpl),
+    /// A scalar quantizer index
+    ScalarQuantizer(ScalarQuantizerIndexImpl),
+    /// An IVF scalar quantizer index
+    IvfScalarQuantizer(IVFScalarQuantizerIndexImpl<IndexImpl>),
+    /// An LSH index
+    Lsh(LshIndex),
+    /// An index with a vector transformation applied beforehand
+    PreTransform(PreTransformIndexImpl<IndexImpl>),
+    /// A refine flat index
+    RefineFlat(RefineFlatIndexImpl<IndexImpl>),
+    /// An ID-mapped index
+    IdMap(IdMap<IndexImpl>),
+    /// An index of some other type
+    Other(IndexImpl),
+}
+
+impl AnyIndex {
+    /// Build an index with `index_factory` and resolve it to its type.
+    pub fn from_factory<D>(d: u32, description: D, metric: MetricType) -> Result<Self>
+    where
+        D: AsRef<str>,
+    {
+        Ok(AnyIndex::from_index(index_factory(d, description, metric)?))
+    }
+
+    /// Resolve the native type of the given index.
+    pub fn from_index(index: IndexImpl) -> Self {
+        let ptr = index.inner_ptr();
+        unsafe {
+            // the order matters: more specific types are checked first
+            if !faiss_IndexIVFFlat_cast(ptr).is_null() {
+                AnyIndex::IvfFlat(index.into_ivf_flat().unwrap())
+            } else if !faiss_IndexIVFScalarQuantizer_cast(ptr).is_null() {
+                AnyIndex::IvfScalarQuantizer(index.into_ivf_scalar_quantizer().unwrap())
+            } else if !faiss_IndexRefineFlat_cast(ptr).is_null() {
+                mem::forget(index);
+                AnyIndex::RefineFlat(RefineFlatIndexImpl::from_inner_ptr(ptr))
+            } else if !faiss_IndexFlat_cast(ptr).is_null() {
+                AnyIndex::Flat(index.into_flat().unwrap())
+            } else if !faiss_IndexScalarQuantizer_cast(ptr).is_null() {
+                AnyIndex::ScalarQuantizer(index.into_scalar_quantizer().unwrap())
+            } else if !faiss_IndexLSH_cast(ptr).is_null() {
+                AnyIndex::Lsh(index.into_lsh().unwrap())
+            } else if !faiss_IndexPreTransform_cast(ptr).is_null() {
+                AnyIndex::PreTransform(index.into_pre_transform().unwrap())
+            } else if !faiss_IndexIDMap_cast(ptr).is_null() {
+                AnyIndex::IdMap(index.into_id_map().unwrap())
+            } else {
+                AnyIndex::Other(index)
+            }
+        }
+    }
+
+    /// Obtain a reference to the index as a trait object.
+    pub fn as_index(&self) -> &dyn Index {
+        match self {
+            AnyIndex::Flat(index) => index,
+            AnyIndex::IvfFlat(index) => index,
+            AnyIndex::ScalarQuantizer(index) => index,
+            AnyIndex::IvfScalarQuantizer(index) => index,
+            AnyIndex::Lsh(index) => index,
+            AnyIndex::PreTransform(index) => index,
+            AnyIndex::RefineFlat(index) => index,
+            AnyIndex::IdMap(index) => index,
+            AnyIndex::Other(index) => index,
+        }
+    }
+
+    /// Obtain a mutable reference to the index as a trait object.
+    pub fn as_index_mut(&mut self) -> &mut dyn Index {
+        match self {
+            AnyIndex::Flat(index) => index,
+            AnyIndex::IvfFlat(index) => index,
+            AnyIndex::ScalarQuantizer(index) => index,
+            AnyIndex::IvfScalarQuantizer(index) => index,
+            AnyIndex::Lsh(index) => index,
+            AnyIndex::PreTransform(index) => index,
+            AnyIndex::RefineFlat(index) => index,
+            AnyIndex::IdMap(index) => index,
+            AnyIndex::Other(index) => index,
+        }
+    }
+}
+
+impl From<IndexImpl> for AnyIndex {
+    fn from(index: IndexImpl) -> Self {
+        AnyIndex::from_index(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AnyIndex;
+    use crate::MetricType;
+
+    const D: u32 = 8;
+
+    #[test]
+    fn any_index_ivf_flat() {
+        let index = AnyIndex::from_factory(D, "IVF100,Flat", MetricType::L2).unwrap();
+        match index {
+            AnyIndex::IvfFlat(mut index) => {
+                index.set_nprobe(5);
+                assert_eq!(index.nprobe(), 5);
+            }
+            other => panic!("unexpected index type: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn any_index_variants() {
+        let index = AnyIndex::from_factory(D, "Flat", MetricType::L2).unwrap();
+        assert!(matches!(index, AnyIndex::Flat(_)));
+        let index = AnyIndex::from_factory(D, "SQ8", MetricType::L2).unwrap();
+        assert!(matches!(index, AnyIndex::ScalarQuantizer(_)));
+        let index = AnyIndex::from_factory(D, "IDMap,Flat", MetricType::L2).unwrap();
+        assert!(matches!(index, AnyIndex::IdMap(_)));
+        let index = AnyIndex::from_factory(D, "PCA4,Flat", MetricType::L2).unwrap();
+        assert!(matches!(index, AnyIndex::PreTransform(_)));
+        let mut index = AnyIndex::from_factory(D, "IVF4,SQ8", MetricType::L2).unwrap();
+        assert!(matches!(index, AnyIndex::IvfScalarQuantizer(_)));
+        assert_eq!(index.as_index().d(), D);
+        assert_eq!(index.as_index_mut().ntotal(), 0);
+    }
+}
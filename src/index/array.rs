@@ -0,0 +1,203 @@
+//! Integration with the [`ndarray`] crate.
+//!
+//! This module is only available with the `ndarray` Cargo feature. It
+//! provides the [`IndexArrayExt`] extension trait, so that vectors can be
+//! added and searched as the rows of a two-dimensional array, rather than as
+//! a flat slice.
+//!
+//! [`ndarray`]: https://docs.rs/ndarray
+//! [`IndexArrayExt`]: trait.IndexArrayExt.html
+//!
+//! # Examples
+//!
+//! ```
+//! use faiss::{FlatIndex, Index};
+//! use faiss::index::array::IndexArrayExt;
+//! use ndarray::array;
+//! # fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let mut index = FlatIndex::new_l2(2)?;
+//! index.add_array(array![[0., 0.], [1., 1.], [4., 4.]].view())?;
+//! let (distances, labels) = index.search_array(array![[0.9, 0.9]].view(), 2)?;
+//! assert_eq!(labels.dim(), (1, 2));
+//! assert_eq!(distances.dim(), (1, 2));
+//! # Ok(())
+//! # }
+//! # run().unwrap();
+//! ```
+
+use super::{Idx, Index};
+use crate::error::{Error, Result};
+use ndarray::{Array2, ArrayView2};
+
+/// Extension trait for adding and searching vectors in the form of
+/// two-dimensional arrays, where each row is a vector.
+///
+/// Arrays must be in standard (row-major) layout and have exactly `d`
+/// columns, where `d` is the dimensionality of the index.
+pub trait IndexArrayExt: Index {
+    /// Add the rows of `x` as new data vectors to the index.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::BadDimension`] if the number of columns does not
+    /// match the index dimensionality, or [`Error::BadArrayLayout`] if the
+    /// array is not in row-major layout.
+    ///
+    /// [`Error::BadDimension`]: ../../error/enum.Error.html#variant.BadDimension
+    /// [`Error::BadArrayLayout`]: ../../error/enum.Error.html#variant.BadArrayLayout
+    fn add_array(&mut self, x: ArrayView2<f32>) -> Result<()>;
+
+    /// Perform a search for the `k` closest vectors to each row of `q`.
+    ///
+    /// Returns the distances and labels as `nq × k` arrays, where `nq` is
+    /// the number of query rows.
+    ///
+    /// # Errors
+    ///
+    /// Fails under the same conditions as [`add_array`], or if the
+    /// search operation fails.
+    ///
+    /// [`add_array`]: #tymethod.add_array
+    fn search_array(&mut self, q: ArrayView2<f32>, k: usize) -> Result<(Array2<f32>, Array2<Idx>)>;
+
+    /// Reconstruct `n` stored vectors with sequential keys starting at
+    /// `start`, as the rows of an `n × d` array.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the index does not support reconstruction, or if the keys
+    /// are out of range.
+    fn reconstruct_n_array(&self, start: Idx, n: usize) -> Result<Array2<f32>>;
+}
+
+impl<I> IndexArrayExt for I
+where
+    I: ?Sized + Index,
+{
+    fn add_array(&mut self, x: ArrayView2<f32>) -> Result<()> {
+        let x = rows_as_slice(&x, self.d())?;
+        self.add(x)
+    }
+
+    fn search_array(&mut self, q: ArrayView2<f32>, k: usize) -> Result<(Array2<f32>, Array2<Idx>)> {
+        let nq = q.nrows();
+        let result = self.search(rows_as_slice(&q, self.d())?, k)?;
+        let distances = Array2::from_shape_vec((nq, k), result.distances)
+            .expect("search should have produced `nq * k` distances");
+        let labels = Array2::from_shape_vec((nq, k), result.labels)
+            .expect("search should have produced `nq * k` labels");
+        Ok((distances, labels))
+    }
+
+    fn reconstruct_n_array(&self, start: Idx, n: usize) -> Result<Array2<f32>> {
+        let d = self.d() as usize;
+        let x = if n > 0 {
+            self.reconstruct_n(start, n)?
+        } else {
+            Vec::new()
+        };
+        Ok(Array2::from_shape_vec((n, d), x)
+            .expect("reconstruction should have produced `n * d` values"))
+    }
+}
+
+/// Obtain the contiguous memory of a row-major array of `d`-dimensional vectors.
+fn rows_as_slice<'a>(x: &'a ArrayView2<f32>, d: u32) -> Result<&'a [f32]> {
+    if x.ncols() != d as usize {
+        return Err(Error::BadDimension);
+    }
+    x.as_slice().ok_or(Error::BadArrayLayout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IndexArrayExt;
+    use crate::error::Error;
+    use crate::index::flat::FlatIndex;
+    use crate::index::{Idx, Index};
+    use ndarray::{Array2, ShapeBuilder};
+
+    const D: u32 = 8;
+
+    #[test]
+    fn flat_index_add_search_array() {
+        let mut index = FlatIndex::new_l2(D).unwrap();
+        let some_data = vec![
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 1., 1., 1., -1., 0., 0.,
+            0., 1., 1., 0., 0., -1., 100., 100., 100., 100., -100., 100., 100., 100., 120., 100.,
+            100., 105., -100., 100., 100., 105.,
+        ];
+        let data = Array2::from_shape_vec((5, D as usize), some_data.clone()).unwrap();
+        index.add_array(data.view()).unwrap();
+        assert_eq!(index.ntotal(), 5);
+        assert_eq!(index.xb(), &some_data[..]);
+
+        let mut queries = Array2::zeros((2, D as usize));
+        queries.row_mut(1).fill(100.);
+        let (distances, labels) = index.search_array(queries.view(), 5).unwrap();
+        assert_eq!(distances.dim(), (2, 5));
+        assert_eq!(labels.dim(), (2, 5));
+        assert_eq!(
+            labels.row(0).to_vec(),
+            vec![2, 1, 0, 3, 4]
+                .into_iter()
+                .map(Idx::new)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            labels.row(1).to_vec(),
+            vec![3, 4, 0, 1, 2]
+                .into_iter()
+                .map(Idx::new)
+                .collect::<Vec<_>>()
+        );
+        assert!(distances.iter().all(|x| *x > 0.));
+
+        // same results as with flat slices
+        let result = index.search(queries.as_slice().unwrap(), 5).unwrap();
+        assert_eq!(distances.as_slice().unwrap(), &result.distances[..]);
+        assert_eq!(labels.as_slice().unwrap(), &result.labels[..]);
+    }
+
+    #[test]
+    fn flat_index_reconstruct_n_array() {
+        let mut index = FlatIndex::new_l2(D).unwrap();
+        let some_data: Vec<f32> = (0..4 * D).map(|i| i as f32).collect();
+        index.add(&some_data).unwrap();
+
+        let rows = index.reconstruct_n_array(Idx::new(1), 2).unwrap();
+        assert_eq!(rows.dim(), (2, D as usize));
+        assert_eq!(
+            rows.as_slice().unwrap(),
+            &some_data[D as usize..3 * D as usize]
+        );
+        assert_eq!(
+            rows.row(1).to_vec(),
+            &some_data[2 * D as usize..3 * D as usize]
+        );
+
+        let empty = index.reconstruct_n_array(Idx::new(0), 0).unwrap();
+        assert_eq!(empty.dim(), (0, D as usize));
+    }
+
+    #[test]
+    fn array_bad_dimension() {
+        let mut index = FlatIndex::new_l2(D).unwrap();
+        let data = Array2::<f32>::zeros((3, D as usize + 1));
+        assert_eq!(index.add_array(data.view()), Err(Error::BadDimension));
+        assert_eq!(index.ntotal(), 0);
+        assert_eq!(
+            index.search_array(data.view(), 1).unwrap_err(),
+            Error::BadDimension
+        );
+    }
+
+    #[test]
+    fn array_bad_layout() {
+        let mut index = FlatIndex::new_l2(D).unwrap();
+        // column-major arrays are not accepted
+        let data = Array2::<f32>::zeros((3, D as usize).f());
+        assert_eq!(index.add_array(data.view()), Err(Error::BadArrayLayout));
+        assert_eq!(index.ntotal(), 0);
+    }
+}
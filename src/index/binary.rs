@@ -0,0 +1,217 @@
+//! Interface and implementation to binary indexes, which store bit vectors
+//! packed into bytes and compare them by Hamming distance.
+//!
+//! The Faiss C API does not provide binary index constructors, so these
+//! indexes are obtained by reading them from a file, with
+//! [`read_index_binary`].
+//!
+//! [`read_index_binary`]: ../io/fn.read_index_binary.html
+
+use super::{checked_idx_t, checked_result_len, Idx, RangeSearchResult};
+use crate::error::{Error, Result};
+use crate::faiss_try;
+use faiss_sys::*;
+use std::ptr;
+
+/// The outcome of a binary index search operation. Distances are Hamming
+/// distances.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BinarySearchResult {
+    pub distances: Vec<i32>,
+    pub labels: Vec<Idx>,
+}
+
+/// The outcome of a binary index range search operation. The Hamming
+/// distances are reported as `f32` values.
+pub type BinaryRangeSearchResult = RangeSearchResult;
+
+/// Native implementation of a Faiss binary index.
+#[derive(Debug)]
+pub struct BinaryIndexImpl {
+    inner: *mut FaissIndexBinary,
+}
+
+unsafe impl Send for BinaryIndexImpl {}
+unsafe impl Sync for BinaryIndexImpl {}
+
+impl Drop for BinaryIndexImpl {
+    fn drop(&mut self) {
+        unsafe {
+            faiss_IndexBinary_free(self.inner);
+        }
+    }
+}
+
+impl BinaryIndexImpl {
+    /// Create a binary index from a raw pointer.
+    ///
+    /// # Safety
+    ///
+    /// `inner_ptr` must point to a valid, non-freed binary index, and cannot
+    /// be shared across multiple instances. The index is freed when the
+    /// value is dropped.
+    pub unsafe fn from_inner_ptr(inner_ptr: *mut FaissIndexBinary) -> Self {
+        BinaryIndexImpl { inner: inner_ptr }
+    }
+
+    /// Retrieve a pointer to the native binary index.
+    pub fn inner_ptr(&self) -> *mut FaissIndexBinary {
+        self.inner
+    }
+
+    /// Whether the index does not require training, or has been trained.
+    pub fn is_trained(&self) -> bool {
+        unsafe { faiss_IndexBinary_is_trained(self.inner) != 0 }
+    }
+
+    /// The total number of vectors indexed.
+    pub fn ntotal(&self) -> u64 {
+        unsafe { faiss_IndexBinary_ntotal(self.inner) as u64 }
+    }
+
+    /// The dimensionality of the indexed vectors, in bits.
+    pub fn d(&self) -> u32 {
+        unsafe { faiss_IndexBinary_d(self.inner) as u32 }
+    }
+
+    /// The number of bytes of each indexed vector.
+    pub fn code_size(&self) -> usize {
+        (self.d() / 8) as usize
+    }
+
+    /// Add new data vectors to the index, each packed into `code_size()`
+    /// bytes.
+    pub fn add(&mut self, x: &[u8]) -> Result<()> {
+        let n = self.checked_len(x)?;
+        unsafe {
+            faiss_try(faiss_IndexBinary_add(self.inner, n, x.as_ptr()))?;
+            Ok(())
+        }
+    }
+
+    /// Perform a search for the `k` closest vectors to the given queries.
+    /// Searching with `k == 0` returns an empty result without calling into
+    /// the native index.
+    pub fn search(&self, query: &[u8], k: usize) -> Result<BinarySearchResult> {
+        if k == 0 {
+            return Ok(Default::default());
+        }
+        let nq = self.checked_len(query)?;
+        let len = checked_result_len(nq as usize, k)?;
+        let mut distances = vec![0_i32; len];
+        let mut labels = vec![Idx::none(); len];
+        unsafe {
+            faiss_try(faiss_IndexBinary_search(
+                self.inner,
+                nq,
+                query.as_ptr(),
+                checked_idx_t(k)?,
+                distances.as_mut_ptr(),
+                labels.as_mut_ptr() as *mut _,
+            ))?;
+        }
+        Ok(BinarySearchResult { distances, labels })
+    }
+
+    /// Search for all vectors within a Hamming distance of at most `radius`
+    /// bits from each of the given queries.
+    pub fn range_search(&self, query: &[u8], radius: i32) -> Result<BinaryRangeSearchResult> {
+        let nq = self.checked_len(query)?;
+        unsafe {
+            let mut p_res: *mut FaissRangeSearchResult = ptr::null_mut();
+            faiss_try(faiss_RangeSearchResult_new(&mut p_res, nq))?;
+            let result = RangeSearchResult { inner: p_res };
+            // the native search is exclusive of the radius
+            faiss_try(faiss_IndexBinary_range_search(
+                self.inner,
+                nq,
+                query.as_ptr(),
+                radius.saturating_add(1),
+                p_res,
+            ))?;
+            Ok(result)
+        }
+    }
+
+    /// Clear the entire index.
+    pub fn reset(&mut self) -> Result<()> {
+        unsafe {
+            faiss_try(faiss_IndexBinary_reset(self.inner))?;
+            Ok(())
+        }
+    }
+
+    fn checked_len(&self, x: &[u8]) -> Result<idx_t> {
+        let code_size = self.code_size();
+        if code_size == 0 || !x.chunks_exact(code_size).remainder().is_empty() {
+            return Err(Error::BadDimension);
+        }
+        checked_idx_t(x.len() / code_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::index::io::read_index_binary;
+    use std::io::Write;
+
+    const D: u32 = 16;
+
+    /// Write an empty binary flat index in the Faiss serialization format,
+    /// since binary indexes cannot be built through the C API.
+    fn empty_binary_flat_file(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(name);
+        let mut f = std::fs::File::create(&path).unwrap();
+        f.write_all(b"IBxF").unwrap();
+        f.write_all(&(D as i32).to_le_bytes()).unwrap(); // d
+        f.write_all(&(D as i32 / 8).to_le_bytes()).unwrap(); // code_size
+        f.write_all(&0_i64.to_le_bytes()).unwrap(); // ntotal
+        f.write_all(&[1]).unwrap(); // is_trained
+        f.write_all(&0_i32.to_le_bytes()).unwrap(); // metric_type
+        f.write_all(&0_u64.to_le_bytes()).unwrap(); // codes
+        path
+    }
+
+    #[test]
+    fn binary_flat_range_search() {
+        let path = empty_binary_flat_file("test_binary_flat_range_search.index");
+        let mut index = read_index_binary(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(index.d(), D);
+        assert_eq!(index.ntotal(), 0);
+
+        // at Hamming distances 0, 2, 3, 16 and 1 from the query
+        let codes = [
+            [0b0000_0000, 0b0000_0000],
+            [0b0000_0011, 0b0000_0000],
+            [0b0000_0111, 0b0000_0000],
+            [0b1111_1111, 0b1111_1111],
+            [0b0000_0000, 0b1000_0000],
+        ];
+        index.add(&codes.concat()).unwrap();
+        assert_eq!(index.ntotal(), 5);
+
+        let query = &[0_u8, 0];
+        let res = index.range_search(query, 2).unwrap();
+        assert_eq!(res.lims(), &[0, 3]);
+        let mut labels: Vec<_> = res.labels().iter().map(|l| l.get().unwrap()).collect();
+        labels.sort_unstable();
+        assert_eq!(labels, vec![0, 1, 4]);
+        for &label in &labels {
+            let bits: u32 = codes[label as usize]
+                .iter()
+                .zip(query)
+                .map(|(a, b)| (a ^ b).count_ones())
+                .sum();
+            assert!(bits <= 2);
+        }
+
+        let res = index.search(query, 2).unwrap();
+        assert_eq!(res.distances, vec![0, 1]);
+        assert_eq!(res.labels[0].get(), Some(0));
+
+        let res = index.search(query, 0).unwrap();
+        assert!(res.distances.is_empty());
+        assert!(res.labels.is_empty());
+    }
+}
@@ -0,0 +1,192 @@
+//! Cosine similarity search over inner product indexes.
+//!
+//! The inner product of two vectors is their cosine similarity as long as
+//! both are L2-normalized. The [`CosineIndex`] wrapper normalizes all
+//! vectors given to the index, so that searches are scored by cosine
+//! similarity, with results sorted by descending similarity.
+//!
+//! [`CosineIndex`]: struct.CosineIndex.html
+
+use super::flat::FlatIndexImpl;
+use super::*;
+
+use crate::utils::renorm_l2;
+
+/// An index wrapper scoring vectors by cosine similarity. It holds an inner
+/// product index, and L2-normalizes copies of all vectors which are added,
+/// trained on or searched for, so that the reported distances are cosine
+/// similarities.
+///
+/// Vectors reconstructed or decoded from the index are the normalized ones.
+#[derive(Debug)]
+pub struct CosineIndex<I> {
+    index: I,
+}
+
+impl CosineIndex<FlatIndexImpl> {
+    /// Create an exact cosine similarity index over a new flat index.
+    pub fn new_flat(d: u32) -> Result<Self> {
+        CosineIndex::new(FlatIndexImpl::new_ip(d)?)
+    }
+}
+
+impl<I> CosineIndex<I>
+where
+    I: Index,
+{
+    /// Wrap the given inner product index. Any vectors already in it should
+    /// be L2-normalized.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Unsupported` if the index does not use the inner
+    /// product metric.
+    pub fn new(index: I) -> Result<Self> {
+        if index.metric_type() != MetricType::InnerProduct {
+            return Err(Error::Unsupported(
+                "cosine similarity over a metric other than inner product",
+            ));
+        }
+        Ok(CosineIndex { index })
+    }
+
+    /// Obtain a reference to the wrapped index.
+    pub fn inner(&self) -> &I {
+        &self.index
+    }
+
+    /// Discard the wrapper, recovering the wrapped index.
+    pub fn into_inner(self) -> I {
+        self.index
+    }
+
+    // kept private, so that no vectors are added without being normalized
+    fn inner_mut(&mut self) -> &mut I {
+        &mut self.index
+    }
+
+    fn normalized(&self, x: &[f32]) -> Result<Vec<f32>> {
+        let mut x = x.to_vec();
+        renorm_l2(&mut x, self.index.d() as usize)?;
+        Ok(x)
+    }
+}
+
+impl<I> Index for CosineIndex<I>
+where
+    I: Index,
+{
+    forward_index_methods! {
+        inner, inner_mut;
+        is_trained,
+        ntotal,
+        d,
+        metric_type,
+        reset,
+        remove_ids,
+        verbose,
+        set_verbose,
+        sa_code_size,
+        sa_decode_into,
+        reconstruct,
+        reconstruct_n,
+    }
+
+    fn add(&mut self, x: &[f32]) -> Result<()> {
+        let x = self.normalized(x)?;
+        self.index.add(&x)
+    }
+
+    fn add_with_ids(&mut self, x: &[f32], xids: &[Idx]) -> Result<()> {
+        let x = self.normalized(x)?;
+        self.index.add_with_ids(&x, xids)
+    }
+
+    fn train(&mut self, x: &[f32]) -> Result<()> {
+        let x = self.normalized(x)?;
+        self.index.train(&x)
+    }
+
+    fn assign(&mut self, q: &[f32], k: usize) -> Result<AssignSearchResult> {
+        let q = self.normalized(q)?;
+        self.index.assign(&q, k)
+    }
+
+    fn search(&mut self, q: &[f32], k: usize) -> Result<SearchResult> {
+        let q = self.normalized(q)?;
+        self.index.search(&q, k)
+    }
+
+    fn range_search(&mut self, q: &[f32], radius: f32) -> Result<RangeSearchResult> {
+        let q = self.normalized(q)?;
+        self.index.range_search(&q, radius)
+    }
+
+    fn sa_encode(&self, x: &[f32]) -> Result<Vec<u8>> {
+        let x = self.normalized(x)?;
+        self.index.sa_encode(&x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CosineIndex;
+    use crate::error::Error;
+    use crate::index::flat::FlatIndexImpl;
+    use crate::index::{Idx, Index};
+
+    fn cosine(a: &[f32], b: &[f32]) -> f32 {
+        let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+        let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+        dot / (norm(a) * norm(b))
+    }
+
+    #[test]
+    fn cosine_index_search() {
+        let mut index = CosineIndex::new_flat(3).unwrap();
+        let some_data = [[3., 0., 0.], [1., 1., 0.], [0., 0., 5.], [-2., 1., 1.]];
+        index.add(&some_data.concat()).unwrap();
+        assert_eq!(index.ntotal(), 4);
+
+        let my_query = [2., 1., 0.];
+        let result = index.search(&my_query, 4).unwrap();
+        assert_eq!(
+            result.labels,
+            vec![Idx::new(1), Idx::new(0), Idx::new(2), Idx::new(3)]
+        );
+        for (label, distance) in result.labels.iter().zip(&result.distances) {
+            let expected = cosine(&my_query, &some_data[label.get().unwrap() as usize]);
+            assert!((distance - expected).abs() < 1e-5);
+        }
+        assert!(result.distances.windows(2).all(|w| w[0] >= w[1]));
+    }
+
+    #[test]
+    fn cosine_index_requires_ip() {
+        let index = FlatIndexImpl::new_l2(3).unwrap();
+        assert!(matches!(
+            CosineIndex::new(index),
+            Err(Error::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn cosine_index_stores_normalized() {
+        let mut index = CosineIndex::new_flat(2).unwrap();
+        index.add(&[3., 4., 0., -2.]).unwrap();
+        let stored = index.reconstruct_n(Idx::new(0), 2).unwrap();
+        for (x, expected) in stored.iter().zip(&[0.6, 0.8, 0., -1.]) {
+            assert!((x - expected).abs() < 1e-6);
+        }
+        assert_eq!(
+            index.inner().reconstruct(Idx::new(1)).unwrap(),
+            vec![0., -1.]
+        );
+
+        // the radius is a cosine similarity, whatever the norm of the query
+        let result = index.range_search(&[10., 0.], 0.5).unwrap();
+        let (distances, labels) = result.distance_and_labels();
+        assert_eq!(labels, &[Idx::new(0)]);
+        assert!((distances[0] - 0.6).abs() < 1e-6);
+    }
+}
@@ -2,6 +2,8 @@
 
 use super::*;
 
+use std::convert::{TryFrom, TryInto};
+
 /// Alias for the native implementation of a flat index.
 pub type FlatIndex = FlatIndexImpl;
 
@@ -72,9 +74,9 @@ impl FlatIndexImpl {
             let mut distances = vec![0.; n * k];
             faiss_try(faiss_IndexFlat_compute_distance_subset(
                 self.inner,
-                n as idx_t,
+                checked_idx_t(n)?,
                 x.as_ptr(),
-                k as idx_t,
+                checked_idx_t(k)?,
                 distances.as_mut_ptr(),
                 labels.as_ptr() as *const _,
             ))?;
@@ -153,9 +155,255 @@ impl TryClone for FlatIndexImpl {
 
 impl_concurrent_index!(FlatIndexImpl);
 
+impl FlatIndexImpl {
+    /// Create a read-only flat index over the given C-contiguous sequence
+    /// of `d`-dimensional vectors, without copying them. This suits vectors
+    /// which already live in memory owned elsewhere, such as a memory-mapped
+    /// file. Only the L2 and inner product metrics are supported.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::BadDimension` if `d` is zero or the length of `data`
+    /// is not a multiple of `d`, and `Error::Unsupported` for other metrics.
+    pub fn from_borrowed(
+        d: u32,
+        data: &[f32],
+        metric: MetricType,
+    ) -> Result<BorrowedFlatIndex<'_>> {
+        BorrowedFlatIndex::new(d, data, metric)
+    }
+}
+
+/// A read-only flat index over vectors borrowed from the caller, created
+/// with [`FlatIndexImpl::from_borrowed`].
+///
+/// Searches are exhaustive as in a flat index, with distances computed by
+/// the native optimized routines, directly over the borrowed vectors.
+/// Operations which would modify the vectors return `Error::Unsupported`,
+/// as does `range_search`.
+///
+/// [`FlatIndexImpl::from_borrowed`]: struct.FlatIndexImpl.html#method.from_borrowed
+#[derive(Debug, Clone, Copy)]
+pub struct BorrowedFlatIndex<'a> {
+    d: u32,
+    metric: MetricType,
+    data: &'a [f32],
+}
+
+impl<'a> BorrowedFlatIndex<'a> {
+    fn new(d: u32, data: &'a [f32], metric: MetricType) -> Result<Self> {
+        let du = d as usize;
+        if du == 0 || !data.chunks_exact(du).remainder().is_empty() {
+            return Err(Error::BadDimension);
+        }
+        if metric != MetricType::L2 && metric != MetricType::InnerProduct {
+            return Err(Error::Unsupported(
+                "borrowed flat index with a metric other than L2 or inner product",
+            ));
+        }
+        Ok(BorrowedFlatIndex { d, metric, data })
+    }
+
+    /// Obtain a reference to the borrowed vectors.
+    pub fn xb(&self) -> &'a [f32] {
+        self.data
+    }
+
+    fn search_borrowed(&self, q: &[f32], k: usize) -> Result<SearchResult> {
+        let d = self.d as usize;
+        if !q.chunks_exact(d).remainder().is_empty() {
+            return Err(Error::BadDimension);
+        }
+        let n = self.data.len() / d;
+        let len = checked_result_len(q.len() / d, k)?;
+        let (worst, better): (f32, fn(f32, f32) -> bool) = match self.metric {
+            MetricType::InnerProduct => (f32::NEG_INFINITY, |a, b| a > b),
+            _ => (f32::INFINITY, |a, b| a < b),
+        };
+        let mut distances = Vec::with_capacity(len);
+        let mut labels = Vec::with_capacity(len);
+        let mut dis = vec![0_f32; n];
+        let mut order: Vec<usize> = Vec::with_capacity(n);
+        for query in q.chunks_exact(d) {
+            unsafe {
+                match self.metric {
+                    MetricType::InnerProduct => faiss_fvec_inner_products_ny(
+                        dis.as_mut_ptr(),
+                        query.as_ptr(),
+                        self.data.as_ptr(),
+                        d,
+                        n,
+                    ),
+                    _ => faiss_fvec_L2sqr_ny(
+                        dis.as_mut_ptr(),
+                        query.as_ptr(),
+                        self.data.as_ptr(),
+                        d,
+                        n,
+                    ),
+                }
+            }
+            order.clear();
+            order.extend(0..n);
+            // order by distance, then by position to break ties
+            let cmp = |&a: &usize, &b: &usize| {
+                if better(dis[a], dis[b]) {
+                    std::cmp::Ordering::Less
+                } else if better(dis[b], dis[a]) {
+                    std::cmp::Ordering::Greater
+                } else {
+                    a.cmp(&b)
+                }
+            };
+            if k < n {
+                order.select_nth_unstable_by(k, cmp);
+                order.truncate(k);
+            }
+            order.sort_unstable_by(cmp);
+            distances.extend(order.iter().map(|&i| dis[i]));
+            labels.extend(order.iter().map(|&i| Idx::new(i as u64)));
+            for _ in order.len()..k {
+                distances.push(worst);
+                labels.push(Idx::none());
+            }
+        }
+        Ok(SearchResult { distances, labels })
+    }
+}
+
+impl Index for BorrowedFlatIndex<'_> {
+    fn is_trained(&self) -> bool {
+        true
+    }
+
+    fn ntotal(&self) -> u64 {
+        (self.data.len() / self.d as usize) as u64
+    }
+
+    fn d(&self) -> u32 {
+        self.d
+    }
+
+    fn metric_type(&self) -> MetricType {
+        self.metric
+    }
+
+    fn add(&mut self, _x: &[f32]) -> Result<()> {
+        Err(Error::Unsupported(
+            "adding vectors to a borrowed flat index",
+        ))
+    }
+
+    fn add_with_ids(&mut self, _x: &[f32], _xids: &[Idx]) -> Result<()> {
+        Err(Error::Unsupported(
+            "adding vectors to a borrowed flat index",
+        ))
+    }
+
+    fn train(&mut self, x: &[f32]) -> Result<()> {
+        // flat indexes do not need training
+        if !x.chunks_exact(self.d as usize).remainder().is_empty() {
+            return Err(Error::BadDimension);
+        }
+        Ok(())
+    }
+
+    fn assign(&mut self, q: &[f32], k: usize) -> Result<AssignSearchResult> {
+        ConcurrentIndex::assign(self, q, k)
+    }
+
+    fn search(&mut self, q: &[f32], k: usize) -> Result<SearchResult> {
+        self.search_borrowed(q, k)
+    }
+
+    fn range_search(&mut self, _q: &[f32], _radius: f32) -> Result<RangeSearchResult> {
+        Err(Error::Unsupported("range search on a borrowed flat index"))
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        Err(Error::Unsupported(
+            "removing vectors from a borrowed flat index",
+        ))
+    }
+
+    fn remove_ids(&mut self, _sel: &IdSelector) -> Result<usize> {
+        Err(Error::Unsupported(
+            "removing vectors from a borrowed flat index",
+        ))
+    }
+
+    fn verbose(&self) -> bool {
+        false
+    }
+
+    fn set_verbose(&mut self, _value: bool) {}
+
+    fn sa_code_size(&self) -> Result<usize> {
+        Ok(self.d as usize * mem::size_of::<f32>())
+    }
+
+    fn sa_encode(&self, x: &[f32]) -> Result<Vec<u8>> {
+        // flat codes are the vectors in native byte order
+        if !x.chunks_exact(self.d as usize).remainder().is_empty() {
+            return Err(Error::BadDimension);
+        }
+        Ok(x.iter().flat_map(|v| v.to_ne_bytes()).collect())
+    }
+
+    fn sa_decode_into(&self, codes: &[u8], out: &mut [f32]) -> Result<()> {
+        let code_size = self.sa_code_size()?;
+        if !codes.chunks_exact(code_size).remainder().is_empty() {
+            return Err(Error::BadCodeSize);
+        }
+        if codes.len() / code_size * self.d as usize != out.len() {
+            return Err(Error::BadDimension);
+        }
+        for (v, bytes) in out
+            .iter_mut()
+            .zip(codes.chunks_exact(mem::size_of::<f32>()))
+        {
+            *v = f32::from_ne_bytes(bytes.try_into().unwrap());
+        }
+        Ok(())
+    }
+
+    fn reconstruct(&self, key: Idx) -> Result<Vec<f32>> {
+        self.reconstruct_n(key, 1)
+    }
+
+    fn reconstruct_n(&self, first_key: Idx, count: usize) -> Result<Vec<f32>> {
+        let d = self.d as usize;
+        first_key
+            .get()
+            .and_then(|first| usize::try_from(first).ok())
+            .and_then(|first| Some(first..first.checked_add(count)?))
+            .and_then(|keys| self.data.get(keys.start * d..keys.end * d))
+            .map(<[f32]>::to_vec)
+            .ok_or(Error::Unsupported(
+                "reconstruction of keys outside of a borrowed flat index",
+            ))
+    }
+}
+
+impl ConcurrentIndex for BorrowedFlatIndex<'_> {
+    fn assign(&self, q: &[f32], k: usize) -> Result<AssignSearchResult> {
+        let labels = self.search_borrowed(q, k)?.labels;
+        Ok(AssignSearchResult { labels })
+    }
+
+    fn search(&self, q: &[f32], k: usize) -> Result<SearchResult> {
+        self.search_borrowed(q, k)
+    }
+
+    fn range_search(&self, _q: &[f32], _radius: f32) -> Result<RangeSearchResult> {
+        Err(Error::Unsupported("range search on a borrowed flat index"))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::FlatIndexImpl;
+    use crate::error::Error;
     use crate::index::{
         index_factory, ConcurrentIndex, FromInnerPtr, Idx, Index, NativeIndex, TryClone,
         UpcastIndex,
@@ -164,6 +412,72 @@ mod tests {
 
     const D: u32 = 8;
 
+    #[test]
+    fn flat_index_search_k_zero() {
+        let mut index = FlatIndexImpl::new_l2(D).unwrap();
+        index.add(&[1.; 3 * D as usize]).unwrap();
+        let my_query = [0.; 2 * D as usize];
+
+        let result = index.search(&my_query, 0).unwrap();
+        assert!(result.labels.is_empty());
+        assert!(result.distances.is_empty());
+        assert!(index.assign(&my_query, 0).unwrap().labels.is_empty());
+        let result = ConcurrentIndex::search(&index, &my_query, 0).unwrap();
+        assert!(result.labels.is_empty());
+    }
+
+    #[test]
+    fn flat_index_search_k_above_ntotal() {
+        let mut index = FlatIndexImpl::new_l2(D).unwrap();
+        index.add(&[1.; 2 * D as usize]).unwrap();
+        let my_query = [0.; 2 * D as usize];
+
+        let result = index.search(&my_query, 5).unwrap();
+        assert_eq!(result.labels.len(), 2 * 5);
+        assert_eq!(result.distances.len(), 2 * 5);
+        for (labels, distances) in result.labels.chunks(5).zip(result.distances.chunks(5)) {
+            assert!(labels[..2].iter().all(|l| l.is_some()));
+            assert!(labels[2..].iter().all(|l| l.is_none()));
+            assert!(distances[..2].iter().all(|&d| d == D as f32));
+            assert!(distances[2..].iter().all(|&d| d == f32::INFINITY));
+        }
+        let labels = index.assign(&my_query, 5).unwrap().labels;
+        assert_eq!(labels, result.labels);
+    }
+
+    #[test]
+    fn flat_index_from_borrowed() {
+        let some_data: Vec<f32> = (0..64 * D).map(|i| ((i * 29) % 97) as f32).collect();
+        let my_query = &some_data[5 * D as usize..7 * D as usize];
+        for &metric in &[MetricType::L2, MetricType::InnerProduct] {
+            let mut copied = FlatIndexImpl::new(D, metric).unwrap();
+            copied.add(&some_data).unwrap();
+            let mut index = FlatIndexImpl::from_borrowed(D, &some_data, metric).unwrap();
+            assert_eq!(index.ntotal(), 64);
+            assert_eq!(index.xb().as_ptr(), some_data.as_ptr());
+
+            let expected = copied.search(my_query, 5).unwrap();
+            let result = index.search(my_query, 5).unwrap();
+            assert_eq!(result.labels, expected.labels);
+            for (x, y) in result.distances.iter().zip(&expected.distances) {
+                assert!((x - y).abs() <= 1e-3 * y.abs().max(1.));
+            }
+            let result = ConcurrentIndex::search(&index, my_query, 70).unwrap();
+            assert_eq!(result.labels[64..], [Idx::none(); 6]);
+
+            assert_eq!(
+                index.reconstruct(Idx::new(5)).unwrap(),
+                &some_data[5 * D as usize..6 * D as usize]
+            );
+            assert!(matches!(index.add(my_query), Err(Error::Unsupported(_))));
+        }
+
+        assert_eq!(
+            FlatIndexImpl::from_borrowed(D, &some_data[1..], MetricType::L2).unwrap_err(),
+            Error::BadDimension
+        );
+    }
+
     #[test]
     fn flat_index_from_upcast() {
         let index = FlatIndexImpl::new_l2(D).unwrap();
@@ -324,6 +638,42 @@ mod tests {
         assert_eq!(index.ntotal(), 0);
     }
 
+    #[test]
+    fn flat_index_assign_with_distances_shared() {
+        let mut index = FlatIndexImpl::new_l2(D).unwrap();
+        let some_data = &[
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 1., 1., 1., -1., 0., 0.,
+            0., 1., 1., 0., 0., -1., 100., 100., 100., 100., -100., 100., 100., 100., 120., 100.,
+            100., 105., -100., 100., 100., 105.,
+        ];
+        index.add(some_data).unwrap();
+        let index = std::sync::Arc::new(index);
+
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let index = index.clone();
+                std::thread::spawn(move || {
+                    let my_query = [if i % 2 == 0 { 0. } else { 100. }; D as usize];
+                    (i, index.assign_with_distances(&my_query, 5).unwrap())
+                })
+            })
+            .collect();
+        for handle in handles {
+            let (i, result) = handle.join().unwrap();
+            let expected = if i % 2 == 0 {
+                vec![2, 1, 0, 3, 4]
+            } else {
+                vec![3, 4, 0, 1, 2]
+            };
+            assert_eq!(
+                result.labels,
+                expected.into_iter().map(Idx::new).collect::<Vec<_>>()
+            );
+            assert!(result.distances.iter().all(|x| *x > 0.));
+            assert!(result.distances.windows(2).all(|w| w[0] <= w[1]));
+        }
+    }
+
     #[test]
     fn flat_index_range_search() {
         let mut index = FlatIndexImpl::new(D, MetricType::L2).unwrap();
@@ -367,4 +717,29 @@ mod tests {
         };
         assert_eq!(index.ntotal(), 5);
     }
+
+    #[test]
+    fn flat_index_jensen_shannon() {
+        let js = |p: &[f32], q: &[f32]| -> f32 {
+            p.iter()
+                .zip(q)
+                .map(|(&a, &b)| {
+                    let m = 0.5 * (a + b);
+                    0.5 * (a * (a / m).ln() + b * (b / m).ln())
+                })
+                .sum()
+        };
+
+        let mut index = FlatIndexImpl::new(3, MetricType::JensenShannon).unwrap();
+        assert_eq!(index.metric_type(), MetricType::JensenShannon);
+        let far = [0.1, 0.1, 0.8];
+        let near = [0.4, 0.35, 0.25];
+        index.add(&[far, near].concat()).unwrap();
+
+        let my_query = [0.5, 0.3, 0.2];
+        let result = index.search(&my_query, 2).unwrap();
+        assert_eq!(result.labels, vec![Idx::new(1), Idx::new(0)]);
+        assert!((result.distances[0] - js(&my_query, &near)).abs() < 1e-5);
+        assert!((result.distances[1] - js(&my_query, &far)).abs() < 1e-5);
+    }
 }
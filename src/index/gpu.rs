@@ -4,12 +4,12 @@ use super::flat::FlatIndexImpl;
 use super::ivf_flat::IVFFlatIndexImpl;
 use super::scalar_quantizer::IVFScalarQuantizerIndexImpl;
 use super::{
-    AssignSearchResult, CpuIndex, FromInnerPtr, Idx, Index, IndexImpl, NativeIndex,
-    RangeSearchResult, SearchResult,
+    checked_decode_len, checked_idx_t, checked_result_len, live_ptr, AssignSearchResult, CpuIndex,
+    FromInnerPtr, Idx, Index, IndexImpl, NativeIndex, RangeSearchResult, SearchResult,
 };
 use crate::error::Result;
 use crate::faiss_try;
-use crate::gpu::GpuResourcesProvider;
+use crate::gpu::{GpuClonerOptions, GpuMultipleClonerOptions, GpuResourcesProvider};
 use crate::metric::MetricType;
 use crate::selector::IdSelector;
 use faiss_sys::*;
@@ -29,8 +29,25 @@ pub trait GpuIndex: Index {}
 /// [`into_gpu`] or [`into_cpu`] isn't necessarily faster, but will automatically
 /// free the originating index.
 ///
+/// Vectors obtained with `reconstruct` or `reconstruct_n` are copied from
+/// device memory into host memory. Index types which do not support
+/// reconstruction on the GPU fail with a native error describing the
+/// unsupported operation.
+///
 /// The `'gpu` lifetime ensures that the [GPU resources] are in scope for as
-/// long as the index lives.
+/// long as the index lives. Dropping the resources while a GPU index still
+/// refers to them is rejected at compile time:
+///
+/// ```compile_fail
+/// use faiss::{index_factory, Index, MetricType, StandardGpuResources};
+/// # fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// let res = StandardGpuResources::new()?;
+/// let index = index_factory(8, "Flat", MetricType::L2)?.into_gpu(&res, 0)?;
+/// drop(res); // error: cannot move out of `res` because it is borrowed
+/// assert_eq!(index.ntotal(), 0);
+/// # Ok(())
+/// # }
+/// ```
 ///
 /// [`into_gpu`]: ../struct.IndexImpl.html#method.into_gpu
 /// [`to_gpu`]: ../struct.IndexImpl.html#method.to_gpu
@@ -92,6 +109,44 @@ where
         }
     }
 
+    /// Build a GPU index from the given CPU native index, with the given
+    /// cloner options.
+    /// Users will indirectly use this through [`to_gpu_with_options`] or
+    /// [`into_gpu_with_options`].
+    ///
+    /// # Error
+    ///
+    /// The operation fails if the index does not provide GPU support.
+    ///
+    /// [`to_gpu_with_options`]: ../struct.IndexImpl.html#method.to_gpu_with_options
+    /// [`into_gpu_with_options`]: ../struct.IndexImpl.html#method.into_gpu_with_options
+    pub(crate) fn from_cpu_with_options<G>(
+        index: &I,
+        gpu_res: &G,
+        device: i32,
+        options: &GpuClonerOptions,
+    ) -> Result<Self>
+    where
+        I: NativeIndex,
+        I: CpuIndex,
+        G: GpuResourcesProvider,
+    {
+        unsafe {
+            let mut gpuindex_ptr = ptr::null_mut();
+            faiss_try(faiss_index_cpu_to_gpu_with_options(
+                gpu_res.inner_ptr(),
+                device,
+                index.inner_ptr(),
+                options.inner_ptr(),
+                &mut gpuindex_ptr,
+            ))?;
+            Ok(GpuIndexImpl {
+                inner: gpuindex_ptr,
+                phantom: PhantomData,
+            })
+        }
+    }
+
     /// Build a GPU in from the given CPU native index.
     /// Users will indirectly use this through [`to_gpu`] or [`into_gpu`].
     ///
@@ -128,6 +183,50 @@ where
             })
         }
     }
+
+    /// Build a GPU index from the given CPU native index, distributed over
+    /// the devices as specified by the cloner options.
+    /// Users will indirectly use this through [`to_gpu_multiple_with_options`].
+    ///
+    /// # Error
+    ///
+    /// The operation fails if the number of GPU resources and number of
+    /// devices do not match, or the index does not provide GPU support.
+    ///
+    /// [`to_gpu_multiple_with_options`]: ../struct.IndexImpl.html#method.to_gpu_multiple_with_options
+    pub(crate) fn from_cpu_multiple_with_options<G>(
+        index: &I,
+        gpu_res: &[G],
+        devices: &[i32],
+        options: &GpuMultipleClonerOptions,
+    ) -> Result<Self>
+    where
+        I: NativeIndex,
+        I: CpuIndex,
+        G: GpuResourcesProvider,
+    {
+        if gpu_res.len() != devices.len() {
+            return Err(crate::error::Error::GpuResourcesMatch);
+        }
+
+        let res_ptr: Vec<*mut _> = gpu_res.iter().map(|r| r.inner_ptr()).collect();
+        unsafe {
+            let mut gpuindex_ptr = ptr::null_mut();
+            faiss_try(faiss_index_cpu_to_gpu_multiple_with_options(
+                res_ptr.as_ptr(),
+                res_ptr.len(),
+                devices.as_ptr(),
+                devices.len(),
+                index.inner_ptr(),
+                options.inner_ptr(),
+                &mut gpuindex_ptr,
+            ))?;
+            Ok(GpuIndexImpl {
+                inner: gpuindex_ptr,
+                phantom: PhantomData,
+            })
+        }
+    }
 }
 
 impl IndexImpl {
@@ -165,6 +264,44 @@ impl IndexImpl {
         // let the CPU index drop naturally
     }
 
+    /// Build a GPU index from the given CPU native index, with the given
+    /// cloner options.
+    ///
+    /// # Errors
+    ///
+    /// The operation fails if the index type does not provide GPU support.
+    pub fn to_gpu_with_options<'gpu, G>(
+        &self,
+        gpu_res: &'gpu G,
+        device: i32,
+        options: &GpuClonerOptions,
+    ) -> Result<GpuIndexImpl<'gpu, IndexImpl>>
+    where
+        G: GpuResourcesProvider + 'gpu,
+    {
+        GpuIndexImpl::from_cpu_with_options(self, gpu_res, device, options)
+    }
+
+    /// Build a GPU index from the given CPU native index, with the given
+    /// cloner options. The index residing in CPU memory is discarded in the
+    /// process.
+    ///
+    /// # Errors
+    ///
+    /// The operation fails if the index type does not provide GPU support.
+    pub fn into_gpu_with_options<'gpu, G>(
+        self,
+        gpu_res: &'gpu G,
+        device: i32,
+        options: &GpuClonerOptions,
+    ) -> Result<GpuIndexImpl<'gpu, IndexImpl>>
+    where
+        G: GpuResourcesProvider + 'gpu,
+    {
+        self.to_gpu_with_options(gpu_res, device, options)
+        // let the CPU index drop naturally
+    }
+
     /// Build a GPU index from the given CPU native index.
     ///
     /// # Errors
@@ -200,6 +337,46 @@ impl IndexImpl {
         self.to_gpu_multiple(gpu_res, devices)
         // let the CPU index drop naturally
     }
+
+    /// Build a GPU index from the given CPU native index, sharded or
+    /// replicated over the devices according to `options`.
+    ///
+    /// # Errors
+    ///
+    /// The operation fails if the number of GPU resources and number of
+    /// devices do not match, or the index does not provide GPU support.
+    pub fn to_gpu_multiple_with_options<'gpu, G>(
+        &self,
+        gpu_res: &'gpu [G],
+        devices: &[i32],
+        options: &GpuMultipleClonerOptions,
+    ) -> Result<GpuIndexImpl<'gpu, IndexImpl>>
+    where
+        G: GpuResourcesProvider + 'gpu,
+    {
+        GpuIndexImpl::from_cpu_multiple_with_options(self, gpu_res, devices, options)
+    }
+
+    /// Build a GPU index from the given CPU native index, sharded or
+    /// replicated over the devices according to `options`. The index
+    /// residing in CPU memory is discarded in the process.
+    ///
+    /// # Errors
+    ///
+    /// The operation fails if the number of GPU resources and number of
+    /// devices do not match, or the index does not provide GPU support.
+    pub fn into_gpu_multiple_with_options<'gpu, G>(
+        self,
+        gpu_res: &'gpu [G],
+        devices: &[i32],
+        options: &GpuMultipleClonerOptions,
+    ) -> Result<GpuIndexImpl<'gpu, IndexImpl>>
+    where
+        G: GpuResourcesProvider + 'gpu,
+    {
+        self.to_gpu_multiple_with_options(gpu_res, devices, options)
+        // let the CPU index drop naturally
+    }
 }
 
 impl<'gpu, I> GpuIndexImpl<'gpu, I>
@@ -229,35 +406,37 @@ where
     I: Index,
 {
     fn is_trained(&self) -> bool {
-        unsafe { faiss_Index_is_trained(self.inner) != 0 }
+        unsafe { faiss_Index_is_trained(live_ptr(self.inner)) != 0 }
     }
 
     fn ntotal(&self) -> u64 {
-        unsafe { faiss_Index_ntotal(self.inner) as u64 }
+        unsafe { faiss_Index_ntotal(live_ptr(self.inner)) as u64 }
     }
 
     fn d(&self) -> u32 {
-        unsafe { faiss_Index_d(self.inner) as u32 }
+        unsafe { faiss_Index_d(live_ptr(self.inner)) as u32 }
     }
 
     fn metric_type(&self) -> MetricType {
-        unsafe { MetricType::from_code(faiss_Index_metric_type(self.inner) as u32).unwrap() }
+        unsafe {
+            MetricType::from_code(faiss_Index_metric_type(live_ptr(self.inner)) as u32).unwrap()
+        }
     }
 
     fn add(&mut self, x: &[f32]) -> Result<()> {
         unsafe {
-            let n = x.len() / self.d() as usize;
-            faiss_try(faiss_Index_add(self.inner, n as i64, x.as_ptr()))?;
+            let n = checked_idx_t(x.len() / self.d() as usize)?;
+            faiss_try(faiss_Index_add(self.inner, n, x.as_ptr()))?;
             Ok(())
         }
     }
 
     fn add_with_ids(&mut self, x: &[f32], xids: &[Idx]) -> Result<()> {
         unsafe {
-            let n = x.len() / self.d() as usize;
+            let n = checked_idx_t(x.len() / self.d() as usize)?;
             faiss_try(faiss_Index_add_with_ids(
                 self.inner,
-                n as i64,
+                n,
                 x.as_ptr(),
                 xids.as_ptr() as *const _,
             ))?;
@@ -267,37 +446,44 @@ where
 
     fn train(&mut self, x: &[f32]) -> Result<()> {
         unsafe {
-            let n = x.len() / self.d() as usize;
-            faiss_try(faiss_Index_train(self.inner, n as i64, x.as_ptr()))?;
+            let n = checked_idx_t(x.len() / self.d() as usize)?;
+            faiss_try(faiss_Index_train(self.inner, n, x.as_ptr()))?;
             Ok(())
         }
     }
 
     fn assign(&mut self, query: &[f32], k: usize) -> Result<AssignSearchResult> {
+        if k == 0 {
+            return Ok(Default::default());
+        }
         unsafe {
             let nq = query.len() / self.d() as usize;
-            let mut out_labels = vec![Idx::none(); k * nq];
+            let mut out_labels = vec![Idx::none(); checked_result_len(nq, k)?];
             faiss_try(faiss_Index_assign(
                 self.inner,
-                nq as idx_t,
+                checked_idx_t(nq)?,
                 query.as_ptr(),
                 out_labels.as_mut_ptr() as *mut _,
-                k as i64,
+                checked_idx_t(k)?,
             ))?;
             Ok(AssignSearchResult { labels: out_labels })
         }
     }
 
     fn search(&mut self, query: &[f32], k: usize) -> Result<SearchResult> {
+        if k == 0 {
+            return Ok(Default::default());
+        }
         unsafe {
             let nq = query.len() / self.d() as usize;
-            let mut distances = vec![0_f32; k * nq];
-            let mut labels = vec![Idx::none(); k * nq];
+            let len = checked_result_len(nq, k)?;
+            let mut distances = vec![0_f32; len];
+            let mut labels = vec![Idx::none(); len];
             faiss_try(faiss_Index_search(
                 self.inner,
-                nq as idx_t,
+                checked_idx_t(nq)?,
                 query.as_ptr(),
-                k as idx_t,
+                checked_idx_t(k)?,
                 distances.as_mut_ptr(),
                 labels.as_mut_ptr() as *mut _,
             ))?;
@@ -307,7 +493,7 @@ where
 
     fn range_search(&mut self, query: &[f32], radius: f32) -> Result<RangeSearchResult> {
         unsafe {
-            let nq = (query.len() / self.d() as usize) as idx_t;
+            let nq = checked_idx_t(query.len() / self.d() as usize)?;
             let mut p_res: *mut FaissRangeSearchResult = ptr::null_mut();
             faiss_try(faiss_RangeSearchResult_new(&mut p_res, nq))?;
             faiss_try(faiss_Index_range_search(
@@ -349,6 +535,66 @@ where
             faiss_Index_set_verbose(self.inner, std::os::raw::c_int::from(value));
         }
     }
+
+    fn sa_code_size(&self) -> Result<usize> {
+        unsafe {
+            let mut size = 0;
+            faiss_try(faiss_Index_sa_code_size(self.inner, &mut size))?;
+            Ok(size)
+        }
+    }
+
+    fn sa_encode(&self, x: &[f32]) -> Result<Vec<u8>> {
+        unsafe {
+            let n = x.len() / self.d() as usize;
+            let mut codes = vec![0_u8; checked_result_len(n, self.sa_code_size()?)?];
+            faiss_try(faiss_Index_sa_encode(
+                self.inner,
+                checked_idx_t(n)?,
+                x.as_ptr(),
+                codes.as_mut_ptr(),
+            ))?;
+            Ok(codes)
+        }
+    }
+
+    fn sa_decode_into(&self, codes: &[u8], out: &mut [f32]) -> Result<()> {
+        unsafe {
+            let n = checked_decode_len(self.sa_code_size()?, self.d(), codes.len(), out.len())?;
+            faiss_try(faiss_Index_sa_decode(
+                self.inner,
+                n,
+                codes.as_ptr(),
+                out.as_mut_ptr(),
+            ))?;
+            Ok(())
+        }
+    }
+
+    fn reconstruct(&self, key: Idx) -> Result<Vec<f32>> {
+        unsafe {
+            let mut output = vec![0_f32; self.d() as usize];
+            faiss_try(faiss_Index_reconstruct(
+                self.inner,
+                key.to_native(),
+                output.as_mut_ptr(),
+            ))?;
+            Ok(output)
+        }
+    }
+
+    fn reconstruct_n(&self, first_key: Idx, count: usize) -> Result<Vec<f32>> {
+        unsafe {
+            let mut output = vec![0_f32; checked_result_len(count, self.d() as usize)?];
+            faiss_try(faiss_Index_reconstruct_n(
+                self.inner,
+                first_key.to_native(),
+                checked_idx_t(count)?,
+                output.as_mut_ptr(),
+            ))?;
+            Ok(output)
+        }
+    }
 }
 
 impl<'g, I> NativeIndex for GpuIndexImpl<'g, I>
@@ -565,13 +811,35 @@ where
 mod tests {
     use super::super::{index_factory, CpuIndex, Idx, Index};
     use super::GpuIndex;
-    use crate::gpu::{GpuResources, StandardGpuResources};
+    use crate::gpu::{
+        GpuClonerOptions, GpuMultipleClonerOptions, GpuResources, StandardGpuResources,
+    };
     use crate::index::flat::FlatIndex;
     use crate::metric::MetricType;
 
     fn is_in_gpu<I: GpuIndex>(_: &I) {}
     fn is_in_cpu<I: CpuIndex>(_: &I) {}
 
+    #[test]
+    fn resources_shared_across_indexes() {
+        let res = StandardGpuResources::new().unwrap();
+        {
+            // both indexes borrow the same resources
+            let index1 = index_factory(8, "Flat", MetricType::L2)
+                .unwrap()
+                .into_gpu(&res, 0)
+                .unwrap();
+            let index2 = index_factory(8, "Flat", MetricType::L2)
+                .unwrap()
+                .into_gpu(&res, 0)
+                .unwrap();
+            assert_eq!(index1.ntotal(), 0);
+            assert_eq!(index2.ntotal(), 0);
+        }
+        // the indexes are gone, so the resources may now be dropped
+        drop(res);
+    }
+
     #[test]
     fn flat_in_and_out() {
         let mut res = StandardGpuResources::new().unwrap();
@@ -625,6 +893,78 @@ mod tests {
         assert_eq!(gpu_index.ntotal(), 5); // indexed vectors should be retained
     }
 
+    #[test]
+    fn flat_sharded_multiple() {
+        let res = [
+            StandardGpuResources::new().unwrap(),
+            StandardGpuResources::new().unwrap(),
+        ];
+        let devices = [0, 0];
+        let options = GpuMultipleClonerOptions::new().unwrap().shard(true);
+
+        let mut index = index_factory(8, "Flat", MetricType::L2)
+            .unwrap()
+            .into_gpu_multiple_with_options(&res, &devices, &options)
+            .unwrap();
+        let some_data = &[
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 1., 1., 1., -1., 0., 0.,
+            0., 1., 1., 0., 0., -1., 100., 100., 100., 100., -100., 100., 100., 100., 120., 100.,
+            100., 105., -100., 100., 100., 105.,
+        ];
+        index.add(some_data).unwrap();
+        assert_eq!(index.ntotal(), 5);
+
+        // results from all shards are merged
+        let result = index.search(&[0.; 8], 5).unwrap();
+        assert_eq!(
+            result.labels,
+            vec![2, 1, 0, 3, 4]
+                .into_iter()
+                .map(Idx::new)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn ivfpq_float16_lookup_tables() {
+        let res = StandardGpuResources::new().unwrap();
+        let options = GpuClonerOptions::new().unwrap().use_float16(true);
+
+        let some_data: Vec<f32> = (0..1024 * 16).map(|i| ((i * 13) % 37) as f32).collect();
+        let mut index = index_factory(16, "IVF8,PQ4", MetricType::L2).unwrap();
+        index.train(&some_data).unwrap();
+        index.add(&some_data).unwrap();
+        let mut index = index.into_gpu_with_options(&res, 0, &options).unwrap();
+        is_in_gpu(&index);
+        assert_eq!(index.ntotal(), 1024);
+
+        let result = index.search(&some_data[..2 * 16], 4).unwrap();
+        assert_eq!(result.labels.len(), 8);
+        for (label, distance) in result.labels.iter().zip(&result.distances) {
+            assert!(label.get().unwrap() < 1024);
+            assert!(distance.is_finite() && *distance >= 0.);
+        }
+    }
+
+    #[test]
+    fn flat_index_reconstruct_gpu() {
+        let res = StandardGpuResources::new().unwrap();
+
+        let mut index = index_factory(8, "Flat", MetricType::L2)
+            .unwrap()
+            .into_gpu(&res, 0)
+            .unwrap();
+        let some_data = &[
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 1., 1., 1., -1., 0., 0.,
+            0., 1., 1., 0., 0., -1.,
+        ];
+        index.add(some_data).unwrap();
+
+        assert_eq!(index.reconstruct(Idx::new(1)).unwrap(), &some_data[8..16]);
+        assert_eq!(index.reconstruct_n(Idx::new(0), 3).unwrap(), some_data);
+        assert!(index.reconstruct(Idx::new(3)).is_err());
+    }
+
     #[test]
     fn flat_index_search_into_gpu() {
         let res = StandardGpuResources::new().unwrap();
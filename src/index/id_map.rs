@@ -34,7 +34,8 @@
 //!
 //! `IdMap` also works for GPU backed indexes, but the index map will reside
 //! in CPU memory. Once an index map is made, moving an index to/from the GPU
-//! is not possible.
+//! is not possible. The supported order is to move the index to the GPU first,
+//! and then to wrap it in an `IdMap`.
 //!
 //! ```
 //! # #[cfg(feature = "gpu")]
@@ -52,11 +53,33 @@
 //! # run().unwrap()
 //! ```
 //!
+//! An ID map over a GPU index is not a CPU index, so it cannot be moved to
+//! the GPU again, or written to a file.
+#![cfg_attr(
+    feature = "gpu",
+    doc = r#"
+```compile_fail
+# use faiss::{GpuResources, StandardGpuResources, Index, FlatIndex, IdMap};
+# use faiss::error::Result;
+# fn run() -> Result<()> {
+let gpu_res = StandardGpuResources::new()?;
+let index = IdMap::new(FlatIndex::new_l2(8)?.into_gpu(&gpu_res, 0)?)?;
+let index = index.into_gpu(&gpu_res, 0)?; // error: no method `into_gpu`
+# Ok(())
+# }
+```
+"#
+)]
+//!
+//! Erasing the index type with `upcast` bypasses this check, in which case
+//! moving the index to the GPU fails with a native error.
+//!
 
 use crate::error::{Error, Result};
 use crate::index::{
-    self, AssignSearchResult, ConcurrentIndex, CpuIndex, FromInnerPtr, Idx, Index, NativeIndex,
-    RangeSearchResult, SearchResult,
+    self, checked_decode_len, checked_idx_t, checked_result_len, live_ptr, AssignSearchResult,
+    ConcurrentIndex, CpuIndex, FromInnerPtr, Idx, Index, NativeIndex, RangeSearchResult,
+    SearchResult,
 };
 use crate::selector::IdSelector;
 use crate::{faiss_try, MetricType};
@@ -131,6 +154,29 @@ where
         }
     }
 
+    /// Collect the distinct IDs of the vectors in the index, in ascending
+    /// order. Unlike [`id_map`], this skips any empty IDs, and lists each
+    /// ID only once even if several vectors were added with it.
+    ///
+    /// [`id_map`]: #method.id_map
+    pub fn ids(&self) -> Vec<Idx> {
+        let mut ids: Vec<_> = self.id_map().iter().filter_map(|id| id.get()).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids.into_iter().map(Idx::new).collect()
+    }
+
+    /// Translate a user ID to its position in the internal index, which is
+    /// also its position in [`id_map`]. Returns `None` if no vector in the
+    /// index has this ID.
+    ///
+    /// This performs a linear scan of the ID map.
+    ///
+    /// [`id_map`]: #method.id_map
+    pub fn internal_position(&self, id: Idx) -> Option<usize> {
+        self.id_map().iter().position(|&x| x == id)
+    }
+
     /// Obtain the raw pointer to the internal index.
     ///
     /// # Safety
@@ -144,11 +190,19 @@ where
     }
 
     /// Discard the ID map, recovering the index originally created without it.
+    ///
+    /// Ownership of the internal index is transferred to the returned value,
+    /// so that it is freed exactly once, when that value is dropped.
     pub fn into_inner(self) -> I
     where
         I: FromInnerPtr,
     {
         unsafe {
+            debug_assert_ne!(
+                faiss_IndexIDMap_own_fields(self.inner),
+                0,
+                "ID map should own its internal index"
+            );
             // make id map disown the index
             faiss_IndexIDMap_set_own_fields(self.inner, 0);
             // now it's safe to build a managed index
@@ -159,16 +213,26 @@ where
 
     /// Discard the ID map, recovering the index originally created without it.
     /// Safety build managed index from pointer.
+    ///
+    /// If the internal index is not compatible with `I`, an error is returned
+    /// and the internal index is freed along with the ID map.
     pub fn try_into_inner(self) -> Result<I>
     where
         I: index::TryFromInnerPtr,
     {
         unsafe {
-            // make id map disown the index
-            faiss_IndexIDMap_set_own_fields(self.inner, 0);
-            // now it's safe to build a managed index
+            debug_assert_ne!(
+                faiss_IndexIDMap_own_fields(self.inner),
+                0,
+                "ID map should own its internal index"
+            );
+            // build a managed index first
             // (`index_inner` is expected to always point to a valid index)
-            I::try_from_inner_ptr(self.index_inner)
+            let index = I::try_from_inner_ptr(self.index_inner)?;
+            // only then make id map disown the index,
+            // otherwise it would be leaked on failure
+            faiss_IndexIDMap_set_own_fields(self.inner, 0);
+            Ok(index)
         }
     }
 
@@ -197,35 +261,38 @@ where
 
 impl<I> Index for IdMap<I> {
     fn is_trained(&self) -> bool {
-        unsafe { faiss_Index_is_trained(self.inner_ptr()) != 0 }
+        unsafe { faiss_Index_is_trained(live_ptr(self.inner_ptr())) != 0 }
     }
 
     fn ntotal(&self) -> u64 {
-        unsafe { faiss_Index_ntotal(self.inner_ptr()) as u64 }
+        unsafe { faiss_Index_ntotal(live_ptr(self.inner_ptr())) as u64 }
     }
 
     fn d(&self) -> u32 {
-        unsafe { faiss_Index_d(self.inner_ptr()) as u32 }
+        unsafe { faiss_Index_d(live_ptr(self.inner_ptr())) as u32 }
     }
 
     fn metric_type(&self) -> MetricType {
-        unsafe { MetricType::from_code(faiss_Index_metric_type(self.inner_ptr()) as u32).unwrap() }
+        unsafe {
+            MetricType::from_code(faiss_Index_metric_type(live_ptr(self.inner_ptr())) as u32)
+                .unwrap()
+        }
     }
 
     fn add(&mut self, x: &[f32]) -> Result<()> {
         unsafe {
-            let n = x.len() / self.d() as usize;
-            faiss_try(faiss_Index_add(self.inner_ptr(), n as i64, x.as_ptr()))?;
+            let n = checked_idx_t(x.len() / self.d() as usize)?;
+            faiss_try(faiss_Index_add(self.inner_ptr(), n, x.as_ptr()))?;
             Ok(())
         }
     }
 
     fn add_with_ids(&mut self, x: &[f32], xids: &[Idx]) -> Result<()> {
         unsafe {
-            let n = x.len() / self.d() as usize;
+            let n = checked_idx_t(x.len() / self.d() as usize)?;
             faiss_try(faiss_Index_add_with_ids(
                 self.inner_ptr(),
-                n as i64,
+                n,
                 x.as_ptr(),
                 xids.as_ptr() as *const _,
             ))?;
@@ -234,35 +301,42 @@ impl<I> Index for IdMap<I> {
     }
     fn train(&mut self, x: &[f32]) -> Result<()> {
         unsafe {
-            let n = x.len() / self.d() as usize;
-            faiss_try(faiss_Index_train(self.inner_ptr(), n as i64, x.as_ptr()))?;
+            let n = checked_idx_t(x.len() / self.d() as usize)?;
+            faiss_try(faiss_Index_train(self.inner_ptr(), n, x.as_ptr()))?;
             Ok(())
         }
     }
     fn assign(&mut self, query: &[f32], k: usize) -> Result<AssignSearchResult> {
+        if k == 0 {
+            return Ok(Default::default());
+        }
         unsafe {
             let nq = query.len() / self.d() as usize;
-            let mut out_labels = vec![Idx::none(); k * nq];
+            let mut out_labels = vec![Idx::none(); checked_result_len(nq, k)?];
             faiss_try(faiss_Index_assign(
                 self.inner_ptr(),
-                nq as idx_t,
+                checked_idx_t(nq)?,
                 query.as_ptr(),
                 out_labels.as_mut_ptr() as *mut _,
-                k as i64,
+                checked_idx_t(k)?,
             ))?;
             Ok(AssignSearchResult { labels: out_labels })
         }
     }
     fn search(&mut self, query: &[f32], k: usize) -> Result<SearchResult> {
+        if k == 0 {
+            return Ok(Default::default());
+        }
         unsafe {
             let nq = query.len() / self.d() as usize;
-            let mut distances = vec![0_f32; k * nq];
-            let mut labels = vec![Idx::none(); k * nq];
+            let len = checked_result_len(nq, k)?;
+            let mut distances = vec![0_f32; len];
+            let mut labels = vec![Idx::none(); len];
             faiss_try(faiss_Index_search(
                 self.inner_ptr(),
-                nq as idx_t,
+                checked_idx_t(nq)?,
                 query.as_ptr(),
-                k as idx_t,
+                checked_idx_t(k)?,
                 distances.as_mut_ptr(),
                 labels.as_mut_ptr() as *mut _,
             ))?;
@@ -271,7 +345,7 @@ impl<I> Index for IdMap<I> {
     }
     fn range_search(&mut self, query: &[f32], radius: f32) -> Result<RangeSearchResult> {
         unsafe {
-            let nq = (query.len() / self.d() as usize) as idx_t;
+            let nq = checked_idx_t(query.len() / self.d() as usize)?;
             let mut p_res: *mut FaissRangeSearchResult = ::std::ptr::null_mut();
             faiss_try(faiss_RangeSearchResult_new(&mut p_res, nq))?;
             faiss_try(faiss_Index_range_search(
@@ -313,6 +387,68 @@ impl<I> Index for IdMap<I> {
             faiss_Index_set_verbose(self.inner_ptr(), c_int::from(value));
         }
     }
+
+    // the standalone codec is not implemented by the ID map itself,
+    // so it is forwarded to the inner index, which encodes the same vectors
+    fn sa_code_size(&self) -> Result<usize> {
+        unsafe {
+            let mut size = 0;
+            faiss_try(faiss_Index_sa_code_size(self.index_inner, &mut size))?;
+            Ok(size)
+        }
+    }
+
+    fn sa_encode(&self, x: &[f32]) -> Result<Vec<u8>> {
+        unsafe {
+            let n = x.len() / self.d() as usize;
+            let mut codes = vec![0_u8; checked_result_len(n, self.sa_code_size()?)?];
+            faiss_try(faiss_Index_sa_encode(
+                self.index_inner,
+                checked_idx_t(n)?,
+                x.as_ptr(),
+                codes.as_mut_ptr(),
+            ))?;
+            Ok(codes)
+        }
+    }
+
+    fn sa_decode_into(&self, codes: &[u8], out: &mut [f32]) -> Result<()> {
+        unsafe {
+            let n = checked_decode_len(self.sa_code_size()?, self.d(), codes.len(), out.len())?;
+            faiss_try(faiss_Index_sa_decode(
+                self.index_inner,
+                n,
+                codes.as_ptr(),
+                out.as_mut_ptr(),
+            ))?;
+            Ok(())
+        }
+    }
+
+    fn reconstruct(&self, key: Idx) -> Result<Vec<f32>> {
+        unsafe {
+            let mut output = vec![0_f32; self.d() as usize];
+            faiss_try(faiss_Index_reconstruct(
+                self.inner_ptr(),
+                key.to_native(),
+                output.as_mut_ptr(),
+            ))?;
+            Ok(output)
+        }
+    }
+
+    fn reconstruct_n(&self, first_key: Idx, count: usize) -> Result<Vec<f32>> {
+        unsafe {
+            let mut output = vec![0_f32; checked_result_len(count, self.d() as usize)?];
+            faiss_try(faiss_Index_reconstruct_n(
+                self.inner_ptr(),
+                first_key.to_native(),
+                checked_idx_t(count)?,
+                output.as_mut_ptr(),
+            ))?;
+            Ok(output)
+        }
+    }
 }
 
 impl<I> ConcurrentIndex for IdMap<I>
@@ -320,29 +456,36 @@ where
     I: ConcurrentIndex,
 {
     fn assign(&self, query: &[f32], k: usize) -> Result<AssignSearchResult> {
+        if k == 0 {
+            return Ok(Default::default());
+        }
         unsafe {
             let nq = query.len() / self.d() as usize;
-            let mut out_labels = vec![Idx::none(); k * nq];
+            let mut out_labels = vec![Idx::none(); checked_result_len(nq, k)?];
             faiss_try(faiss_Index_assign(
                 self.inner,
-                nq as idx_t,
+                checked_idx_t(nq)?,
                 query.as_ptr(),
                 out_labels.as_mut_ptr() as *mut _,
-                k as i64,
+                checked_idx_t(k)?,
             ))?;
             Ok(AssignSearchResult { labels: out_labels })
         }
     }
     fn search(&self, query: &[f32], k: usize) -> Result<SearchResult> {
+        if k == 0 {
+            return Ok(Default::default());
+        }
         unsafe {
             let nq = query.len() / self.d() as usize;
-            let mut distances = vec![0_f32; k * nq];
-            let mut labels = vec![Idx::none(); k * nq];
+            let len = checked_result_len(nq, k)?;
+            let mut distances = vec![0_f32; len];
+            let mut labels = vec![Idx::none(); len];
             faiss_try(faiss_Index_search(
                 self.inner,
-                nq as idx_t,
+                checked_idx_t(nq)?,
                 query.as_ptr(),
-                k as idx_t,
+                checked_idx_t(k)?,
                 distances.as_mut_ptr(),
                 labels.as_mut_ptr() as *mut _,
             ))?;
@@ -351,7 +494,7 @@ where
     }
     fn range_search(&self, query: &[f32], radius: f32) -> Result<RangeSearchResult> {
         unsafe {
-            let nq = (query.len() / self.d() as usize) as idx_t;
+            let nq = checked_idx_t(query.len() / self.d() as usize)?;
             let mut p_res: *mut FaissRangeSearchResult = ptr::null_mut();
             faiss_try(faiss_RangeSearchResult_new(&mut p_res, nq))?;
             faiss_try(faiss_Index_range_search(
@@ -391,10 +534,67 @@ impl IndexImpl {
 #[cfg(test)]
 mod tests {
     use super::IdMap;
-    use crate::index::{flat::FlatIndexImpl, index_factory, Idx, Index, IndexImpl};
+    use crate::index::{flat::FlatIndexImpl, index_factory, Idx, Index, IndexExt, IndexImpl};
     use crate::selector::IdSelector;
     use crate::MetricType;
 
+    #[test]
+    fn sq_index_sa_codec() {
+        let index = index_factory(4, "SQ8", MetricType::L2).unwrap();
+        let mut index = IdMap::new(index).unwrap();
+        let some_data = &[0., 0.25, 0.5, 1., 1., 0.5, 0.25, 0.];
+        index.train(some_data).unwrap();
+        index
+            .add_with_ids(some_data, &[Idx::new(7), Idx::new(9)])
+            .unwrap();
+
+        assert_eq!(index.sa_code_size().unwrap(), 4);
+        let codes = index.sa_encode(some_data).unwrap();
+        assert_eq!(codes.len(), 8);
+        let decoded = index.sa_decode(&codes).unwrap();
+        assert_eq!(decoded.len(), some_data.len());
+        for (x, y) in decoded.iter().zip(some_data) {
+            assert!((x - y).abs() < 0.01);
+        }
+    }
+
+    #[test]
+    fn flat_index_ids() {
+        let index = index_factory(4, "Flat", MetricType::L2).unwrap();
+        let some_data = &[
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 0., 0., 0., 0.,
+        ];
+        let mut index = IdMap::new(index).unwrap();
+        assert!(index.ids().is_empty());
+        index
+            .add_with_ids(&some_data[..12], &[Idx::new(9), Idx::new(3), Idx::new(6)])
+            .unwrap();
+        assert_eq!(index.ids(), vec![Idx::new(3), Idx::new(6), Idx::new(9)]);
+
+        index
+            .add_with_ids(&some_data[12..], &[Idx::new(6)])
+            .unwrap();
+        assert_eq!(index.id_map().len(), 4);
+        assert_eq!(index.ids(), vec![Idx::new(3), Idx::new(6), Idx::new(9)]);
+    }
+
+    #[test]
+    fn flat_index_internal_position() {
+        let index = index_factory(4, "Flat", MetricType::L2).unwrap();
+        let some_data = &[
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1.,
+        ];
+        let some_ids = &[Idx::new(3), Idx::new(6), Idx::new(9)];
+        let mut index = IdMap::new(index).unwrap();
+        index.add_with_ids(some_data, some_ids).unwrap();
+
+        assert_eq!(index.internal_position(Idx::new(3)), Some(0));
+        assert_eq!(index.internal_position(Idx::new(6)), Some(1));
+        assert_eq!(index.internal_position(Idx::new(9)), Some(2));
+        assert_eq!(index.internal_position(Idx::new(4)), None);
+        assert_eq!(index.id_map()[1], Idx::new(6));
+    }
+
     #[test]
     fn flat_index_search_ids() {
         let index = index_factory(8, "Flat", MetricType::L2).unwrap();
@@ -450,6 +650,31 @@ mod tests {
         assert!(result.distances.iter().all(|x| *x > 0.));
     }
 
+    #[test]
+    fn flat_index_add_with_base_id() {
+        let index = index_factory(2, "Flat", MetricType::L2).unwrap();
+        let mut index = IdMap::new(index).unwrap();
+        index
+            .add_with_base_id(&[0., 0., 1., 1., 2., 2., 3., 3.], Idx::new(100))
+            .unwrap();
+        assert_eq!(index.ntotal(), 4);
+
+        let result = index.search(&[0., 0., 3., 3.], 4).unwrap();
+        assert_eq!(
+            result.labels,
+            vec![100, 101, 102, 103, 103, 102, 101, 100]
+                .into_iter()
+                .map(Idx::new)
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(
+            index.add_with_base_id(&[0., 0.], Idx::none()),
+            Err(crate::error::Error::Overflow)
+        );
+        assert_eq!(index.ntotal(), 4);
+    }
+
     #[test]
     fn index_remove_ids() {
         let index = index_factory(4, "Flat", MetricType::L2).unwrap();
@@ -478,6 +703,41 @@ mod tests {
         assert_eq!(index.d(), 4);
     }
 
+    #[test]
+    fn into_inner_drop_cycles() {
+        let some_data = &[
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 1., 1., 1., -1.,
+        ];
+        let some_ids = &[Idx::new(3), Idx::new(6), Idx::new(9), Idx::new(12)];
+        for _ in 0..1000 {
+            let index = FlatIndexImpl::new_l2(4).unwrap();
+            let mut id_index = IdMap::new(index).unwrap();
+            id_index.add_with_ids(some_data, some_ids).unwrap();
+            let mut index: FlatIndexImpl = id_index.into_inner();
+            // the ID map is gone, but the recovered index must still be valid
+            assert_eq!(index.ntotal(), 4);
+            assert_eq!(index.xb(), some_data);
+            index.add(&some_data[..4]).unwrap();
+            assert_eq!(index.ntotal(), 5);
+
+            // wrapping the same index again must not share ownership
+            let id_index = IdMap::new(index).unwrap();
+            let index: FlatIndexImpl = id_index.try_into_inner().unwrap();
+            assert_eq!(index.ntotal(), 5);
+        }
+    }
+
+    #[test]
+    fn try_cast_inner_index_failure_cycles() {
+        use crate::index::lsh::LshIndex;
+        for _ in 0..1000 {
+            let index = index_factory(4, "Flat", MetricType::L2).unwrap();
+            let id_index = IdMap::new(index).unwrap();
+            // the ID map is dropped along with its index on failure
+            assert!(id_index.try_cast_inner_index::<LshIndex>().is_err());
+        }
+    }
+
     #[test]
     fn try_cast_inner_index() {
         let index = index_factory(4, "Flat", MetricType::L2).unwrap();
@@ -503,4 +763,23 @@ mod tests {
 
         assert_eq!(id_map.d(), 4);
     }
+
+    #[cfg(feature = "gpu")]
+    #[test]
+    fn id_map_over_gpu_index_stays_on_gpu() {
+        use crate::gpu::StandardGpuResources;
+        use crate::index::UpcastIndex;
+
+        let res = StandardGpuResources::new().unwrap();
+        let index = FlatIndexImpl::new_l2(4).unwrap().into_gpu(&res, 0).unwrap();
+        let mut index = IdMap::new(index).unwrap();
+        index
+            .add_with_ids(&[0., 1., 0., 1.], &[Idx::new(5)])
+            .unwrap();
+
+        // the GPU sub-index cannot be cloned onto the GPU again
+        let index = index.upcast();
+        assert!(index.to_gpu(&res, 0).is_err());
+        assert_eq!(index.ntotal(), 1);
+    }
 }
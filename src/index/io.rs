@@ -2,10 +2,15 @@
 
 use crate::error::{Error, Result};
 use crate::faiss_try;
-use crate::index::{CpuIndex, FromInnerPtr, IndexImpl, NativeIndex};
+use crate::index::binary::BinaryIndexImpl;
+use crate::index::{index_factory, CpuIndex, FromInnerPtr, Idx, Index, IndexImpl, NativeIndex};
+use crate::metric::MetricType;
 use faiss_sys::*;
+use std::convert::TryInto;
 use std::ffi::CString;
-use std::os::raw::c_int;
+use std::fs::File;
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::os::raw::{c_int, c_void};
 use std::ptr;
 
 pub use super::io_flags::IoFlags;
@@ -56,7 +61,10 @@ where
 
 /// Read an index from a file with I/O flags.
 ///
-/// You can memory map some index types with this.
+/// You can memory map some index types with this. See [`IoFlags`] for the
+/// available flags, which can be combined with `|`.
+///
+/// [`IoFlags`]: struct.IoFlags.html
 ///
 /// # Error
 ///
@@ -79,11 +87,447 @@ where
     }
 }
 
+/// An anonymous temporary C stream, closed and deleted on drop.
+struct TempStream(*mut FILE);
+
+extern "C" {
+    fn tmpfile() -> *mut FILE;
+    fn fclose(f: *mut FILE) -> c_int;
+    fn fread(ptr: *mut c_void, size: usize, n: usize, f: *mut FILE) -> usize;
+    fn fwrite(ptr: *const c_void, size: usize, n: usize, f: *mut FILE) -> usize;
+    fn fflush(f: *mut FILE) -> c_int;
+    fn rewind(f: *mut FILE);
+    fn ferror(f: *mut FILE) -> c_int;
+}
+
+impl TempStream {
+    fn new() -> Result<Self> {
+        let f = unsafe { tmpfile() };
+        if f.is_null() {
+            return Err(Error::Io(std::io::Error::last_os_error().kind()));
+        }
+        Ok(TempStream(f))
+    }
+
+    fn rewind(&mut self) -> Result<()> {
+        unsafe {
+            if fflush(self.0) != 0 {
+                return Err(Error::Io(ErrorKind::Other));
+            }
+            rewind(self.0);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TempStream {
+    fn drop(&mut self) {
+        unsafe {
+            fclose(self.0);
+        }
+    }
+}
+
+const STREAM_BUFFER_SIZE: usize = 1 << 16;
+
+/// Write an index to the given writer, such as an in-memory buffer or a
+/// network stream.
+///
+/// Since the native API only serializes to C streams, the index is buffered
+/// through an anonymous temporary file, which is never given a path.
+///
+/// # Error
+///
+/// This function returns `Error::Io` if the temporary file could not be used
+/// or writing fails, or an error if the internal index writing operation
+/// fails.
+pub fn write_index_to_writer<I, W>(index: &I, mut writer: W) -> Result<()>
+where
+    I: NativeIndex,
+    I: CpuIndex,
+    W: Write,
+{
+    let mut stream = TempStream::new()?;
+    unsafe {
+        faiss_try(faiss_write_index(index.inner_ptr(), stream.0))?;
+    }
+    stream.rewind()?;
+    let mut buf = vec![0_u8; STREAM_BUFFER_SIZE];
+    loop {
+        let n = unsafe { fread(buf.as_mut_ptr() as *mut c_void, 1, buf.len(), stream.0) };
+        if n == 0 {
+            if unsafe { ferror(stream.0) } != 0 {
+                return Err(Error::Io(ErrorKind::Other));
+            }
+            break;
+        }
+        writer
+            .write_all(&buf[..n])
+            .map_err(|e| Error::Io(e.kind()))?;
+    }
+    writer.flush().map_err(|e| Error::Io(e.kind()))
+}
+
+/// Read an index from the given reader, until the end of its contents.
+///
+/// Since the native API only deserializes from C streams, the index is
+/// buffered through an anonymous temporary file, which is never given a path.
+///
+/// # Error
+///
+/// This function returns `Error::Io` if the temporary file could not be used
+/// or reading fails, or an error if the internal index reading operation
+/// fails.
+pub fn read_index_from_reader<R>(mut reader: R) -> Result<IndexImpl>
+where
+    R: Read,
+{
+    let mut stream = TempStream::new()?;
+    let mut buf = vec![0_u8; STREAM_BUFFER_SIZE];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(Error::Io(e.kind())),
+        };
+        let written = unsafe { fwrite(buf.as_ptr() as *const c_void, 1, n, stream.0) };
+        if written != n {
+            return Err(Error::Io(ErrorKind::WriteZero));
+        }
+    }
+    stream.rewind()?;
+    unsafe {
+        let mut inner = ptr::null_mut();
+        faiss_try(faiss_read_index(
+            stream.0,
+            IoFlags::MEM_RESIDENT.into(),
+            &mut inner,
+        ))?;
+        Ok(IndexImpl::from_inner_ptr(inner))
+    }
+}
+
+/// Write a binary index to a file.
+///
+/// # Error
+///
+/// This function returns an error if the description contains any byte with the value `\0` (since
+/// it cannot be converted to a C string), or if the internal index writing operation fails.
+pub fn write_index_binary<P>(index: &BinaryIndexImpl, file_name: P) -> Result<()>
+where
+    P: AsRef<str>,
+{
+    unsafe {
+        let f = file_name.as_ref();
+        let f = CString::new(f).map_err(|_| Error::BadFilePath)?;
+
+        faiss_try(faiss_write_index_binary_fname(
+            index.inner_ptr(),
+            f.as_ptr(),
+        ))?;
+        Ok(())
+    }
+}
+
+/// Read a binary index from a file.
+///
+/// # Error
+///
+/// This function returns an error if the description contains any byte with the value `\0` (since
+/// it cannot be converted to a C string), or if the internal index reading operation fails.
+pub fn read_index_binary<P>(file_name: P) -> Result<BinaryIndexImpl>
+where
+    P: AsRef<str>,
+{
+    unsafe {
+        let f = file_name.as_ref();
+        let f = CString::new(f).map_err(|_| Error::BadFilePath)?;
+        let mut inner = ptr::null_mut();
+        faiss_try(faiss_read_index_binary_fname(
+            f.as_ptr(),
+            IoFlags::MEM_RESIDENT.into(),
+            &mut inner,
+        ))?;
+        Ok(BinaryIndexImpl::from_inner_ptr(inner))
+    }
+}
+
+/// The metadata at the start of a serialized index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexHeader {
+    /// The four-character code of the index type, such as `IxF2` for a flat
+    /// index with the L2 metric
+    pub index_type: String,
+    /// The dimensionality of the indexed vectors
+    pub d: u32,
+    /// The number of indexed vectors
+    pub ntotal: u64,
+    /// Whether the index is trained
+    pub is_trained: bool,
+    /// The metric of the index
+    pub metric_type: MetricType,
+}
+
+/// Read the metadata of an index from the start of a file, without loading
+/// the rest of the index. Binary indexes have a different layout, and are
+/// not supported.
+///
+/// # Error
+///
+/// This function returns `Error::Io` if the file could not be read, with
+/// `ErrorKind::InvalidData` if it does not start with a valid header.
+pub fn read_index_header<P>(file_name: P) -> Result<IndexHeader>
+where
+    P: AsRef<str>,
+{
+    // fourcc, d, ntotal, two unused fields, is_trained, metric_type
+    let mut buf = [0_u8; 37];
+    File::open(file_name.as_ref())
+        .and_then(|mut f| f.read_exact(&mut buf))
+        .map_err(|e| Error::Io(e.kind()))?;
+    let invalid = Error::Io(ErrorKind::InvalidData);
+
+    let index_type = std::str::from_utf8(&buf[0..4])
+        .map_err(|_| invalid.clone())?
+        .to_string();
+    let d = i32::from_le_bytes(buf[4..8].try_into().unwrap());
+    let ntotal = i64::from_le_bytes(buf[8..16].try_into().unwrap());
+    let is_trained = buf[32] != 0;
+    let metric_type = i32::from_le_bytes(buf[33..37].try_into().unwrap());
+    if d < 0 || ntotal < 0 || metric_type < 0 {
+        return Err(invalid);
+    }
+    let metric_type = MetricType::from_code(metric_type as u32).ok_or(invalid)?;
+    Ok(IndexHeader {
+        index_type,
+        d: d as u32,
+        ntotal: ntotal as u64,
+        is_trained,
+        metric_type,
+    })
+}
+
+/// Check that a file holds a plausible serialized index, without loading
+/// it. The header is read and validated as in `read_index_header`, and the
+/// length of the file is checked against the size recorded in the header.
+/// Only the vectors of flat indexes are checked in full, other index types
+/// are only checked to have some content after the header.
+///
+/// Loading a corrupted file with `read_index` may allocate large amounts of
+/// memory or fail deep within the native library, so this is worth calling
+/// first on files from unreliable storage. A successful verification does
+/// not guarantee that the index can be loaded.
+///
+/// # Error
+///
+/// This function returns `Error::Io` if the file could not be read, with
+/// `ErrorKind::UnexpectedEof` if it is truncated, or
+/// `ErrorKind::InvalidData` if its contents are inconsistent.
+pub fn verify_index_file<P>(file_name: P) -> Result<()>
+where
+    P: AsRef<str>,
+{
+    let header = read_index_header(file_name.as_ref())?;
+    let mut f = File::open(file_name.as_ref()).map_err(|e| Error::Io(e.kind()))?;
+    let file_len = f.metadata().map_err(|e| Error::Io(e.kind()))?.len();
+    let header_len = match header.metric_type {
+        MetricType::L2 | MetricType::InnerProduct => METRIC_ARG_OFFSET,
+        _ => METRIC_ARG_OFFSET + 4,
+    } as u64;
+    let truncated = Error::Io(ErrorKind::UnexpectedEof);
+    let invalid = Error::Io(ErrorKind::InvalidData);
+    if file_len <= header_len {
+        return Err(truncated);
+    }
+
+    if let "IxF2" | "IxFI" | "IxFl" = header.index_type.as_str() {
+        // the vectors follow as a length-prefixed array of floats
+        let n = header
+            .ntotal
+            .checked_mul(u64::from(header.d))
+            .ok_or_else(|| invalid.clone())?;
+        let expected = n
+            .checked_mul(4)
+            .and_then(|len| len.checked_add(header_len + 8))
+            .ok_or_else(|| invalid.clone())?;
+        let mut buf = [0_u8; 8];
+        f.seek(SeekFrom::Start(header_len))
+            .and_then(|_| f.read_exact(&mut buf))
+            .map_err(|e| Error::Io(e.kind()))?;
+        if u64::from_le_bytes(buf) != n {
+            return Err(invalid);
+        }
+        if file_len < expected {
+            return Err(truncated);
+        }
+        if file_len > expected {
+            return Err(invalid);
+        }
+    }
+    Ok(())
+}
+
+/// The offset of the metric argument in a serialized index, which follows
+/// the header fields parsed by `read_index_header`, for metrics other than
+/// L2 and inner product.
+const METRIC_ARG_OFFSET: usize = 37;
+
+/// Check that `buf` holds a serialized flat index with a metric argument,
+/// whose layout is exactly the one patched by `index_factory_lp` and read
+/// by `metric_arg`: the header, the metric argument, then the vectors as a
+/// length-prefixed array of `ntotal * d` floats.
+fn check_flat_lp_layout(buf: &[u8]) -> Result<()> {
+    let unsupported = Error::Unsupported("metric argument of a non-flat index layout");
+    let header_len = METRIC_ARG_OFFSET + 12;
+    if !buf.starts_with(b"IxFl") || buf.len() < header_len {
+        return Err(unsupported);
+    }
+    let d = i32::from_le_bytes(buf[4..8].try_into().unwrap());
+    let ntotal = i64::from_le_bytes(buf[8..16].try_into().unwrap());
+    let metric_type = i32::from_le_bytes(buf[33..37].try_into().unwrap());
+    let len = u64::from_le_bytes(buf[header_len - 8..header_len].try_into().unwrap());
+    // inner product and L2, coded as 0 and 1, have no metric argument
+    if d < 0 || ntotal < 0 || metric_type <= MetricType::L2 as i32 {
+        return Err(unsupported);
+    }
+    let expected_len = (d as u64).checked_mul(ntotal as u64);
+    let data_len = (buf.len() - header_len) as u64;
+    if expected_len != Some(len) || len.checked_mul(4) != Some(data_len) {
+        return Err(unsupported);
+    }
+    Ok(())
+}
+
+/// Use the index factory to create an index with the `Lp` metric for the
+/// given `p`. The native C API cannot set the metric argument of an index,
+/// so the index is created with `index_factory`, then serialized and read
+/// back with the metric argument replaced.
+///
+/// Only a flat index has its metric argument in a single known place,
+/// right after the header, so the description must produce a plain flat
+/// index, such as `"Flat"`. The serialized bytes are checked to have
+/// exactly this layout before being patched.
+///
+/// # Error
+///
+/// This function returns `Error::Unsupported` if the description does not
+/// produce a flat index with the expected layout, or another error if the
+/// index could not be created, or if it could not be serialized and read
+/// back.
+pub fn index_factory_lp<D>(d: u32, description: D, p: f32) -> Result<IndexImpl>
+where
+    D: AsRef<str>,
+{
+    let index = index_factory(d, description, MetricType::Lp)?;
+    let mut buf = Vec::new();
+    write_index_to_writer(&index, &mut buf)?;
+    check_flat_lp_layout(&buf)?;
+    buf[METRIC_ARG_OFFSET..METRIC_ARG_OFFSET + 4].copy_from_slice(&p.to_le_bytes());
+    read_index_from_reader(&buf[..])
+}
+
+/// Obtain the metric argument of a flat index, such as `p` for the `Lp`
+/// metric, by serializing it. Indexes with the L2 or inner product metrics
+/// do not store an argument, and yield 0 without being serialized.
+///
+/// For other metrics, the whole index is serialized into memory to read
+/// these 4 bytes, which takes time and memory proportional to the size of
+/// the index. Avoid calling this on large indexes, or on a hot path.
+///
+/// # Error
+///
+/// This function returns `Error::Unsupported` if the index is not a flat
+/// index with the expected layout, or another error if the index could not
+/// be serialized.
+pub fn metric_arg<I>(index: &I) -> Result<f32>
+where
+    I: NativeIndex,
+    I: CpuIndex,
+{
+    match index.metric_type() {
+        MetricType::L2 | MetricType::InnerProduct => return Ok(0.),
+        _ => {}
+    }
+    let mut buf = Vec::new();
+    write_index_to_writer(index, &mut buf)?;
+    check_flat_lp_layout(&buf)?;
+    Ok(f32::from_le_bytes(
+        buf[METRIC_ARG_OFFSET..METRIC_ARG_OFFSET + 4]
+            .try_into()
+            .unwrap(),
+    ))
+}
+
+/// Write all vectors of an index to a file as raw data, for use by other
+/// tools. The file holds the number of vectors and their dimensionality as
+/// two little-endian `u64` values, followed by the vectors as contiguous
+/// little-endian `f32` values. With NumPy, the vectors can be read with
+/// `np.fromfile(path, dtype="<f4", offset=16).reshape(n, d)`.
+///
+/// # Error
+///
+/// This function returns an error if the vectors could not be reconstructed,
+/// or `Error::Io` if the file could not be written.
+pub fn dump_vectors<I, P>(index: &I, file_name: P) -> Result<()>
+where
+    I: ?Sized + Index,
+    P: AsRef<str>,
+{
+    let n = index.ntotal();
+    let vectors = if n > 0 {
+        index.reconstruct_n(Idx::new(0), n as usize)?
+    } else {
+        Vec::new()
+    };
+    let mut buf = Vec::with_capacity(16 + vectors.len() * 4);
+    buf.extend_from_slice(&n.to_le_bytes());
+    buf.extend_from_slice(&u64::from(index.d()).to_le_bytes());
+    for v in vectors {
+        buf.extend_from_slice(&v.to_le_bytes());
+    }
+    std::fs::write(file_name.as_ref(), buf).map_err(|e| Error::Io(e.kind()))
+}
+
+/// Read vectors from a file written by [`dump_vectors`], returning their
+/// dimensionality and their values.
+///
+/// [`dump_vectors`]: fn.dump_vectors.html
+///
+/// # Error
+///
+/// This function returns `Error::Io` if the file could not be read, with
+/// `ErrorKind::InvalidData` if its length does not match its header.
+pub fn load_vectors<P>(file_name: P) -> Result<(u32, Vec<f32>)>
+where
+    P: AsRef<str>,
+{
+    let buf = std::fs::read(file_name.as_ref()).map_err(|e| Error::Io(e.kind()))?;
+    let invalid = Error::Io(ErrorKind::InvalidData);
+    if buf.len() < 16 {
+        return Err(invalid);
+    }
+    let n = u64::from_le_bytes(buf[0..8].try_into().unwrap());
+    let d = u64::from_le_bytes(buf[8..16].try_into().unwrap());
+    let d: u32 = d.try_into().map_err(|_| invalid.clone())?;
+    let len = n
+        .checked_mul(u64::from(d))
+        .and_then(|len| len.checked_mul(4))
+        .ok_or_else(|| invalid.clone())?;
+    if (buf.len() - 16) as u64 != len {
+        return Err(invalid);
+    }
+    let vectors = buf[16..]
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    Ok((d, vectors))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::index::flat::FlatIndex;
-    use crate::index::Index;
     const D: u32 = 8;
 
     #[test]
@@ -107,6 +551,171 @@ mod tests {
         ::std::fs::remove_file(&filepath).unwrap();
     }
 
+    #[test]
+    fn write_read_header() {
+        let mut index = FlatIndex::new_ip(D).unwrap();
+        index.add(&[1.; 3 * D as usize]).unwrap();
+
+        let filepath = ::std::env::temp_dir().join("test_write_read_header.index");
+        let filename = filepath.to_str().unwrap();
+        write_index(&index, filename).unwrap();
+        let header = read_index_header(filename).unwrap();
+        ::std::fs::remove_file(&filepath).unwrap();
+        assert_eq!(
+            header,
+            IndexHeader {
+                index_type: "IxFI".to_string(),
+                d: D,
+                ntotal: 3,
+                is_trained: true,
+                metric_type: MetricType::InnerProduct,
+            }
+        );
+
+        assert_eq!(
+            read_index_header("file_name"),
+            Err(Error::Io(ErrorKind::NotFound))
+        );
+    }
+
+    #[test]
+    fn verify_truncated_file() {
+        let mut index = FlatIndex::new_l2(D).unwrap();
+        index.add(&[1.; 4 * D as usize]).unwrap();
+
+        let filepath = ::std::env::temp_dir().join("test_verify_truncated_file.index");
+        let filename = filepath.to_str().unwrap();
+        write_index(&index, filename).unwrap();
+        verify_index_file(filename).unwrap();
+
+        let f = ::std::fs::OpenOptions::new()
+            .write(true)
+            .open(&filepath)
+            .unwrap();
+        let len = f.metadata().unwrap().len();
+        f.set_len(len - 10).unwrap();
+        let r = verify_index_file(filename);
+        assert_eq!(r, Err(Error::Io(ErrorKind::UnexpectedEof)));
+
+        f.set_len(20).unwrap();
+        let r = verify_index_file(filename);
+        ::std::fs::remove_file(&filepath).unwrap();
+        assert_eq!(r, Err(Error::Io(ErrorKind::UnexpectedEof)));
+    }
+
+    #[test]
+    fn write_read_stream() {
+        let mut index = FlatIndex::new_l2(D).unwrap();
+        let some_data = &[
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 1., 1., 1., -1.,
+        ];
+        index.add(some_data).unwrap();
+
+        let mut buf = ::std::io::Cursor::new(Vec::new());
+        write_index_to_writer(&index, &mut buf).unwrap();
+        assert!(buf.get_ref().starts_with(b"IxF2"));
+
+        buf.set_position(0);
+        let index = read_index_from_reader(&mut buf).unwrap();
+        assert_eq!(index.d(), D);
+        assert_eq!(index.ntotal(), 2);
+        assert_eq!(index.reconstruct_n(Idx::new(0), 2).unwrap(), some_data);
+
+        let truncated = ::std::io::Cursor::new(&b"IxF2"[..]);
+        assert!(read_index_from_reader(truncated).is_err());
+    }
+
+    #[test]
+    fn write_read_read_only() {
+        let mut index = FlatIndex::new_l2(D).unwrap();
+        let some_data = &[0.; 2 * D as usize];
+        index.add(some_data).unwrap();
+
+        let filepath = ::std::env::temp_dir().join("test_write_read_read_only.index");
+        let filename = filepath.to_str().unwrap();
+        write_index(&index, filename).unwrap();
+        let mut index = read_index_with_flags(filename, IoFlags::READ_ONLY).unwrap();
+        ::std::fs::remove_file(&filepath).unwrap();
+        assert_eq!(index.ntotal(), 2);
+        let result = index.search(&[0.; D as usize], 1).unwrap();
+        assert_eq!(result.distances, vec![0.]);
+    }
+
+    #[test]
+    fn write_read_mmap_warmup() {
+        let some_data: Vec<f32> = (0..3000 * D).map(|i| ((i * 31) % 101) as f32).collect();
+        let mut index = index_factory(D, "IVF16,Flat", MetricType::L2).unwrap();
+        index.train(&some_data).unwrap();
+        index.add(&some_data).unwrap();
+        let my_query = &some_data[17 * D as usize..19 * D as usize];
+        let expected = index.search(my_query, 4).unwrap();
+
+        let filepath = ::std::env::temp_dir().join("test_write_read_mmap_warmup.index");
+        let filename = filepath.to_str().unwrap();
+        write_index(&index, filename).unwrap();
+        let mut index = read_index_with_flags(filename, IoFlags::MMAP_IVF).unwrap();
+        index.warmup().unwrap();
+        assert_eq!(index.ntotal(), 3000);
+        assert_eq!(index.search(my_query, 4).unwrap(), expected);
+        drop(index);
+        ::std::fs::remove_file(&filepath).unwrap();
+    }
+
+    #[test]
+    fn dump_load_vectors() {
+        let mut index = FlatIndex::new_l2(D).unwrap();
+        let some_data: Vec<f32> = (0..3 * D).map(|i| i as f32 * 0.5).collect();
+        index.add(&some_data).unwrap();
+
+        let filepath = ::std::env::temp_dir().join("test_dump_load_vectors.f32");
+        let filename = filepath.to_str().unwrap();
+        dump_vectors(&index, filename).unwrap();
+        let bytes = ::std::fs::read(&filepath).unwrap();
+        assert_eq!(bytes.len(), 16 + some_data.len() * 4);
+        assert_eq!(&bytes[..8], &3_u64.to_le_bytes());
+        assert_eq!(&bytes[8..16], &u64::from(D).to_le_bytes());
+        assert_eq!(&bytes[16 + 4..16 + 8], &0.5_f32.to_le_bytes());
+
+        let (d, vectors) = load_vectors(filename).unwrap();
+        assert_eq!(d, D);
+        assert_eq!(vectors, some_data);
+
+        ::std::fs::write(&filepath, &bytes[..bytes.len() - 4]).unwrap();
+        assert_eq!(
+            load_vectors(filename),
+            Err(Error::Io(ErrorKind::InvalidData))
+        );
+        ::std::fs::remove_file(&filepath).unwrap();
+    }
+
+    #[test]
+    fn index_factory_lp3() {
+        let mut index = index_factory_lp(2, "Flat", 3.).unwrap();
+        assert_eq!(index.metric_type(), MetricType::Lp);
+        assert_eq!(metric_arg(&index).unwrap(), 3.);
+        let l2 = FlatIndex::new_l2(2).unwrap();
+        assert_eq!(metric_arg(&l2).unwrap(), 0.);
+
+        // (0, 2.7) is closer to the origin by L2, (2, 2) is closer by L3
+        index.add(&[2., 2., 0., 2.7]).unwrap();
+        let result = index.search(&[0., 0.], 2).unwrap();
+        assert_eq!(result.labels, vec![Idx::new(0), Idx::new(1)]);
+        assert!((result.distances[0] - 16.).abs() < 1e-4);
+        assert!((result.distances[1] - 2.7_f32.powi(3)).abs() < 1e-3);
+
+        assert_eq!(metric_arg(&index).unwrap(), 3.);
+
+        // nested indexes would keep a metric argument of 0
+        for description in &["IDMap,Flat", "IVF2,Flat"] {
+            assert!(matches!(
+                index_factory_lp(2, description, 3.),
+                Err(Error::Unsupported(_))
+            ));
+        }
+        let nested = index_factory(2, "IDMap,Flat", MetricType::L1).unwrap();
+        assert!(matches!(metric_arg(&nested), Err(Error::Unsupported(_))));
+    }
+
     #[test]
     fn test_read_with_flags() {
         let index = read_index_with_flags("file_name", IoFlags::MEM_MAP | IoFlags::READ_ONLY);
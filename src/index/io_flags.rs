@@ -4,15 +4,30 @@
 ///
 /// Note that not all flags are applicable to all index types.
 #[derive(Debug, Copy, Clone, Eq, Hash, PartialEq)]
-pub struct IoFlags(pub(crate) u16);
+pub struct IoFlags(pub(crate) i32);
 
 impl IoFlags {
     /// Load entire index into memory (default behavior)
     pub const MEM_RESIDENT: Self = IoFlags(0x00);
     /// Memory-map index
     pub const MEM_MAP: Self = IoFlags(0x01);
-    /// Index is read-only
+    /// Index is read-only. This applies to inverted lists stored on disk,
+    /// other index types can still be modified after reading.
     pub const READ_ONLY: Self = IoFlags(0x02);
+    /// Look for the files of on-disk inverted lists in the directory of the
+    /// index file
+    pub const ONDISK_SAME_DIR: Self = IoFlags(0x04);
+    /// Skip reading the contents of the inverted lists of IVF indexes
+    pub const SKIP_IVF_DATA: Self = IoFlags(0x08);
+    /// Memory-map the inverted lists of IVF indexes from the index file,
+    /// rather than reading them into memory. This is the `IO_FLAG_MMAP`
+    /// flag of Faiss 1.8, which includes `SKIP_IVF_DATA`.
+    pub const MMAP_IVF: Self = IoFlags(0x646f_0008);
+
+    /// Check whether all flags in `other` are set.
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
 }
 
 impl Default for IoFlags {
@@ -31,13 +46,13 @@ impl std::ops::BitOr for IoFlags {
 
 impl From<i32> for IoFlags {
     fn from(n: i32) -> IoFlags {
-        IoFlags(n as u16)
+        IoFlags(n)
     }
 }
 
 impl From<IoFlags> for i32 {
     fn from(io_flag: IoFlags) -> i32 {
-        io_flag.0 as i32
+        io_flag.0
     }
 }
 
@@ -52,6 +67,16 @@ mod tests {
         assert_eq!(IoFlags(0x03), mmap | ro);
     }
 
+    #[test]
+    fn contains_flags() {
+        let flags = IoFlags::READ_ONLY | IoFlags::SKIP_IVF_DATA;
+        assert!(flags.contains(IoFlags::READ_ONLY));
+        assert!(flags.contains(IoFlags::SKIP_IVF_DATA));
+        assert!(!flags.contains(IoFlags::ONDISK_SAME_DIR));
+        assert!(flags.contains(IoFlags::MEM_RESIDENT));
+        assert!(IoFlags::MMAP_IVF.contains(IoFlags::SKIP_IVF_DATA));
+    }
+
     #[test]
     fn can_coerce_to_i32() {
         let mmap = IoFlags::MEM_MAP;
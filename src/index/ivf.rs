@@ -0,0 +1,1236 @@
+//! Interface to the operations shared by inverted file (IVF) index types.
+
+use super::autotune::ParameterSpace;
+use super::flat::FlatIndexImpl;
+use super::ivf_flat::TrainType;
+use super::*;
+use crate::cluster::{Clustering, ClusteringParameters};
+use std::convert::TryFrom;
+use std::marker::PhantomData;
+
+/// The kind of map from vector IDs to their location in the inverted lists.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DirectMapType {
+    /// No direct map is kept, so vectors cannot be reconstructed by ID.
+    NoMap,
+    /// An array indexed by ID, which requires the IDs to be sequential.
+    Array,
+    /// A hash table, suitable for sparse IDs.
+    Hashtable,
+}
+
+/// A read-only view of the inverted lists of an IVF index.
+///
+/// The native C API does not provide custom inverted list backends, so the
+/// storage of an index cannot be replaced through this view.
+#[derive(Debug, Clone, Copy)]
+pub struct InvertedLists<'a> {
+    ivf: *mut FaissIndexIVF,
+    phantom: PhantomData<&'a ()>,
+}
+
+impl InvertedLists<'_> {
+    /// Get the number of inverted lists.
+    pub fn nlist(&self) -> usize {
+        unsafe { faiss_IndexIVF_nlist(self.ivf) }
+    }
+
+    /// Get the number of vectors in the inverted list `list_no`, or `None`
+    /// if there is no such list.
+    pub fn list_size(&self, list_no: usize) -> Option<usize> {
+        if list_no < self.nlist() {
+            Some(unsafe { faiss_IndexIVF_get_list_size(self.ivf, list_no) })
+        } else {
+            None
+        }
+    }
+
+    /// Get the IDs of the vectors in the inverted list `list_no`, or `None`
+    /// if there is no such list.
+    pub fn list_ids(&self, list_no: usize) -> Option<Vec<Idx>> {
+        let size = self.list_size(list_no)?;
+        let mut ids = vec![Idx::none(); size];
+        unsafe {
+            faiss_IndexIVF_invlists_get_ids(self.ivf, list_no, ids.as_mut_ptr() as *mut idx_t);
+        }
+        Some(ids)
+    }
+
+    /// Get the IDs of the vectors in the inverted list `list_no`, which is
+    /// expected to exist, such as when walking all lists of the index.
+    pub(crate) fn checked_list_ids(&self, list_no: usize) -> Result<Vec<Idx>> {
+        self.list_ids(list_no)
+            .ok_or(Error::Unsupported("reading an inverted list out of range"))
+    }
+
+    /// Get the imbalance factor of the inverted lists, which is 1 if they are
+    /// perfectly balanced and greater otherwise.
+    pub fn imbalance_factor(&self) -> f64 {
+        unsafe { faiss_IndexIVF_imbalance_factor(self.ivf) }
+    }
+}
+
+/// Obtain a pointer to the IVF portion of a native index.
+fn ivf_ptr<I>(index: &I) -> *mut FaissIndexIVF
+where
+    I: ?Sized + NativeIndex,
+{
+    unsafe { faiss_IndexIVF_cast(index.inner_ptr()) }
+}
+
+/// Read the IDs and codes of every inverted list of an IVF index once, as
+/// in `NativeIndex::warmup`. The codes are scanned by searching with as many
+/// queries as needed for every list to be visited by exactly one.
+pub(crate) unsafe fn warmup_invlists(ivf: *mut FaissIndexIVF) -> Result<()> {
+    let invlists = InvertedLists {
+        ivf,
+        phantom: PhantomData,
+    };
+    let nlist = invlists.nlist();
+    for list_no in 0..nlist {
+        invlists.checked_list_ids(list_no)?;
+    }
+    if faiss_Index_ntotal(ivf as *mut FaissIndex) == 0 {
+        return Ok(());
+    }
+
+    let d = faiss_Index_d(ivf as *mut FaissIndex) as usize;
+    let nprobe = faiss_IndexIVF_nprobe(ivf).min(nlist).max(1);
+    let nq = nlist.div_ceil(nprobe);
+    let len = checked_result_len(nq, nprobe)?;
+    let assign: Vec<idx_t> = (0..len)
+        .map(|i| if i < nlist { i as idx_t } else { -1 })
+        .collect();
+    let coarse_dis = vec![0_f32; len];
+    let query = vec![0_f32; checked_result_len(nq, d)?];
+    let mut distances = vec![0_f32; nq];
+    let mut labels = vec![Idx::none(); nq];
+    faiss_try(faiss_IndexIVF_search_preassigned(
+        ivf,
+        checked_idx_t(nq)?,
+        query.as_ptr(),
+        1,
+        assign.as_ptr(),
+        coarse_dis.as_ptr(),
+        distances.as_mut_ptr(),
+        labels.as_mut_ptr() as *mut _,
+        0,
+    ))?;
+    Ok(())
+}
+
+/// Derive the size of the codes stored in the inverted lists of an IVF
+/// index from its standalone code size, which also encodes the list number.
+unsafe fn invlists_code_size(ivf: *mut FaissIndexIVF) -> Result<usize> {
+    let nlist = faiss_IndexIVF_nlist(ivf);
+    // the number of bytes needed to encode a list number up to nlist - 1
+    let mut coarse_code_size = 0;
+    let mut nl = nlist.saturating_sub(1);
+    while nl > 0 {
+        coarse_code_size += 1;
+        nl >>= 8;
+    }
+    let mut sa_code_size = 0;
+    faiss_try(faiss_Index_sa_code_size(
+        ivf as *mut FaissIndex,
+        &mut sa_code_size,
+    ))?;
+    sa_code_size
+        .checked_sub(coarse_code_size)
+        .ok_or(Error::Unsupported("code size without the list number"))
+}
+
+/// Fail unless the IVF index owns its coarse quantizer. A borrowed quantizer
+/// may be shared with other indexes, whose inverted lists would no longer
+/// match its centroids if it were modified through this one.
+pub(crate) fn check_owns_quantizer(ivf: *mut FaissIndexIVF) -> Result<()> {
+    if unsafe { faiss_IndexIVF_own_fields(ivf) } == 0 {
+        return Err(Error::Unsupported(
+            "modifying a quantizer not owned by the index",
+        ));
+    }
+    Ok(())
+}
+
+/// Check whether two IVF indexes have the same quantizer, or quantizers
+/// holding the same centroids.
+fn same_quantizer(a: *mut FaissIndexIVF, b: *mut FaissIndexIVF) -> Result<bool> {
+    unsafe {
+        let qa = faiss_IndexIVF_quantizer(a);
+        let qb = faiss_IndexIVF_quantizer(b);
+        if qa == qb {
+            return Ok(true);
+        }
+        if faiss_Index_d(qa) != faiss_Index_d(qb)
+            || faiss_Index_ntotal(qa) != faiss_Index_ntotal(qb)
+        {
+            return Ok(false);
+        }
+        Ok(quantizer_centroids(qa)? == quantizer_centroids(qb)?)
+    }
+}
+
+/// Reconstruct all vectors held by a coarse quantizer, which are the
+/// centroids of the inverted lists.
+unsafe fn quantizer_centroids(quantizer: *mut FaissIndex) -> Result<Vec<f32>> {
+    let d = faiss_Index_d(quantizer);
+    let ntotal = faiss_Index_ntotal(quantizer);
+    let mut centroids = vec![0_f32; checked_result_len(ntotal as usize, d as usize)?];
+    if ntotal > 0 {
+        faiss_try(faiss_Index_reconstruct_n(
+            quantizer,
+            0,
+            ntotal,
+            centroids.as_mut_ptr(),
+        ))?;
+    }
+    Ok(centroids)
+}
+
+/// Trait for native index types based on an inverted file (IVF).
+///
+/// All methods here operate on the IVF structure shared by these index
+/// types, regardless of how the vectors in each inverted list are encoded.
+pub trait IVFIndex: NativeIndex {
+    /// Move the vectors with IDs selected by `selector` into a new index of
+    /// the same type. The new index shares the training state of this one,
+    /// while this index retains the remaining vectors. The encoded vectors
+    /// are moved as they are, so no reconstruction is needed.
+    ///
+    /// The new index starts as a full clone of this one, from which the
+    /// vectors not selected are then removed, so memory for a second copy
+    /// of the index is needed in the meantime.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Unsupported` if an inverted list could not be read.
+    /// May result in a native error if the index could not be cloned, or if
+    /// the vectors could not be removed. This index is only modified by the
+    /// last step, so it is left untouched on any earlier error.
+    fn split_off(&mut self, selector: &IdSelector) -> Result<Self>
+    where
+        Self: TryClone + Sized,
+    {
+        let mut other = self.try_clone()?;
+        let invlists = self.invlists();
+        let mut rest = Vec::new();
+        for l in 0..invlists.nlist() {
+            let ids = invlists.checked_list_ids(l)?;
+            rest.extend(ids.into_iter().filter(|&id| !selector.is_member(id)));
+        }
+        other.remove_ids(&IdSelector::batch(&rest)?)?;
+        self.remove_ids(selector)?;
+        Ok(other)
+    }
+
+    /// Move all vectors of `other` into this index, adding `add_id` to their
+    /// IDs. The other index is left empty. For sequential IDs, `add_id`
+    /// would be the number of vectors in this index before the merge.
+    ///
+    /// Both indexes must use the same coarse quantization for the results to
+    /// be meaningful, such as when built from a shared quantizer.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::IncompatibleMerge` if the indexes cannot be merged, as
+    /// described in `can_merge_from`, or `Error::Overflow` if `add_id` does
+    /// not fit in the native ID type.
+    fn merge_from<I>(&mut self, other: &mut I, add_id: u64) -> Result<()>
+    where
+        I: ?Sized + IVFIndex,
+    {
+        self.can_merge_from(other)?;
+        let add_id = idx_t::try_from(add_id).map_err(|_| Error::Overflow)?;
+        unsafe {
+            faiss_try(faiss_IndexIVF_merge_from(
+                ivf_ptr(self),
+                ivf_ptr(other),
+                add_id,
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Add new data vectors to the index as in `add`, encoding them in
+    /// parallel. The data is split into consecutive parts, one per worker
+    /// thread, each of which is added in chunks of up to `chunk_rows`
+    /// vectors to an empty clone of this trained index. The clones are then
+    /// merged in order into the first one, which is finally merged into this
+    /// index, so that the vectors receive the same sequential IDs as with
+    /// `add`.
+    ///
+    /// The number of workers is the number of chunks, capped by
+    /// `std::thread::available_parallelism`, so at most that many clones
+    /// exist at once. Native indexes may already parallelize `add`
+    /// internally, so this is mostly worthwhile for bulk loads into indexes
+    /// with costly encoders.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::BadDimension` if the data does not match the
+    /// dimensionality of the index, or `Error::WorkerPanicked` if a worker
+    /// thread panicked. May also result in a native error if the index could
+    /// not be cloned, or if the vectors could not be encoded or merged. Only
+    /// the final merge modifies this index, so an error on any earlier step
+    /// leaves it unchanged.
+    fn par_add(&mut self, x: &[f32], chunk_rows: usize) -> Result<()>
+    where
+        Self: TryClone + Send + Sized,
+    {
+        let d = self.d() as usize;
+        if d == 0 || !x.chunks_exact(d).remainder().is_empty() {
+            return Err(Error::BadDimension);
+        }
+        let chunk_rows = chunk_rows.max(1);
+        let chunks = (x.len() / d).div_ceil(chunk_rows);
+        let workers = std::thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(chunks);
+        if workers <= 1 {
+            return self.add(x);
+        }
+        let chunk_len = checked_result_len(chunk_rows, d)?;
+        let part_len = checked_result_len(chunks.div_ceil(workers), chunk_len)?;
+        let mut shards = x
+            .chunks(part_len)
+            .map(|part| Ok((self.clone_empty_trained()?, part)))
+            .collect::<Result<Vec<_>>>()?;
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = shards
+                .iter_mut()
+                .map(|(shard, part)| {
+                    scope.spawn(move || {
+                        part.chunks(chunk_len)
+                            .try_for_each(|chunk| shard.add(chunk))
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().map_err(|_| Error::WorkerPanicked))
+                .collect::<Result<Vec<_>>>()?
+                .into_iter()
+                .collect::<Result<()>>()
+        })?;
+        let mut shards = shards.into_iter().map(|(shard, _)| shard);
+        let mut merged = shards.next().expect("at least two parts");
+        for mut shard in shards {
+            let add_id = merged.ntotal();
+            merged.merge_from(&mut shard, add_id)?;
+        }
+        let add_id = self.ntotal();
+        self.merge_from(&mut merged, add_id)
+    }
+
+    /// Move all inverted lists of `other` into this index, adding `add_id`
+    /// to the IDs of its vectors, after checking that both indexes use the
+    /// same coarse quantizer. This is the case for indexes sharing a
+    /// quantizer, or whose quantizers hold the same centroids, so that the
+    /// encoded vectors are moved directly between matching lists.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::IncompatibleMerge` if the indexes cannot be merged, as
+    /// described in `can_merge_from`, or if their quantizers differ.
+    fn merge_ivf_from<I>(&mut self, other: &mut I, add_id: u64) -> Result<()>
+    where
+        I: ?Sized + IVFIndex,
+    {
+        self.can_merge_from(other)?;
+        if !same_quantizer(ivf_ptr(self), ivf_ptr(other))? {
+            return Err(Error::IncompatibleMerge("quantizers differ"));
+        }
+        self.merge_from(other, add_id)
+    }
+
+    /// Check whether the contents of `other` could be merged into this
+    /// index. Both must be IVF indexes with the same dimensionality, metric,
+    /// number of inverted lists and code size.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::IncompatibleMerge` describing the first mismatch
+    /// found, if any.
+    fn can_merge_from<I>(&self, other: &I) -> Result<()>
+    where
+        I: ?Sized + NativeIndex,
+    {
+        let other_ivf = ivf_ptr(other);
+        if other_ivf.is_null() {
+            return Err(Error::IncompatibleMerge("not an IVF index"));
+        }
+        if self.d() != other.d() {
+            return Err(Error::IncompatibleMerge("dimensionality differs"));
+        }
+        if self.metric_type() != other.metric_type() {
+            return Err(Error::IncompatibleMerge("metric type differs"));
+        }
+        unsafe {
+            if faiss_IndexIVF_nlist(ivf_ptr(self)) != faiss_IndexIVF_nlist(other_ivf) {
+                return Err(Error::IncompatibleMerge("number of inverted lists differs"));
+            }
+        }
+        if self.sa_code_size()? != other.sa_code_size()? {
+            return Err(Error::IncompatibleMerge("code size differs"));
+        }
+        Ok(())
+    }
+
+    /// Train only the coarse quantizer of the index with the given data,
+    /// replacing any existing centroids. The encoder of the inverted lists
+    /// is not trained, so the index itself may still not be marked as
+    /// trained afterwards. A subsequent call to `train` will reuse these
+    /// centroids, as long as the quantizer holds exactly `nlist` of them.
+    ///
+    /// The training follows the native procedure of `train` for each
+    /// `TrainType`. A quantizer which trains alone is trained on the data.
+    /// Otherwise, k-means runs on the data, with the quantizer or a flat L2
+    /// index as the assigner. In the latter case, as for an HNSW quantizer,
+    /// the quantizer is also trained on the centroids if it is not trained
+    /// yet, before they are added to it. The k-means parameters cannot be
+    /// read from the index through the C API, so the native defaults of an
+    /// IVF index are used: 10 iterations, and spherical k-means for the inner
+    /// product. The centroids may thus differ from those of `train`, if the
+    /// index was given other clustering parameters natively.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Unsupported` if the index does not own its quantizer,
+    /// such as an index created with a shared quantizer, or if the quantizer
+    /// uses a metric type unknown to this crate.
+    fn train_quantizer(&mut self, x: &[f32]) -> Result<()> {
+        check_owns_quantizer(ivf_ptr(self))?;
+        unsafe {
+            let ivf = ivf_ptr(self);
+            let quantizer = faiss_IndexIVF_quantizer(ivf);
+            let d = faiss_Index_d(quantizer) as u32;
+            let nlist = faiss_IndexIVF_nlist(ivf) as u32;
+            let metric = MetricType::from_code(faiss_Index_metric_type(quantizer) as u32)
+                .ok_or(Error::Unsupported("unknown metric type of the quantizer"))?;
+            match TrainType::from_code(faiss_IndexIVF_quantizer_trains_alone(ivf)) {
+                Some(TrainType::QuantizerTrainsAlone) => {
+                    let n = checked_idx_t(x.len() / d as usize)?;
+                    faiss_try(faiss_Index_train(quantizer, n, x.as_ptr()))?;
+                }
+                train_type => {
+                    // run k-means on a flat index, as done by the native
+                    // IVF training procedure
+                    let mut params = ClusteringParameters::new();
+                    params.set_niter(10);
+                    params.set_spherical(metric == MetricType::InnerProduct);
+                    let mut clustering = Clustering::new_with_params(d, nlist, &params)?;
+                    let mut assigner = match train_type {
+                        Some(TrainType::FlatIndexAndQuantizer) => FlatIndexImpl::new_l2(d)?,
+                        _ => FlatIndexImpl::new(d, metric)?,
+                    };
+                    clustering.train(x, &mut assigner)?;
+                    let centroids = clustering.centroids()?.concat();
+                    if train_type == Some(TrainType::FlatIndexAndQuantizer)
+                        && faiss_Index_is_trained(quantizer) == 0
+                    {
+                        faiss_try(faiss_Index_train(
+                            quantizer,
+                            nlist as idx_t,
+                            centroids.as_ptr(),
+                        ))?;
+                    }
+                    faiss_try(faiss_Index_reset(quantizer))?;
+                    faiss_try(faiss_Index_add(
+                        quantizer,
+                        nlist as idx_t,
+                        centroids.as_ptr(),
+                    ))?;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Train only the encoder of the inverted lists with the given data,
+    /// such as the scalar quantizer of an IVF scalar quantizer index, given
+    /// the inverted list each vector is assigned to, as by
+    /// `quantizer_assign` with `k = 1`. Indexes of vectors stored as is have
+    /// no encoder to train.
+    ///
+    /// As with `train_quantizer`, this does not mark the index as trained,
+    /// which the native C API only does through `train`. The trained
+    /// encoder is kept by the index and written along with it, but adding
+    /// or searching still requires a call to `train`, which trains the
+    /// encoder again.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::BadDimension` if the data does not match the
+    /// dimensionality of the index, or if there is not one assignment per
+    /// vector.
+    fn train_encoder(&mut self, x: &[f32], assign: &[Idx]) -> Result<()> {
+        let d = self.d() as usize;
+        if d == 0 || !x.chunks_exact(d).remainder().is_empty() || x.len() / d != assign.len() {
+            return Err(Error::BadDimension);
+        }
+        unsafe {
+            faiss_try(faiss_IndexIVF_train_encoder(
+                ivf_ptr(self),
+                checked_idx_t(assign.len())?,
+                x.as_ptr(),
+                assign.as_ptr() as *const _,
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Set the maximum number of codes scanned per query, so as to bound the
+    /// work of a search regardless of the number of probes. The search
+    /// stops visiting inverted lists once this many codes were scanned, so
+    /// fewer than `k` results may be returned. Use `usize::MAX` to remove
+    /// the limit.
+    fn set_max_codes(&mut self, max_codes: usize) -> Result<()> {
+        let value = if max_codes == usize::MAX {
+            f64::INFINITY
+        } else {
+            max_codes as f64
+        };
+        // not exposed directly by the C API,
+        // but supported by the native parameter space
+        ParameterSpace::new()?.set_index_parameter(self, "max_codes", value)
+    }
+
+    /// The size of the code of each vector stored in the inverted lists, in
+    /// bytes. This is the standalone code size without the bytes encoding
+    /// the list number.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Unsupported` if the standalone code size of the index
+    /// does not include the bytes encoding the list number.
+    fn code_size(&self) -> Result<usize> {
+        unsafe { invlists_code_size(ivf_ptr(self)) }
+    }
+
+    /// The total size of the codes stored in the inverted lists, in bytes,
+    /// which is `ntotal` times the code size. Vector IDs are stored
+    /// alongside them, taking another 8 bytes per vector.
+    fn total_codes_bytes(&self) -> Result<usize> {
+        let code_size = self.code_size()?;
+        checked_result_len(self.ntotal() as usize, code_size)
+    }
+
+    /// Obtain a read-only view of the inverted lists of this index.
+    fn invlists(&self) -> InvertedLists<'_> {
+        InvertedLists {
+            ivf: ivf_ptr(self),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Assign each of the given vectors to its nearest inverted list and
+    /// count how many vectors land in each list. Returns a vector of `nlist`
+    /// counts, which reveals any imbalance in the coarse clustering.
+    fn assignment_histogram(&self, x: &[f32]) -> Result<Vec<u64>> {
+        let nlist = unsafe { faiss_IndexIVF_nlist(ivf_ptr(self)) };
+        let mut histogram = vec![0_u64; nlist];
+        for label in self.quantizer_assign(x, 1)?.labels {
+            if let Some(list) = label.get() {
+                histogram[list as usize] += 1;
+            }
+        }
+        Ok(histogram)
+    }
+
+    /// Set the kind of direct map kept by this index, building it from the
+    /// current contents of the inverted lists. A direct map is required to
+    /// reconstruct vectors by ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Unsupported` for `DirectMapType::Hashtable`, which
+    /// the native C API does not provide. May also result in a native error
+    /// if the map could not be built, such as when building an array map
+    /// over non-sequential IDs.
+    fn set_direct_map_type(&mut self, t: DirectMapType) -> Result<()> {
+        let maintain = match t {
+            DirectMapType::NoMap => 0,
+            DirectMapType::Array => 1,
+            DirectMapType::Hashtable => {
+                return Err(Error::Unsupported("hash table direct map"));
+            }
+        };
+        unsafe {
+            faiss_try(faiss_IndexIVF_make_direct_map(ivf_ptr(self), maintain))?;
+        }
+        Ok(())
+    }
+
+    /// Rebuild an array direct map from the current contents of the inverted
+    /// lists, discarding any previous map. Native indexes reject removals
+    /// while an array map is kept, so vectors should be removed after
+    /// setting `DirectMapType::NoMap`, then the map brought back with this
+    /// method for reconstruction to work again.
+    ///
+    /// # Errors
+    ///
+    /// May result in a native error if the map could not be built, such as
+    /// when the remaining IDs are not sequential from 0.
+    fn update_direct_map(&mut self) -> Result<()> {
+        unsafe {
+            faiss_try(faiss_IndexIVF_make_direct_map(ivf_ptr(self), 0))?;
+            faiss_try(faiss_IndexIVF_make_direct_map(ivf_ptr(self), 1))?;
+        }
+        Ok(())
+    }
+
+    /// Assign the given vectors to the `k` nearest inverted lists, using only
+    /// the coarse quantizer of the index. The resulting labels are inverted
+    /// list numbers rather than vector IDs.
+    fn quantizer_assign(&self, x: &[f32], k: usize) -> Result<AssignSearchResult> {
+        unsafe {
+            let quantizer = faiss_IndexIVF_quantizer(ivf_ptr(self));
+            let nq = x.len() / self.d() as usize;
+            let mut out_labels = vec![Idx::none(); checked_result_len(nq, k)?];
+            faiss_try(faiss_Index_assign(
+                quantizer,
+                checked_idx_t(nq)?,
+                x.as_ptr(),
+                out_labels.as_mut_ptr() as *mut _,
+                checked_idx_t(k)?,
+            ))?;
+            Ok(AssignSearchResult { labels: out_labels })
+        }
+    }
+
+    /// Search the coarse quantizer of the index for the `nprobe` inverted
+    /// lists nearest to each query, which are the lists that a search with
+    /// that many probes would visit. The resulting labels are inverted list
+    /// numbers, along with the distances to their centroids.
+    fn coarse_search(&self, query: &[f32], nprobe: usize) -> Result<SearchResult> {
+        unsafe {
+            let quantizer = faiss_IndexIVF_quantizer(ivf_ptr(self));
+            let nq = query.len() / self.d() as usize;
+            let len = checked_result_len(nq, nprobe)?;
+            let mut distances = vec![0_f32; len];
+            let mut labels = vec![Idx::none(); len];
+            faiss_try(faiss_Index_search(
+                quantizer,
+                checked_idx_t(nq)?,
+                query.as_ptr(),
+                checked_idx_t(nprobe)?,
+                distances.as_mut_ptr(),
+                labels.as_mut_ptr() as *mut _,
+            ))?;
+            Ok(SearchResult { distances, labels })
+        }
+    }
+
+    /// Perform a search for the `k` closest vectors to the given queries,
+    /// with the lists to visit already assigned, such as by `coarse_search`.
+    /// `assign` holds the inverted list numbers to visit for each query, and
+    /// `coarse_dis` the distances from each query to their centroids. Each
+    /// query must be assigned as many lists as the number of probes of the
+    /// index (bounded by the number of lists).
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::BadDimension` if the queries do not match the
+    /// dimensionality of the index, or if the lengths of `assign` and
+    /// `coarse_dis` do not match the number of queries and probes.
+    fn search_preassigned(
+        &self,
+        query: &[f32],
+        k: usize,
+        assign: &[Idx],
+        coarse_dis: &[f32],
+    ) -> Result<SearchResult> {
+        let d = self.d() as usize;
+        if d == 0 || !query.chunks_exact(d).remainder().is_empty() {
+            return Err(Error::BadDimension);
+        }
+        let nq = query.len() / d;
+        unsafe {
+            let ivf = ivf_ptr(self);
+            let nprobe = faiss_IndexIVF_nprobe(ivf).min(faiss_IndexIVF_nlist(ivf));
+            let assigned_len = checked_result_len(nq, nprobe)?;
+            if assign.len() != assigned_len || coarse_dis.len() != assigned_len {
+                return Err(Error::BadDimension);
+            }
+            let len = checked_result_len(nq, k)?;
+            let mut distances = vec![0_f32; len];
+            let mut labels = vec![Idx::none(); len];
+            faiss_try(faiss_IndexIVF_search_preassigned(
+                ivf,
+                checked_idx_t(nq)?,
+                query.as_ptr(),
+                checked_idx_t(k)?,
+                assign.as_ptr() as *const _,
+                coarse_dis.as_ptr(),
+                distances.as_mut_ptr(),
+                labels.as_mut_ptr() as *mut _,
+                0,
+            ))?;
+            Ok(SearchResult { distances, labels })
+        }
+    }
+
+    /// Obtain the centroid of the inverted list nearest to each of the given
+    /// query vectors, by assigning them with the coarse quantizer and
+    /// reconstructing the assigned centroids. Returns `d` values per query.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::BadDimension` if the queries do not match the
+    /// dimensionality of the index, or `Error::BadQuantizer` if a query
+    /// could not be assigned to any list.
+    fn nearest_centroid(&self, query: &[f32]) -> Result<Vec<f32>> {
+        let d = self.d() as usize;
+        if d == 0 || !query.chunks_exact(d).remainder().is_empty() {
+            return Err(Error::BadDimension);
+        }
+        let assigned = self.quantizer_assign(query, 1)?;
+        let mut centroids = vec![0_f32; query.len()];
+        unsafe {
+            let quantizer = faiss_IndexIVF_quantizer(ivf_ptr(self));
+            for (list_no, out) in assigned.labels.iter().zip(centroids.chunks_exact_mut(d)) {
+                let list_no = list_no.get().ok_or(Error::BadQuantizer)?;
+                faiss_try(faiss_Index_reconstruct(
+                    quantizer,
+                    list_no as idx_t,
+                    out.as_mut_ptr(),
+                ))?;
+            }
+        }
+        Ok(centroids)
+    }
+
+    /// Compute the average distance from the given vectors to the centroids
+    /// of their nearest inverted lists, in the units of the index metric
+    /// (squared distances for L2). A high distortion on representative data
+    /// suggests that more inverted lists are needed. Returns 0 for an
+    /// empty set of vectors.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::BadDimension` if the vectors do not match the
+    /// dimensionality of the index, or `Error::BadQuantizer` if a vector
+    /// could not be assigned to any list.
+    fn quantization_error(&self, x: &[f32]) -> Result<f32> {
+        let d = self.d() as usize;
+        if d == 0 || !x.chunks_exact(d).remainder().is_empty() {
+            return Err(Error::BadDimension);
+        }
+        if x.is_empty() {
+            return Ok(0.);
+        }
+        let result = self.coarse_search(x, 1)?;
+        if result.labels.iter().any(|l| l.is_none()) {
+            return Err(Error::BadQuantizer);
+        }
+        let total: f64 = result.distances.iter().map(|&d| f64::from(d)).sum();
+        Ok((total / result.distances.len() as f64) as f32)
+    }
+
+    /// Obtain the coarse centroids of the index, one per inverted list, by
+    /// reconstructing them from the quantizer. The result holds `nlist * d`
+    /// values, and is empty if the quantizer has not been trained yet.
+    fn centroids(&self) -> Result<Vec<f32>> {
+        unsafe { quantizer_centroids(faiss_IndexIVF_quantizer(ivf_ptr(self))) }
+    }
+}
+
+/// Suggest a number of inverted lists for an IVF index which is expected to
+/// hold `ntotal` vectors, following the common heuristic of `4 * sqrt(ntotal)`.
+/// This is only a starting point for tuning `nlist` when creating the index,
+/// and it is never less than 1.
+pub fn recommended_nlist(ntotal: u64) -> usize {
+    ((4. * (ntotal as f64).sqrt()).round() as usize).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{invlists_code_size, recommended_nlist, DirectMapType, IVFIndex};
+    use crate::error::Error;
+    use crate::index::flat::FlatIndexImpl;
+    use crate::index::ivf_flat::IVFFlatIndexImpl;
+    use crate::index::ivf_flat::TrainType;
+    use crate::index::{index_factory, Idx, Index, TryClone};
+    use crate::selector::IdSelector;
+    use crate::MetricType;
+    use faiss_sys::faiss_IndexIVF_cast;
+
+    const D: u32 = 8;
+
+    #[test]
+    fn ivf_flat_split_off() {
+        let q = FlatIndexImpl::new_l2(D).unwrap();
+        let mut index = IVFFlatIndexImpl::new_l2(q, D, 1).unwrap();
+        let some_data = &[
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 1., 1., 1., -1., 0., 0.,
+            0., 1., 1., 0., 0., -1., 100., 100., 100., 100., -100., 100., 100., 100., 120., 100.,
+            100., 105., -100., 100., 100., 105.,
+        ];
+        index.train(some_data).unwrap();
+        index.add(some_data).unwrap();
+        assert_eq!(index.ntotal(), 5);
+
+        let selector = IdSelector::range(Idx::new(1), Idx::new(3)).unwrap();
+        let mut other = index.split_off(&selector).unwrap();
+        assert_eq!(index.ntotal(), 3);
+        assert_eq!(other.ntotal(), 2);
+        assert!(other.is_trained());
+
+        let my_query = [0.; D as usize];
+        let result = index.search(&my_query, 5).unwrap();
+        assert_eq!(
+            result.labels,
+            vec![
+                Idx::new(0),
+                Idx::new(3),
+                Idx::new(4),
+                Idx::none(),
+                Idx::none()
+            ]
+        );
+        let result = other.search(&my_query, 5).unwrap();
+        assert_eq!(
+            result.labels,
+            vec![
+                Idx::new(2),
+                Idx::new(1),
+                Idx::none(),
+                Idx::none(),
+                Idx::none()
+            ]
+        );
+
+        let selector = IdSelector::batch(&[Idx::new(0), Idx::new(4)]).unwrap();
+        let mut last = index.split_off(&selector).unwrap();
+        assert_eq!(index.ntotal(), 1);
+        assert_eq!(last.ntotal(), 2);
+        assert_eq!(
+            index.search(&my_query, 1).unwrap().labels,
+            vec![Idx::new(3)]
+        );
+        assert_eq!(
+            last.search(&my_query, 2).unwrap().labels,
+            vec![Idx::new(0), Idx::new(4)]
+        );
+    }
+
+    #[test]
+    fn ivf_flat_train_quantizer() {
+        let q = FlatIndexImpl::new_l2(D).unwrap();
+        let mut index = IVFFlatIndexImpl::new_l2(q, D, 2).unwrap();
+        let some_data = &[
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 1., 1., 1., -1., 0., 0.,
+            0., 1., 1., 0., 0., -1., 100., 100., 100., 100., -100., 100., 100., 100., 120., 100.,
+            100., 105., -100., 100., 100., 105.,
+        ];
+        index.train_quantizer(some_data).unwrap();
+        assert!(!index.is_trained());
+
+        let my_query = [
+            0., 0., 0., 0., 0., 0., 0., 0., 100., 100., 100., 100., 100., 100., 100., 100.,
+        ];
+        let result = index.quantizer_assign(&my_query, 1).unwrap();
+        assert_eq!(result.labels.len(), 2);
+        assert!(result.labels.iter().all(|l| l.is_some()));
+        assert_ne!(result.labels[0], result.labels[1]);
+
+        // the full training keeps the same coarse centroids
+        index.train(some_data).unwrap();
+        assert!(index.is_trained());
+        let result2 = index.quantizer_assign(&my_query, 1).unwrap();
+        assert_eq!(result.labels, result2.labels);
+    }
+
+    #[test]
+    fn ivf_hnsw_train_quantizer() {
+        // an HNSW quantizer is filled with the centroids of a flat k-means
+        let mut index = index_factory(D, "IVF2_HNSW8,Flat", MetricType::L2)
+            .unwrap()
+            .into_ivf_flat()
+            .unwrap();
+        assert_eq!(index.train_type(), Some(TrainType::FlatIndexAndQuantizer));
+        let some_data: Vec<f32> = (0..64 * D)
+            .map(|i| (i / (32 * D) * 100 + (i * 7) % 5) as f32)
+            .collect();
+        index.train_quantizer(&some_data).unwrap();
+        assert!(!index.is_trained());
+        let centroids = index.centroids().unwrap();
+        assert_eq!(centroids.len(), 2 * D as usize);
+
+        let my_query = [[0.; D as usize], [100.; D as usize]].concat();
+        let result = index.quantizer_assign(&my_query, 1).unwrap();
+        assert!(result.labels.iter().all(|l| l.is_some()));
+        assert_ne!(result.labels[0], result.labels[1]);
+
+        index.train(&some_data).unwrap();
+        assert!(index.is_trained());
+        assert_eq!(index.centroids().unwrap(), centroids);
+    }
+
+    #[test]
+    fn ivf_flat_can_merge_from() {
+        let index = IVFFlatIndexImpl::new_l2(FlatIndexImpl::new_l2(D).unwrap(), D, 2).unwrap();
+        let other = IVFFlatIndexImpl::new_l2(FlatIndexImpl::new_l2(D).unwrap(), D, 2).unwrap();
+        assert_eq!(index.can_merge_from(&other), Ok(()));
+
+        let flat = FlatIndexImpl::new_l2(D).unwrap();
+        assert_eq!(
+            index.can_merge_from(&flat),
+            Err(Error::IncompatibleMerge("not an IVF index"))
+        );
+
+        let other = IVFFlatIndexImpl::new_l2(FlatIndexImpl::new_l2(D).unwrap(), D, 4).unwrap();
+        assert_eq!(
+            index.can_merge_from(&other),
+            Err(Error::IncompatibleMerge("number of inverted lists differs"))
+        );
+
+        let other = IVFFlatIndexImpl::new_ip(FlatIndexImpl::new_ip(D).unwrap(), D, 2).unwrap();
+        assert_eq!(
+            index.can_merge_from(&other),
+            Err(Error::IncompatibleMerge("metric type differs"))
+        );
+    }
+
+    #[test]
+    fn ivf_flat_direct_map() {
+        let q = FlatIndexImpl::new_l2(D).unwrap();
+        let mut index = IVFFlatIndexImpl::new_l2(q, D, 1).unwrap();
+        let some_data = &[
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 1., 1., 1., -1., 0., 0.,
+            0., 1., 1., 0., 0., -1., 100., 100., 100., 100., -100., 100., 100., 100., 120., 100.,
+            100., 105., -100., 100., 100., 105.,
+        ];
+        index.train(some_data).unwrap();
+        index.add(some_data).unwrap();
+        assert!(index.reconstruct(Idx::new(3)).is_err());
+
+        index.set_direct_map_type(DirectMapType::Array).unwrap();
+        assert_eq!(index.reconstruct(Idx::new(3)).unwrap(), &some_data[24..32]);
+
+        assert_eq!(
+            index.set_direct_map_type(DirectMapType::Hashtable),
+            Err(Error::Unsupported("hash table direct map"))
+        );
+
+        index.set_direct_map_type(DirectMapType::NoMap).unwrap();
+        assert!(index.reconstruct(Idx::new(3)).is_err());
+    }
+
+    #[test]
+    fn ivf_flat_update_direct_map() {
+        let q = FlatIndexImpl::new_l2(D).unwrap();
+        let mut index = IVFFlatIndexImpl::new_l2(q, D, 1).unwrap();
+        let some_data: Vec<f32> = (0..5 * D).map(|i| i as f32).collect();
+        index.train(&some_data).unwrap();
+        index.add(&some_data).unwrap();
+        index.set_direct_map_type(DirectMapType::Array).unwrap();
+
+        let last = IdSelector::range(Idx::new(4), Idx::new(5)).unwrap();
+        assert!(index.remove_ids(&last).is_err());
+        index.set_direct_map_type(DirectMapType::NoMap).unwrap();
+        assert_eq!(index.remove_ids(&last).unwrap(), 1);
+        index.update_direct_map().unwrap();
+
+        for (i, expected) in some_data.chunks_exact(D as usize).take(4).enumerate() {
+            assert_eq!(index.reconstruct(Idx::new(i as u64)).unwrap(), expected);
+        }
+        assert!(index.reconstruct(Idx::new(4)).is_err());
+    }
+
+    #[test]
+    fn ivf_flat_assignment_histogram() {
+        let q = FlatIndexImpl::new_l2(D).unwrap();
+        let mut index = IVFFlatIndexImpl::new_l2(q, D, 4).unwrap();
+        // two well separated clusters
+        let data: Vec<f32> = (0..64)
+            .flat_map(|i| {
+                let center = if i % 2 == 0 { 0. } else { 100. };
+                (0..D).map(move |j| center + ((i * 7 + j) % 5) as f32 * 0.1)
+            })
+            .collect();
+        index.train(&data).unwrap();
+
+        let histogram = index.assignment_histogram(&data).unwrap();
+        assert_eq!(histogram.len(), 4);
+        assert_eq!(histogram.iter().sum::<u64>(), 64);
+    }
+
+    #[test]
+    fn ivf_flat_invlists() {
+        let mut quantizer = FlatIndexImpl::new_l2(2).unwrap();
+        quantizer.add(&[0., 0., 10., 0.]).unwrap();
+        let mut index = IVFFlatIndexImpl::new_l2(quantizer, 2, 2).unwrap();
+        let some_data = &[1., 0., 9., 0., 2., 0.];
+        index.train(some_data).unwrap();
+        index.add(some_data).unwrap();
+
+        let invlists = index.invlists();
+        assert_eq!(invlists.nlist(), 2);
+        assert_eq!(invlists.list_size(0), Some(2));
+        assert_eq!(invlists.list_size(1), Some(1));
+        assert_eq!(invlists.list_size(2), None);
+        assert_eq!(invlists.list_ids(0), Some(vec![Idx::new(0), Idx::new(2)]));
+        assert_eq!(invlists.list_ids(1), Some(vec![Idx::new(1)]));
+        assert!(invlists.imbalance_factor() > 1.);
+    }
+
+    #[test]
+    fn ivf_flat_max_codes() {
+        let mut quantizer = FlatIndexImpl::new_l2(2).unwrap();
+        quantizer.add(&[0., 0., 10., 0.]).unwrap();
+        let mut index = IVFFlatIndexImpl::new_l2(quantizer, 2, 2).unwrap();
+        // 5.5 belongs to the second list, closer to 10 than to 0
+        let some_data = &[1., 0., 2., 0., 3., 0., 9., 0., 8., 0., 5.5, 0.];
+        index.train(some_data).unwrap();
+        index.add(some_data).unwrap();
+        index.set_nprobe(2);
+
+        let my_query = [0., 0.];
+        let result = index.search(&my_query, 5).unwrap();
+        assert!(result.labels.iter().all(|l| l.is_some()));
+        let near_border = [4.9, 0.];
+        let result = index.search(&near_border, 1).unwrap();
+        assert_eq!(result.labels, vec![Idx::new(5)]);
+
+        // the first probed list is scanned in full, then the search stops
+        index.set_max_codes(1).unwrap();
+        let result = index.search(&my_query, 5).unwrap();
+        assert_eq!(
+            result.labels,
+            vec![
+                Idx::new(0),
+                Idx::new(1),
+                Idx::new(2),
+                Idx::none(),
+                Idx::none()
+            ]
+        );
+        assert_eq!(result.distances[..3], [1., 4., 9.]);
+
+        // the true nearest neighbor is in the second list, which is skipped,
+        // so the best vector of the first list is returned instead
+        let result = index.search(&near_border, 1).unwrap();
+        assert_eq!(result.labels, vec![Idx::new(2)]);
+        assert!((result.distances[0] - 1.9 * 1.9).abs() < 1e-5);
+
+        index.set_max_codes(usize::MAX).unwrap();
+        let result = index.search(&my_query, 5).unwrap();
+        assert!(result.labels.iter().all(|l| l.is_some()));
+        let result = index.search(&near_border, 1).unwrap();
+        assert_eq!(result.labels, vec![Idx::new(5)]);
+    }
+
+    #[test]
+    fn recommended_nlist_sqrt() {
+        assert_eq!(recommended_nlist(0), 1);
+        assert_eq!(recommended_nlist(10_000), 400);
+        assert_eq!(recommended_nlist(1_000_000), 4000);
+        // quadrupling the number of vectors doubles the recommendation
+        assert_eq!(recommended_nlist(40_000), 2 * recommended_nlist(10_000));
+    }
+
+    #[test]
+    fn ivf_sq_merge_ivf_from() {
+        use crate::index::scalar_quantizer::{IVFScalarQuantizerIndexImpl, QuantizerType};
+        use crate::MetricType;
+
+        let mut quantizer = FlatIndexImpl::new_l2(2).unwrap();
+        quantizer.add(&[0., 0., 10., 0.]).unwrap();
+        let new_shard = || {
+            IVFScalarQuantizerIndexImpl::new_shared(
+                &quantizer,
+                2,
+                QuantizerType::QT_fp16,
+                2,
+                MetricType::L2,
+            )
+            .unwrap()
+        };
+        let mut shard1 = new_shard();
+        let mut shard2 = new_shard();
+        shard1.add(&[1., 0., 9., 0., 2., 0.]).unwrap();
+        shard2.add(&[8., 0., 7., 0.]).unwrap();
+        shard1.merge_ivf_from(&mut shard2, 3).unwrap();
+        assert_eq!(shard1.ntotal(), 5);
+        assert_eq!(shard2.ntotal(), 0);
+        let invlists = shard1.invlists();
+        assert_eq!(invlists.list_size(0), Some(2));
+        assert_eq!(invlists.list_size(1), Some(3));
+        assert_eq!(
+            invlists.list_ids(1),
+            Some(vec![Idx::new(1), Idx::new(3), Idx::new(4)])
+        );
+
+        // a quantizer with other centroids is rejected
+        let mut other_quantizer = FlatIndexImpl::new_l2(2).unwrap();
+        other_quantizer.add(&[0., 0., 0., 10.]).unwrap();
+        let mut other = IVFScalarQuantizerIndexImpl::new_shared(
+            &other_quantizer,
+            2,
+            QuantizerType::QT_fp16,
+            2,
+            MetricType::L2,
+        )
+        .unwrap();
+        other.add(&[0., 9.]).unwrap();
+        assert_eq!(
+            shard1.merge_ivf_from(&mut other, 5),
+            Err(Error::IncompatibleMerge("quantizers differ"))
+        );
+        assert_eq!(other.ntotal(), 1);
+    }
+
+    #[test]
+    fn ivf_flat_coarse_search() {
+        let mut quantizer = FlatIndexImpl::new_l2(2).unwrap();
+        quantizer.add(&[0., 0., 10., 0., 20., 0.]).unwrap();
+        let mut index = IVFFlatIndexImpl::new_l2(quantizer, 2, 3).unwrap();
+        index.train(&[1., 0., 9., 0., 21., 0.]).unwrap();
+
+        let my_query = [8., 0.];
+        let assigned = index.quantizer_assign(&my_query, 1).unwrap();
+        let result = index.coarse_search(&my_query, 1).unwrap();
+        assert_eq!(result.labels, assigned.labels);
+        assert_eq!(result.labels, vec![Idx::new(1)]);
+
+        let result = index.coarse_search(&my_query, 2).unwrap();
+        assert_eq!(result.labels, vec![Idx::new(1), Idx::new(0)]);
+        assert_eq!(result.distances, vec![4., 64.]);
+    }
+
+    #[test]
+    fn ivf_flat_centroids() {
+        let quantizer = FlatIndexImpl::new_l2(D).unwrap();
+        let mut index = IVFFlatIndexImpl::new_l2(quantizer, D, 4).unwrap();
+        assert_eq!(index.centroids().unwrap(), Vec::<f32>::new());
+
+        let some_data: Vec<f32> = (0..64 * D).map(|i| (i % 37) as f32).collect();
+        index.train(&some_data).unwrap();
+        let centroids = index.centroids().unwrap();
+        assert_eq!(centroids.len(), 4 * D as usize);
+        assert!(centroids.iter().all(|c| c.is_finite()));
+    }
+
+    #[test]
+    fn ivf_flat_nearest_centroid() {
+        let mut quantizer = FlatIndexImpl::new_l2(2).unwrap();
+        let centroids = [[0., 0.], [10., 0.], [0., 10.]];
+        quantizer.add(&centroids.concat()).unwrap();
+        let mut index = IVFFlatIndexImpl::new_l2(quantizer, 2, 3).unwrap();
+        index.train(&centroids.concat()).unwrap();
+
+        let my_query = [7., 2., 1., 6.];
+        let nearest = index.nearest_centroid(&my_query).unwrap();
+        assert_eq!(nearest, vec![10., 0., 0., 10.]);
+        let dist =
+            |a: &[f32], b: &[f32]| -> f32 { a.iter().zip(b).map(|(x, y)| (x - y).powi(2)).sum() };
+        for (q, c) in my_query.chunks_exact(2).zip(nearest.chunks_exact(2)) {
+            assert!(centroids.iter().all(|other| dist(q, c) <= dist(q, other)));
+        }
+
+        assert_eq!(index.nearest_centroid(&[1.]), Err(Error::BadDimension));
+    }
+
+    #[test]
+    fn ivf_flat_search_preassigned() {
+        let mut quantizer = FlatIndexImpl::new_l2(2).unwrap();
+        quantizer.add(&[0., 0., 10., 0., 20., 0.]).unwrap();
+        let mut index = IVFFlatIndexImpl::new_l2(quantizer, 2, 3).unwrap();
+        let some_data = [1., 0., 9., 0., 12., 0., 21., 0., -1., 0.];
+        index.train(&some_data).unwrap();
+        index.add(&some_data).unwrap();
+        index.set_nprobe(2);
+
+        let my_query = [8., 0., 16., 0.];
+        let coarse = index.coarse_search(&my_query, 2).unwrap();
+        let result = index
+            .search_preassigned(&my_query, 3, &coarse.labels, &coarse.distances)
+            .unwrap();
+        assert_eq!(result, index.search(&my_query, 3).unwrap());
+
+        let r = index.search_preassigned(&my_query, 3, &coarse.labels[..2], &coarse.distances);
+        assert_eq!(r, Err(Error::BadDimension));
+    }
+
+    #[test]
+    fn ivf_flat_quantization_error() {
+        let mut quantizer = FlatIndexImpl::new_l2(2).unwrap();
+        quantizer.add(&[0., 0., 10., 10.]).unwrap();
+        let mut index = IVFFlatIndexImpl::new_l2(quantizer, 2, 2).unwrap();
+        index.train(&[0., 0., 10., 10.]).unwrap();
+
+        let clustered = [0.1, 0., 0., -0.1, 10., 10.1, 9.9, 10.];
+        let scattered = [5., 5., 3., -4., 15., 12., 6., 2.];
+        let low = index.quantization_error(&clustered).unwrap();
+        let high = index.quantization_error(&scattered).unwrap();
+        assert!((low - 0.01).abs() < 1e-5);
+        assert!(high > 10. * low);
+        assert_eq!(index.quantization_error(&[]).unwrap(), 0.);
+        assert_eq!(index.quantization_error(&[1.]), Err(Error::BadDimension));
+    }
+
+    #[test]
+    fn ivf_flat_par_add() {
+        let some_data: Vec<f32> = (0..100 * D).map(|i| ((i * 31) % 97) as f32).collect();
+        let my_query = &some_data[..2 * D as usize];
+
+        let q = FlatIndexImpl::new_l2(D).unwrap();
+        let mut serial = IVFFlatIndexImpl::new_l2(q, D, 4).unwrap();
+        serial.train(&some_data).unwrap();
+        let mut parallel = serial.try_clone().unwrap();
+        serial.add(&some_data[..10 * D as usize]).unwrap();
+        parallel.add(&some_data[..10 * D as usize]).unwrap();
+        serial.add(&some_data[10 * D as usize..]).unwrap();
+        parallel.par_add(&some_data[10 * D as usize..], 30).unwrap();
+
+        assert_eq!(parallel.ntotal(), serial.ntotal());
+        assert_eq!(
+            parallel.search(my_query, 5).unwrap(),
+            serial.search(my_query, 5).unwrap()
+        );
+        assert_eq!(
+            parallel.par_add(&some_data[1..], 30),
+            Err(Error::BadDimension)
+        );
+        assert_eq!(parallel.ntotal(), 100);
+    }
+
+    #[test]
+    fn ivf_flat_retrain_keeps_quantizer() {
+        let q = FlatIndexImpl::new_l2(D).unwrap();
+        let mut index = IVFFlatIndexImpl::new_l2(q, D, 4).unwrap();
+        let some_data: Vec<f32> = (0..256 * D).map(|i| ((i * 17) % 41) as f32).collect();
+        index.train(&some_data[..128 * D as usize]).unwrap();
+        let centroids = index.centroids().unwrap();
+
+        index.train(&some_data[128 * D as usize..]).unwrap();
+        assert!(index.is_trained());
+        assert_eq!(index.centroids().unwrap(), centroids);
+    }
+
+    #[test]
+    fn ivf_flat_code_size() {
+        let q = FlatIndexImpl::new_l2(D).unwrap();
+        let mut index = IVFFlatIndexImpl::new_l2(q, D, 300).unwrap();
+        assert_eq!(index.code_size().unwrap(), 4 * D as usize);
+        assert_eq!(index.total_codes_bytes().unwrap(), 0);
+
+        let some_data: Vec<f32> = (0..300 * D).map(|i| ((i * 7) % 101) as f32).collect();
+        index.train(&some_data).unwrap();
+        index.add(&some_data[..10 * D as usize]).unwrap();
+        assert_eq!(index.total_codes_bytes().unwrap(), 10 * 4 * D as usize);
+        index.add(&some_data[..10 * D as usize]).unwrap();
+        assert_eq!(index.total_codes_bytes().unwrap(), 20 * 4 * D as usize);
+    }
+
+    #[test]
+    fn ivf_pq_code_size() {
+        // one byte per sub-quantizer, after 1 or 2 bytes of list number
+        for &(description, m) in &[("IVF4,PQ4", 4), ("IVF300,PQ2", 2)] {
+            let index = index_factory(D, description, MetricType::L2).unwrap();
+            let code_size = unsafe { invlists_code_size(faiss_IndexIVF_cast(index.inner_ptr())) };
+            assert_eq!(code_size.unwrap(), m, "{}", description);
+        }
+    }
+}
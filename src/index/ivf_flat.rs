@@ -1,5 +1,6 @@
 //! Interface and implementation to IVFFlat index type.
 
+use super::ivf::{DirectMapType, IVFIndex};
 use super::*;
 
 use std::os::raw::{c_char, c_int};
@@ -50,6 +51,9 @@ impl IVFFlatIndexImpl {
     }
 
     /// Create a new IVF flat index.
+    ///
+    /// See [`recommended_nlist`](../ivf/fn.recommended_nlist.html) for a
+    /// suggested number of lists.
     // The index owns the quantizer.
     pub fn new(quantizer: flat::FlatIndex, d: u32, nlist: u32, metric: MetricType) -> Result<Self> {
         let index = IVFFlatIndexImpl::new_helper(&quantizer, d, nlist, metric, true)?;
@@ -82,6 +86,12 @@ impl IVFFlatIndexImpl {
         }
     }
 
+    /// Set number of probes at query time, returning the index for chaining
+    pub fn with_nprobe(mut self, value: u32) -> Self {
+        self.set_nprobe(value);
+        self
+    }
+
     /// Get number of possible key values
     pub fn nlist(&self) -> u32 {
         unsafe { faiss_IndexIVFFlat_nlist(self.inner_ptr()) as u32 }
@@ -94,6 +104,74 @@ impl IVFFlatIndexImpl {
             TrainType::from_code(code)
         }
     }
+
+    /// Renumber the inverted lists in order of decreasing size, so that
+    /// list 0 holds the most vectors. The native C API provides no such
+    /// utility, so the index is re-packed: the centroids are added back to
+    /// the coarse quantizer in the new order, and the reconstructed vectors
+    /// are added back with their IDs, list by list. Vectors keep their
+    /// relative order within each list, and searches visit the same vectors
+    /// as before, so results are unchanged as long as no two vectors or
+    /// centroids are tied.
+    ///
+    /// This requires reconstructing every vector by ID, so a direct map
+    /// must be kept (see `set_direct_map_type`). The index is left with an
+    /// array direct map. The quantizer must be a flat index. The re-packing
+    /// is done on a copy of the index, which replaces this one only once it
+    /// is complete, so memory for a second index is needed in the meantime.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Unsupported` if the index does not own its quantizer,
+    /// or if an inverted list could not be read. Returns
+    /// `Error::BadQuantizer` if the quantizer does not hold one centroid per
+    /// list. Returns the native error of `reconstruct` if the index has no
+    /// direct map, or a native error if the copy could not be rebuilt. In
+    /// all cases, the index is left untouched.
+    pub fn sort_invlists_by_size(&mut self) -> Result<()> {
+        let ivf = unsafe { faiss_IndexIVF_cast(self.inner_ptr()) };
+        ivf::check_owns_quantizer(ivf)?;
+        let d = self.d() as usize;
+        let invlists = self.invlists();
+        let mut order: Vec<usize> = (0..invlists.nlist()).collect();
+        order.sort_by_key(|&l| std::cmp::Reverse(invlists.list_size(l)));
+        if order.iter().enumerate().all(|(i, &l)| i == l) {
+            return Ok(());
+        }
+
+        let centroids = self.centroids()?;
+        if centroids.len() != checked_result_len(order.len(), d)? {
+            return Err(Error::BadQuantizer);
+        }
+        let mut sorted_centroids = Vec::with_capacity(centroids.len());
+        let mut ids = Vec::with_capacity(self.ntotal() as usize);
+        for &l in &order {
+            sorted_centroids.extend_from_slice(&centroids[l * d..(l + 1) * d]);
+            ids.extend(invlists.checked_list_ids(l)?);
+        }
+        let mut vectors = Vec::with_capacity(checked_result_len(ids.len(), d)?);
+        for &id in &ids {
+            vectors.extend(self.reconstruct(id)?);
+        }
+
+        let mut other = self.try_clone()?;
+        // array direct maps reject adding vectors with IDs
+        other.set_direct_map_type(DirectMapType::NoMap)?;
+        other.reset()?;
+        unsafe {
+            let quantizer = faiss_IndexIVF_quantizer(faiss_IndexIVF_cast(other.inner_ptr()));
+            faiss_try(faiss_Index_reset(quantizer))?;
+            faiss_try(faiss_Index_add(
+                quantizer,
+                checked_idx_t(order.len())?,
+                sorted_centroids.as_ptr(),
+            ))?;
+        }
+        other.add_with_ids(&vectors, &ids)?;
+        other.set_direct_map_type(DirectMapType::Array)?;
+        *self = other;
+        Ok(())
+    }
 }
 
 /**
@@ -149,6 +227,8 @@ impl TryClone for IVFFlatIndexImpl {
 
 impl_concurrent_index!(IVFFlatIndexImpl);
 
+impl ivf::IVFIndex for IVFFlatIndexImpl {}
+
 impl IndexImpl {
     /// Attempt a dynamic cast of an index to the IVF flat index type.
     pub fn into_ivf_flat(self) -> Result<IVFFlatIndexImpl> {
@@ -169,7 +249,8 @@ mod tests {
 
     use super::IVFFlatIndexImpl;
     use crate::index::flat::FlatIndexImpl;
-    use crate::index::{index_factory, ConcurrentIndex, Idx, Index, UpcastIndex};
+    use crate::index::ivf::{DirectMapType, IVFIndex};
+    use crate::index::{index_factory, ConcurrentIndex, Idx, Index, IndexExt, UpcastIndex};
     use crate::MetricType;
 
     const D: u32 = 8;
@@ -209,6 +290,22 @@ mod tests {
         assert_eq!(index.ntotal(), 0);
     }
 
+    #[test]
+    fn index_chained_configuration() {
+        let q = FlatIndexImpl::new_l2(D).unwrap();
+        let index = IVFFlatIndexImpl::new_l2(q, D, 4)
+            .unwrap()
+            .with_nprobe(3)
+            .with_verbose(true);
+        assert_eq!(index.nprobe(), 3);
+        assert!(index.verbose());
+
+        let mut index = index.with_verbose(false);
+        assert!(!index.verbose());
+        index.set_nprobe(2);
+        assert_eq!(index.nprobe(), 2);
+    }
+
     #[test]
     fn index_search_own() {
         let q = FlatIndexImpl::new_l2(D).unwrap();
@@ -300,4 +397,42 @@ mod tests {
         let index_impl = index.upcast();
         assert_eq!(index_impl.d(), D);
     }
+
+    #[test]
+    fn ivf_flat_sort_invlists_by_size() {
+        let centers = [0_f32, 50., 100.];
+        let some_data: Vec<f32> = (0..6 * D)
+            .map(|i| centers[0] + (i % 7) as f32 * 0.01 + (i / D) as f32 * 0.1)
+            .chain((0..D).map(|i| centers[1] + i as f32 * 0.01))
+            .chain((0..3 * D).map(|i| centers[2] + (i % 5) as f32 * 0.01 + (i / D) as f32 * 0.1))
+            .collect();
+        let training: Vec<f32> = centers.iter().flat_map(|&c| vec![c; D as usize]).collect();
+        let mut q = FlatIndexImpl::new_l2(D).unwrap();
+        q.add(&training).unwrap();
+        let mut index = IVFFlatIndexImpl::new_l2(q, D, 3).unwrap();
+        index.train(&training).unwrap();
+        index.add(&some_data).unwrap();
+        let sizes: Vec<_> = (0..3).map(|l| index.invlists().list_size(l)).collect();
+        assert!(index.sort_invlists_by_size().is_err());
+        assert_eq!(index.ntotal(), 10);
+        let unchanged: Vec<_> = (0..3).map(|l| index.invlists().list_size(l)).collect();
+        assert_eq!(unchanged, sizes);
+
+        index.set_direct_map_type(DirectMapType::Array).unwrap();
+        index.set_nprobe(3);
+        let my_query = [[0.; D as usize], [49.; D as usize], [101.; D as usize]].concat();
+        let before = index.search(&my_query, 4).unwrap();
+        index.sort_invlists_by_size().unwrap();
+
+        let mut sizes = sizes;
+        sizes.sort_by(|a, b| b.cmp(a));
+        let sorted: Vec<_> = (0..3).map(|l| index.invlists().list_size(l)).collect();
+        assert_eq!(sorted, sizes);
+        assert_eq!(index.ntotal(), 10);
+        assert_eq!(index.search(&my_query, 4).unwrap(), before);
+        assert_eq!(
+            index.reconstruct(Idx::new(6)).unwrap(),
+            &some_data[6 * D as usize..7 * D as usize]
+        );
+    }
 }
@@ -16,25 +16,38 @@ use crate::metric::MetricType;
 use crate::selector::IdSelector;
 use std::ffi::CString;
 use std::fmt::{self, Display, Formatter, Write};
+use std::io::{ErrorKind, Read};
+use std::ops::Range;
 use std::os::raw::c_uint;
+use std::time::{Duration, Instant};
 use std::{mem, ptr};
 
 use faiss_sys::*;
 
+pub mod any;
 pub mod autotune;
+pub mod binary;
+pub mod cosine;
 pub mod flat;
 pub mod id_map;
 pub mod io;
 pub mod io_flags;
+pub mod ivf;
 pub mod ivf_flat;
 pub mod lsh;
+pub mod norm_check;
 pub mod pretransform;
 pub mod refine_flat;
 pub mod scalar_quantizer;
+pub mod session;
+pub mod sync;
 
 #[cfg(feature = "gpu")]
 pub mod gpu;
 
+#[cfg(feature = "ndarray")]
+pub mod array;
+
 /// Primitive data type for identifying a vector in an index (or lack thereof).
 ///
 /// Depending on the kind of index, it may be possible for vectors to share the
@@ -162,19 +175,35 @@ pub trait Index {
     fn add_with_ids(&mut self, x: &[f32], xids: &[Idx]) -> Result<()>;
 
     /// Train the underlying index with the given data.
+    ///
+    /// Training an index which is already trained does not refine the
+    /// earlier training. Most index types are retrained from scratch on the
+    /// new data, while IVF indexes keep their trained coarse quantizer and
+    /// only retrain the encoding of the vectors.
     fn train(&mut self, x: &[f32]) -> Result<()>;
 
     /// Similar to `search`, but only provides the labels.
     fn assign(&mut self, q: &[f32], k: usize) -> Result<AssignSearchResult>;
 
     /// Perform a search for the `k` closest vectors to the given query vectors.
+    /// For the L2 metric, the reported distances are squared Euclidean
+    /// distances; see `SearchResult::as_euclidean`.
+    ///
+    /// The result always holds `k` entries per query. When fewer than `k`
+    /// vectors are found, such as when `k` exceeds `ntotal`, the remaining
+    /// entries are padded with empty labels, with an infinite distance in
+    /// the direction of the worst possible result. Searching with `k == 0`
+    /// returns an empty result without calling into the native index.
     fn search(&mut self, q: &[f32], k: usize) -> Result<SearchResult>;
 
     /// Perform a ranged search for the vectors closest to the given query vectors
     /// by the given radius.
     fn range_search(&mut self, q: &[f32], radius: f32) -> Result<RangeSearchResult>;
 
-    /// Clear the entire index.
+    /// Clear the entire index, removing all of its vectors. The outcome of
+    /// training is kept, such as the coarse centroids of IVF indexes and the
+    /// codebooks of quantizers, so a trained index remains trained and can
+    /// be added to right away.
     fn reset(&mut self) -> Result<()>;
 
     /// Remove data vectors represented by IDs.
@@ -185,6 +214,479 @@ pub trait Index {
 
     /// Set Index verbosity level
     fn set_verbose(&mut self, value: bool);
+
+    /// The size of the codes produced by the standalone codec, in bytes.
+    /// Not all index types may support this operation, and the default
+    /// implementation returns `Error::Unsupported`.
+    fn sa_code_size(&self) -> Result<usize> {
+        Err(Error::Unsupported("standalone codec"))
+    }
+
+    /// Encode the given vectors with the standalone codec of the index.
+    /// This assumes a C-contiguous memory slice of vectors, where the total
+    /// number of vectors is `x.len() / d`. Returns the codes as a contiguous
+    /// sequence of `n * sa_code_size()` bytes.
+    /// The default implementation returns `Error::Unsupported`.
+    fn sa_encode(&self, _x: &[f32]) -> Result<Vec<u8>> {
+        Err(Error::Unsupported("standalone codec"))
+    }
+
+    /// Decode a sequence of codes produced by `sa_encode` into the given
+    /// buffer, without allocating. The length of `codes` must be a multiple
+    /// of `sa_code_size()`, and `out` must have room for exactly `n * d`
+    /// values, where `n` is the number of codes.
+    /// The default implementation returns `Error::Unsupported`.
+    fn sa_decode_into(&self, _codes: &[u8], _out: &mut [f32]) -> Result<()> {
+        Err(Error::Unsupported("standalone codec"))
+    }
+
+    /// Reconstruct the stored vector with the given key, or an approximation
+    /// of it if the index uses lossy encoding.
+    /// Not all index types may support this operation, and the default
+    /// implementation returns `Error::Unsupported`.
+    fn reconstruct(&self, _key: Idx) -> Result<Vec<f32>> {
+        Err(Error::Unsupported("reconstruction"))
+    }
+
+    /// Reconstruct `count` stored vectors, starting with the key
+    /// `first_key`, into a new C-contiguous vector.
+    /// Not all index types may support this operation, and the default
+    /// implementation returns `Error::Unsupported`.
+    fn reconstruct_n(&self, _first_key: Idx, _count: usize) -> Result<Vec<f32>> {
+        Err(Error::Unsupported("reconstruction"))
+    }
+}
+
+/// Convenience methods available on all indexes, implemented on top of the
+/// methods of [`Index`]. This trait is implemented for every index type, and
+/// is not meant to be implemented by hand.
+///
+/// [`Index`]: trait.Index.html
+pub trait IndexExt: Index {
+    /// Set Index verbosity level, returning the index for chaining.
+    fn with_verbose(mut self, value: bool) -> Self
+    where
+        Self: Sized,
+    {
+        self.set_verbose(value);
+        self
+    }
+
+    /// Add new data vectors to the index with sequential IDs, starting at
+    /// `base` and increasing by one for each vector.
+    /// Not all index types may support this operation.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Overflow` if `base` is none or the IDs would not fit
+    /// in the native ID type.
+    fn add_with_base_id(&mut self, x: &[f32], base: Idx) -> Result<()> {
+        let n = x.len() / self.d() as usize;
+        let first = base.to_native();
+        let last = checked_idx_t(n)
+            .ok()
+            .and_then(|n| first.checked_add(n))
+            .filter(|_| first >= 0)
+            .ok_or(Error::Overflow)?;
+        let ids: Vec<Idx> = (first..last).map(Idx).collect();
+        self.add_with_ids(x, &ids)
+    }
+
+    /// Estimate the memory used by the vectors stored in the index, in bytes.
+    /// This is the number of vectors times the size of their standalone
+    /// codes, such as `4 * d` bytes for a flat index, or `d` bytes for an
+    /// 8-bit scalar quantizer. If the index does not report a code size,
+    /// uncompressed `f32` vectors are assumed.
+    ///
+    /// The estimate leaves out auxiliary structures, such as the centroids
+    /// and ID lists of inverted file indexes or the map of an `IdMap`.
+    fn memory_usage(&self) -> usize {
+        let code_size = self
+            .sa_code_size()
+            .unwrap_or(self.d() as usize * mem::size_of::<f32>());
+        (self.ntotal() as usize).saturating_mul(code_size)
+    }
+
+    /// Measure the recall at `k` of this index for the given queries, taking
+    /// the results of an exact search in `truth_index` as the ground truth.
+    /// Both indexes must hold the same vectors with the same IDs. See
+    /// [`recall_at_k`](../utils/fn.recall_at_k.html) for details.
+    fn evaluate_recall(
+        &mut self,
+        queries: &[f32],
+        k: usize,
+        truth_index: &mut flat::FlatIndex,
+    ) -> Result<f32> {
+        let approx = self.search(queries, k)?;
+        let truth = truth_index.search(queries, k)?;
+        crate::utils::recall_at_k(&approx, &truth, k)
+    }
+
+    /// Add new data vectors to the index, each multiplied by its scale
+    /// factor in `scales` beforehand. For inner product indexes, this
+    /// weights the scores of the stored vectors by their scale.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::BadDimension` if the length of `x` is not a multiple
+    /// of the dimensionality, or if there is not one scale per vector.
+    fn add_scaled(&mut self, x: &[f32], scales: &[f32]) -> Result<()> {
+        let d = self.d() as usize;
+        if d == 0 || !x.chunks_exact(d).remainder().is_empty() || x.len() / d != scales.len() {
+            return Err(Error::BadDimension);
+        }
+        let scaled: Vec<f32> = x
+            .chunks_exact(d)
+            .zip(scales)
+            .flat_map(|(row, &scale)| row.iter().map(move |v| v * scale))
+            .collect();
+        self.add(&scaled)
+    }
+
+    /// Add new data vectors to the index, as in `add`, returning the range
+    /// of sequential IDs assigned to them. These IDs start at the number of
+    /// vectors in the index before the call.
+    fn add_returning_ids(&mut self, x: &[f32]) -> Result<Range<Idx>> {
+        let start = Idx::new(self.ntotal());
+        self.add(x)?;
+        Ok(start..Idx::new(self.ntotal()))
+    }
+
+    /// Reconstruct the stored vectors with the given keys, which do not need
+    /// to be contiguous. Returns `ids.len() * d` values, in the order of
+    /// `ids`.
+    fn reconstruct_batch(&self, ids: &[Idx]) -> Result<Vec<f32>> {
+        let mut output = Vec::with_capacity(checked_result_len(ids.len(), self.d() as usize)?);
+        for &id in ids {
+            output.extend_from_slice(&self.reconstruct(id)?);
+        }
+        Ok(output)
+    }
+
+    /// Find the vector closest to a single query vector, returning its label
+    /// and distance, or `None` if the index has no vectors.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::BadDimension` if the query does not match the
+    /// dimensionality of the index.
+    fn nearest(&mut self, q: &[f32]) -> Result<Option<(Idx, f32)>> {
+        if q.len() != self.d() as usize {
+            return Err(Error::BadDimension);
+        }
+        let result = self.search(q, 1)?;
+        Ok(result
+            .labels
+            .first()
+            .zip(result.distances.first())
+            .filter(|(l, _)| l.is_some())
+            .map(|(&l, &d)| (l, d)))
+    }
+
+    /// Perform a search for the `k` closest vectors to the given query
+    /// vectors, as in `search`, also measuring the wall-clock time that the
+    /// search took.
+    fn search_timed(&mut self, q: &[f32], k: usize) -> Result<(SearchResult, Duration)> {
+        let start = Instant::now();
+        let result = self.search(q, k)?;
+        Ok((result, start.elapsed()))
+    }
+
+    /// Count the vectors within the given radius of each query vector,
+    /// returning one count per query.
+    fn range_count(&mut self, q: &[f32], radius: f32) -> Result<Vec<usize>> {
+        let result = self.range_search(q, radius)?;
+        Ok(result.lims().windows(2).map(|w| w[1] - w[0]).collect())
+    }
+
+    /// Perform a ranged search as in `range_search`, with the hits of each
+    /// query sorted from closest to farthest. This means ascending order of
+    /// distance for L2-like metrics and descending order for the inner
+    /// product.
+    fn range_search_sorted(&mut self, q: &[f32], radius: f32) -> Result<RangeSearchResult> {
+        let mut result = self.range_search(q, radius)?;
+        result.sort_by_distance(self.metric_type().is_similarity());
+        Ok(result)
+    }
+
+    /// Perform a search for the `k` closest vectors to the given query
+    /// vectors, discarding the neighbors farther than `max_distance`, which
+    /// is in the same units as the search distances (squared for L2).
+    /// Discarded entries are replaced with padding, as if the index did not
+    /// have enough vectors, with the same infinite distance as in `search`. For similarity metrics such as the inner product,
+    /// `max_distance` is instead the minimum similarity to keep.
+    fn search_bounded(&mut self, q: &[f32], k: usize, max_distance: f32) -> Result<SearchResult> {
+        let mut result = self.search(q, k)?;
+        let similarity = self.metric_type().is_similarity();
+        let padding = padding_distance(similarity);
+        for (d, l) in result.distances.iter_mut().zip(&mut result.labels) {
+            let beyond = if similarity {
+                *d < max_distance
+            } else {
+                *d > max_distance
+            };
+            if beyond {
+                *d = padding;
+                *l = Idx::none();
+            }
+        }
+        Ok(result)
+    }
+
+    /// Perform a search for the `k` closest vectors to the given query
+    /// vectors after subtracting `anchor` from each of them. This is meant
+    /// for indexes holding data which was centered on the same anchor before
+    /// being added.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::BadDimension` if the anchor or the queries do not
+    /// match the dimensionality of the index.
+    fn search_recentered(&mut self, q: &[f32], anchor: &[f32], k: usize) -> Result<SearchResult> {
+        let d = self.d() as usize;
+        if anchor.len() != d || d == 0 || !q.chunks_exact(d).remainder().is_empty() {
+            return Err(Error::BadDimension);
+        }
+        let centered: Vec<f32> = q
+            .iter()
+            .zip(anchor.iter().cycle())
+            .map(|(x, a)| x - a)
+            .collect();
+        self.search(&centered, k)
+    }
+
+    /// Perform a search for the `k` closest vectors to the given query
+    /// vectors, as in `search`, handling queries with fewer than `k`
+    /// neighbors according to `policy`. With `SearchPolicy::Truncate`, the
+    /// rows of the result keep the largest number of neighbors found for any
+    /// query, which is also the `k` to use when reading the result.
+    ///
+    /// # Errors
+    ///
+    /// With `SearchPolicy::Fail`, returns `Error::NotEnoughResults` if any
+    /// query has fewer than `k` neighbors.
+    fn search_with_policy(
+        &mut self,
+        q: &[f32],
+        k: usize,
+        policy: SearchPolicy,
+    ) -> Result<SearchResult> {
+        let result = self.search(q, k)?;
+        if policy == SearchPolicy::Pad || k == 0 {
+            return Ok(result);
+        }
+        let found = result
+            .labels
+            .chunks_exact(k)
+            .map(|row| row.iter().filter(|l| l.is_some()).count());
+        match policy {
+            SearchPolicy::Fail if found.clone().any(|n| n < k) => Err(Error::NotEnoughResults),
+            SearchPolicy::Truncate => {
+                let kept = found.max().unwrap_or(0);
+                let distances = result
+                    .distances
+                    .chunks_exact(k)
+                    .flat_map(|row| &row[..kept])
+                    .copied()
+                    .collect();
+                let labels = result
+                    .labels
+                    .chunks_exact(k)
+                    .flat_map(|row| &row[..kept])
+                    .copied()
+                    .collect();
+                Ok(SearchResult { distances, labels })
+            }
+            _ => Ok(result),
+        }
+    }
+
+    /// Decode a sequence of codes produced by `sa_encode`, returning the
+    /// reconstructed vectors in a new C-contiguous vector.
+    fn sa_decode(&self, codes: &[u8]) -> Result<Vec<f32>> {
+        let code_size = self.sa_code_size()?;
+        if code_size == 0 {
+            return Err(Error::BadCodeSize);
+        }
+        let n = codes.len() / code_size;
+        let mut out = vec![0_f32; checked_result_len(n, self.d() as usize)?];
+        self.sa_decode_into(codes, &mut out)?;
+        Ok(out)
+    }
+
+    /// Encode query vectors with the standalone codec of the index, as in
+    /// `sa_encode`, so that they can be sent in compressed form. The
+    /// receiver can search with the codes through `search_encoded`, which
+    /// decodes them first.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::BadDimension` if the queries do not match the
+    /// dimensionality of the index, or an error if the index has no
+    /// standalone codec.
+    fn encode_query(&self, q: &[f32]) -> Result<Vec<u8>> {
+        let d = self.d() as usize;
+        if d == 0 || !q.chunks_exact(d).remainder().is_empty() {
+            return Err(Error::BadDimension);
+        }
+        self.sa_encode(q)
+    }
+
+    /// Perform a search for the `k` closest vectors to the query vectors
+    /// encoded by `encode_query`. The queries are decoded before searching,
+    /// so the search is made with their approximate reconstructions.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::BadCodeSize` if the codes do not match the code size
+    /// of the index.
+    fn search_encoded(&mut self, codes: &[u8], k: usize) -> Result<SearchResult> {
+        let code_size = self.sa_code_size()?;
+        if code_size == 0 || !codes.chunks_exact(code_size).remainder().is_empty() {
+            return Err(Error::BadCodeSize);
+        }
+        let q = self.sa_decode(codes)?;
+        self.search(&q, k)
+    }
+
+    /// Add new data vectors to the index by reading them from `r`, in
+    /// batches of up to `batch_rows` vectors at a time. The data is expected
+    /// to be a contiguous sequence of little-endian `f32` values, `d` per
+    /// vector. Returns the total number of vectors added.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::BadDimension` if the index has zero dimensions or if
+    /// the data ends with an incomplete vector, or `Error::Io` if reading
+    /// fails. Vectors from the batches preceding such an error will have
+    /// been added to the index already.
+    fn add_from_reader<R>(&mut self, mut r: R, batch_rows: usize) -> Result<u64>
+    where
+        R: Read,
+        Self: Sized,
+    {
+        if self.d() == 0 {
+            return Err(Error::BadDimension);
+        }
+        let row_bytes = self.d() as usize * mem::size_of::<f32>();
+        let mut buf = vec![0_u8; checked_result_len(batch_rows.max(1), row_bytes)?];
+        let mut batch = Vec::with_capacity(buf.len() / mem::size_of::<f32>());
+        let mut total = 0;
+        loop {
+            let len = read_fully(&mut r, &mut buf)?;
+            if len / row_bytes * row_bytes != len {
+                return Err(Error::BadDimension);
+            }
+            if len > 0 {
+                batch.clear();
+                batch.extend(
+                    buf[..len]
+                        .chunks_exact(mem::size_of::<f32>())
+                        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+                );
+                self.add(&batch)?;
+                total += (len / row_bytes) as u64;
+            }
+            if len < buf.len() {
+                return Ok(total);
+            }
+        }
+    }
+
+    /// Add new data vectors to the index, given as fixed-size rows of `D`
+    /// elements, so that each vector is known to be complete at compile time.
+    ///
+    /// ```compile_fail
+    /// # use faiss::{FlatIndex, IndexExt};
+    /// # fn run() -> faiss::error::Result<()> {
+    /// let mut index = FlatIndex::new_l2(4)?;
+    /// let rows = [[0_f32; 4]; 2];
+    /// index.add_rows::<3>(&rows)?; // error: mismatched types
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::BadDimension` if `D` differs from the dimensionality
+    /// of the index.
+    fn add_rows<const D: usize>(&mut self, rows: &[[f32; D]]) -> Result<()>
+    where
+        Self: Sized,
+    {
+        if D != self.d() as usize {
+            return Err(Error::BadDimension);
+        }
+        // arrays are laid out contiguously, without padding
+        let x = unsafe { std::slice::from_raw_parts(rows.as_ptr() as *const f32, rows.len() * D) };
+        self.add(x)
+    }
+
+    /// Perform a search for the `k` closest vectors to a single query vector
+    /// of `D` elements.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::BadDimension` if `D` differs from the dimensionality
+    /// of the index.
+    fn search_row<const D: usize>(&mut self, q: &[f32; D], k: usize) -> Result<SearchResult>
+    where
+        Self: Sized,
+    {
+        if D != self.d() as usize {
+            return Err(Error::BadDimension);
+        }
+        self.search(q, k)
+    }
+
+    /// Add the data vectors produced by an iterator to the index. The vectors
+    /// are gathered and added in batches of up to 1024 vectors at a time.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `Error::BadDimension` if a vector does not have `d`
+    /// elements. In that case, vectors from the preceding batches will have
+    /// been added to the index already.
+    fn extend_from_vectors<I>(&mut self, iter: I) -> Result<()>
+    where
+        I: IntoIterator<Item = Vec<f32>>,
+        Self: Sized,
+    {
+        const BATCH_ROWS: usize = 1024;
+        let d = self.d() as usize;
+        let mut batch = Vec::with_capacity(BATCH_ROWS * d);
+        for v in iter {
+            if v.len() != d {
+                return Err(Error::BadDimension);
+            }
+            batch.extend_from_slice(&v);
+            if batch.len() == BATCH_ROWS * d {
+                self.add(&batch)?;
+                batch.clear();
+            }
+        }
+        if !batch.is_empty() {
+            self.add(&batch)?;
+        }
+        Ok(())
+    }
+}
+
+impl<I> IndexExt for I where I: ?Sized + Index {}
+
+/// Read from `r` until `buf` is full or the end of the stream is reached,
+/// returning the number of bytes read.
+fn read_fully<R: Read>(r: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match r.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(Error::Io(e.kind())),
+        }
+    }
+    Ok(len)
 }
 
 impl<I> Index for Box<I>
@@ -246,12 +748,204 @@ where
     fn set_verbose(&mut self, value: bool) {
         (**self).set_verbose(value)
     }
+
+    fn sa_code_size(&self) -> Result<usize> {
+        (**self).sa_code_size()
+    }
+
+    fn sa_encode(&self, x: &[f32]) -> Result<Vec<u8>> {
+        (**self).sa_encode(x)
+    }
+
+    fn sa_decode_into(&self, codes: &[u8], out: &mut [f32]) -> Result<()> {
+        (**self).sa_decode_into(codes, out)
+    }
+
+    fn reconstruct(&self, key: Idx) -> Result<Vec<f32>> {
+        (**self).reconstruct(key)
+    }
+
+    fn reconstruct_n(&self, first_key: Idx, count: usize) -> Result<Vec<f32>> {
+        (**self).reconstruct_n(first_key, count)
+    }
+}
+
+impl<I> Index for &mut I
+where
+    I: ?Sized + Index,
+{
+    fn is_trained(&self) -> bool {
+        (**self).is_trained()
+    }
+
+    fn ntotal(&self) -> u64 {
+        (**self).ntotal()
+    }
+
+    fn d(&self) -> u32 {
+        (**self).d()
+    }
+
+    fn metric_type(&self) -> MetricType {
+        (**self).metric_type()
+    }
+
+    fn add(&mut self, x: &[f32]) -> Result<()> {
+        (**self).add(x)
+    }
+
+    fn add_with_ids(&mut self, x: &[f32], xids: &[Idx]) -> Result<()> {
+        (**self).add_with_ids(x, xids)
+    }
+
+    fn train(&mut self, x: &[f32]) -> Result<()> {
+        (**self).train(x)
+    }
+
+    fn assign(&mut self, q: &[f32], k: usize) -> Result<AssignSearchResult> {
+        (**self).assign(q, k)
+    }
+
+    fn search(&mut self, q: &[f32], k: usize) -> Result<SearchResult> {
+        (**self).search(q, k)
+    }
+
+    fn range_search(&mut self, q: &[f32], radius: f32) -> Result<RangeSearchResult> {
+        (**self).range_search(q, radius)
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        (**self).reset()
+    }
+
+    fn remove_ids(&mut self, sel: &IdSelector) -> Result<usize> {
+        (**self).remove_ids(sel)
+    }
+
+    fn verbose(&self) -> bool {
+        (**self).verbose()
+    }
+
+    fn set_verbose(&mut self, value: bool) {
+        (**self).set_verbose(value)
+    }
+
+    fn sa_code_size(&self) -> Result<usize> {
+        (**self).sa_code_size()
+    }
+
+    fn sa_encode(&self, x: &[f32]) -> Result<Vec<u8>> {
+        (**self).sa_encode(x)
+    }
+
+    fn sa_decode_into(&self, codes: &[u8], out: &mut [f32]) -> Result<()> {
+        (**self).sa_decode_into(codes, out)
+    }
+
+    fn reconstruct(&self, key: Idx) -> Result<Vec<f32>> {
+        (**self).reconstruct(key)
+    }
+
+    fn reconstruct_n(&self, first_key: Idx, count: usize) -> Result<Vec<f32>> {
+        (**self).reconstruct_n(first_key, count)
+    }
 }
 
 /// Sub-trait for native implementations of a Faiss index.
 pub trait NativeIndex: Index {
     /// Retrieve a pointer to the native index object.
     fn inner_ptr(&self) -> *mut FaissIndex;
+
+    /// Check whether the native index type supports the given operation,
+    /// which is useful for indexes of unknown type, such as those returned
+    /// by `read_index`. Index types which are not recognized are reported
+    /// as supporting none of the capabilities.
+    fn supports(&self, cap: Capability) -> bool {
+        unsafe { native_supports(self.inner_ptr(), cap) }
+    }
+
+    /// Read all of the data stored in the index once, so that the pages of
+    /// an index read from a memory-mapped file are faulted in before
+    /// serving searches, rather than during the first ones.
+    ///
+    /// The inverted lists of IVF indexes are walked list by list: the IDs
+    /// of each list are read, and all codes are scanned by a search which
+    /// visits every list once. A limit on the number of codes scanned per
+    /// query (see `IVFIndex::set_max_codes`) also limits this scan. Other
+    /// index types read their vectors with `reconstruct_n`, in batches.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `reconstruct_n` for index types which are not
+    /// IVF indexes and cannot reconstruct their vectors.
+    fn warmup(&self) -> Result<()> {
+        unsafe {
+            let ivf = faiss_IndexIVF_cast(self.inner_ptr());
+            if !ivf.is_null() {
+                return ivf::warmup_invlists(ivf);
+            }
+        }
+        const BATCH_SIZE: u64 = 1024;
+        let ntotal = self.ntotal();
+        let mut first = 0;
+        while first < ntotal {
+            let count = BATCH_SIZE.min(ntotal - first);
+            self.reconstruct_n(Idx::new(first), count as usize)?;
+            first += count;
+        }
+        Ok(())
+    }
+}
+
+/// An operation which only some index types support.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// Adding vectors with custom IDs, through `add_with_ids`.
+    AddWithIds,
+    /// Removing vectors, through `remove_ids`.
+    RemoveIds,
+    /// Reconstructing stored vectors, through `reconstruct`. IVF indexes
+    /// only support it with a direct map, so they are not reported as
+    /// supporting it.
+    Reconstruct,
+    /// Searching by radius, through `range_search`.
+    RangeSearch,
+}
+
+unsafe fn native_supports(index: *mut FaissIndex, cap: Capability) -> bool {
+    use Capability::*;
+    let id_map2 = faiss_IndexIDMap2_cast(index);
+    if !id_map2.is_null() {
+        return match cap {
+            AddWithIds | RemoveIds | Reconstruct => true,
+            RangeSearch => native_supports(faiss_IndexIDMap2_sub_index(id_map2), cap),
+        };
+    }
+    let id_map = faiss_IndexIDMap_cast(index);
+    if !id_map.is_null() {
+        return match cap {
+            AddWithIds | RemoveIds => true,
+            Reconstruct => false,
+            RangeSearch => native_supports(faiss_IndexIDMap_sub_index(id_map), cap),
+        };
+    }
+    let pretransform = faiss_IndexPreTransform_cast(index);
+    if !pretransform.is_null() {
+        return native_supports(faiss_IndexPreTransform_index(pretransform), cap);
+    }
+    if !faiss_IndexIVF_cast(index).is_null() {
+        cap != Reconstruct
+    } else if !faiss_IndexFlat_cast(index).is_null()
+        || !faiss_IndexScalarQuantizer_cast(index).is_null()
+    {
+        cap != AddWithIds
+    } else if !faiss_IndexLSH_cast(index).is_null() {
+        cap == RemoveIds
+    } else if !faiss_IndexRefineFlat_cast(index).is_null() {
+        cap == Reconstruct
+    } else {
+        false
+    }
 }
 
 impl<NI: NativeIndex> NativeIndex for Box<NI> {
@@ -278,6 +972,12 @@ pub trait ConcurrentIndex: Index {
     /// Perform a ranged search for the vectors closest to the given query vectors
     /// by the given radius.
     fn range_search(&self, q: &[f32], radius: f32) -> Result<RangeSearchResult>;
+
+    /// Similar to `assign`, but also provides the distance to each assigned
+    /// vector. This is equivalent to a concurrent `search`.
+    fn assign_with_distances(&self, q: &[f32], k: usize) -> Result<SearchResult> {
+        ConcurrentIndex::search(self, q, k)
+    }
 }
 
 impl<CI: ConcurrentIndex> ConcurrentIndex for Box<CI> {
@@ -346,6 +1046,76 @@ pub trait TryClone {
     fn try_clone(&self) -> Result<Self>
     where
         Self: Sized;
+
+    /// Create a clone of this index without any of its vectors, which keeps
+    /// the outcome of training, such as the coarse quantizer of an IVF
+    /// index. This is the usual way to create empty shards consistent with
+    /// an existing index.
+    ///
+    /// # Errors
+    ///
+    /// May result in a native error if the index could not be cloned or
+    /// reset.
+    fn clone_empty_trained(&self) -> Result<Self>
+    where
+        Self: Index + Sized,
+    {
+        let mut other = self.try_clone()?;
+        other.reset()?;
+        Ok(other)
+    }
+}
+
+/// Check in debug builds that a native index pointer is not null, as may
+/// happen after misusing an index handle whose ownership was given away.
+/// Every index type in this crate holds a valid pointer for its lifetime.
+#[inline]
+pub(crate) fn live_ptr<T>(ptr: *mut T) -> *mut T {
+    debug_assert!(!ptr.is_null(), "use of an index with a null native pointer");
+    ptr
+}
+
+/// Convert a vector count (such as `n`, `nq` or `k`) to the native index
+/// type, failing with [`Error::Overflow`] if it does not fit.
+///
+/// [`Error::Overflow`]: ../error/enum.Error.html#variant.Overflow
+pub(crate) fn checked_idx_t(n: usize) -> Result<idx_t> {
+    if n > idx_t::MAX as usize {
+        return Err(Error::Overflow);
+    }
+    Ok(n as idx_t)
+}
+
+/// Calculate the number of entries in the output buffers of a search with
+/// `nq` queries and `k` results per query, failing with [`Error::Overflow`]
+/// if such buffers could not be allocated.
+///
+/// [`Error::Overflow`]: ../error/enum.Error.html#variant.Overflow
+pub(crate) fn checked_result_len(nq: usize, k: usize) -> Result<usize> {
+    nq.checked_mul(k)
+        .filter(|&len| len <= isize::MAX as usize / mem::size_of::<idx_t>())
+        .ok_or(Error::Overflow)
+}
+
+/// Validate the buffers of a standalone decoding operation, returning the
+/// number of codes to decode.
+pub(crate) fn checked_decode_len(
+    code_size: usize,
+    d: u32,
+    codes_len: usize,
+    out_len: usize,
+) -> Result<idx_t> {
+    if code_size == 0 {
+        return Err(Error::BadCodeSize);
+    }
+    let n = codes_len / code_size;
+    if n * code_size != codes_len {
+        return Err(Error::BadCodeSize);
+    }
+    if n.checked_mul(d as usize) != Some(out_len) {
+        return Err(Error::BadDimension);
+    }
+    checked_idx_t(n)
 }
 
 pub fn try_clone_from_inner_ptr<T>(val: &T) -> Result<T>
@@ -360,18 +1130,156 @@ where
 }
 
 /// The outcome of an index assign operation.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct AssignSearchResult {
     pub labels: Vec<Idx>,
 }
 
-/// The outcome of an index search operation.
-#[derive(Debug, Clone, PartialEq)]
-pub struct SearchResult {
-    pub distances: Vec<f32>,
+impl AssignSearchResult {
+    /// Obtain the number of valid assignments of each query, given that the
+    /// operation was made for `k` labels per query. Queries may receive
+    /// fewer than `k` assignments if the index holds fewer than `k` vectors.
+    pub fn valid_counts(&self, k: usize) -> Vec<usize> {
+        if k == 0 {
+            return Vec::new();
+        }
+        self.labels
+            .chunks(k)
+            .map(|row| row.iter().filter(|l| l.is_some()).count())
+            .collect()
+    }
+
+    /// Obtain the labels assigned to each query without the padding
+    /// entries, given that the operation was made for `k` labels per query.
+    pub fn valid_labels(&self, k: usize) -> Vec<Vec<Idx>> {
+        if k == 0 {
+            return Vec::new();
+        }
+        self.labels
+            .chunks(k)
+            .map(|row| row.iter().copied().filter(|l| l.is_some()).collect())
+            .collect()
+    }
+}
+
+/// The handling of queries for which a search finds fewer than `k`
+/// neighbors, such as when the index holds fewer than `k` vectors.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SearchPolicy {
+    /// Fill the missing entries with padding, labelled `-1`, as in `search`.
+    #[default]
+    Pad,
+    /// Shorten the result to the number of neighbors actually found.
+    Truncate,
+    /// Fail with `Error::NotEnoughResults`.
+    Fail,
+}
+
+/// The outcome of an index search operation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchResult {
+    pub distances: Vec<f32>,
     pub labels: Vec<Idx>,
 }
 
+impl SearchResult {
+    /// Obtain the distance to the best match of the query at `query_idx`,
+    /// given that the search was made for the `k` closest vectors.
+    /// Since results are sorted, this is the smallest distance for L2-like
+    /// metrics and the largest for inner product. Returns `None` if there
+    /// are no matches for this query.
+    pub fn best(&self, query_idx: usize, k: usize) -> Option<f32> {
+        self.row_distances(query_idx, k).next()
+    }
+
+    /// Obtain the distance to the worst of the `k` matches of the query at
+    /// `query_idx`. Returns `None` if there are no matches for this query.
+    pub fn worst(&self, query_idx: usize, k: usize) -> Option<f32> {
+        self.row_distances(query_idx, k).last()
+    }
+
+    /// Obtain the minimum and maximum distance over all matches of all
+    /// queries, ignoring padding entries. Returns `None` if there are no
+    /// matches at all.
+    pub fn distance_range(&self) -> Option<(f32, f32)> {
+        self.distances
+            .iter()
+            .zip(&self.labels)
+            .filter(|(_, l)| l.is_some())
+            .map(|(&d, _)| d)
+            .fold(None, |range, d| match range {
+                None => Some((d, d)),
+                Some((min, max)) => Some((min.min(d), max.max(d))),
+            })
+    }
+
+    /// Convert the squared distances reported by a search with the L2
+    /// metric into Euclidean distances, by taking their square root.
+    /// Padding entries are left as they are.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Unsupported` if `metric` is not `MetricType::L2`,
+    /// since other metrics do not report squared distances.
+    pub fn as_euclidean(&self, metric: MetricType) -> Result<SearchResult> {
+        if metric != MetricType::L2 {
+            return Err(Error::Unsupported(
+                "Euclidean distances for a metric other than L2",
+            ));
+        }
+        let distances = self
+            .distances
+            .iter()
+            .zip(&self.labels)
+            .map(|(&d, l)| if l.is_some() { d.sqrt() } else { d })
+            .collect();
+        Ok(SearchResult {
+            distances,
+            labels: self.labels.clone(),
+        })
+    }
+
+    /// Split the result of a search for the `k` closest vectors into one
+    /// list of neighbors per query, in the order returned by the search and
+    /// without padding entries.
+    pub fn to_neighbor_lists(&self, k: usize) -> Vec<Vec<Neighbor>> {
+        if k == 0 {
+            return Vec::new();
+        }
+        self.labels
+            .chunks(k)
+            .zip(self.distances.chunks(k))
+            .map(|(labels, distances)| {
+                labels
+                    .iter()
+                    .zip(distances)
+                    .filter(|(l, _)| l.is_some())
+                    .map(|(&id, &distance)| Neighbor { id, distance })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn row_distances(&self, query_idx: usize, k: usize) -> impl Iterator<Item = f32> + '_ {
+        let start = (query_idx * k).min(self.distances.len());
+        let end = (start + k).min(self.distances.len());
+        self.distances[start..end]
+            .iter()
+            .zip(&self.labels[start..end])
+            .filter(|(_, l)| l.is_some())
+            .map(|(&d, _)| d)
+    }
+}
+
+/// A neighbor of a query vector found by a search.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Neighbor {
+    /// The label of the neighbor
+    pub id: Idx,
+    /// The distance between the query and the neighbor
+    pub distance: f32,
+}
+
 /// The outcome of an index range search operation.
 #[derive(Debug, Clone, PartialEq)]
 pub struct RangeSearchResult {
@@ -443,6 +1351,126 @@ impl RangeSearchResult {
     pub fn labels_mut(&mut self) -> &mut [Idx] {
         self.distance_and_labels_mut().1
     }
+
+    /// Sort the results of each query by distance, in ascending order, or
+    /// in descending order if `descending` is true.
+    pub fn sort_by_distance(&mut self, descending: bool) {
+        let lims = self.lims().to_vec();
+        let full_len = lims.last().cloned().unwrap_or(0);
+        let (distances, labels) = unsafe {
+            let mut distances_ptr = ptr::null_mut();
+            let mut labels_ptr = ptr::null_mut();
+            faiss_RangeSearchResult_labels(self.inner, &mut labels_ptr, &mut distances_ptr);
+            (
+                ::std::slice::from_raw_parts_mut(distances_ptr, full_len),
+                ::std::slice::from_raw_parts_mut(labels_ptr as *mut Idx, full_len),
+            )
+        };
+        let mut hits = Vec::new();
+        for w in lims.windows(2) {
+            let (start, end) = (w[0], w[1]);
+            hits.clear();
+            hits.extend(
+                distances[start..end]
+                    .iter()
+                    .copied()
+                    .zip(labels[start..end].iter().copied()),
+            );
+            hits.sort_by(|a, b| {
+                let ord = a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal);
+                if descending {
+                    ord.reverse()
+                } else {
+                    ord
+                }
+            });
+            for (i, &(d, l)) in hits.iter().enumerate() {
+                distances[start + i] = d;
+                labels[start + i] = l;
+            }
+        }
+    }
+
+    /// Iterate over the hits of each query, borrowing them from the result.
+    pub fn iter(&self) -> RangeSearchIter<'_> {
+        let (distances, labels) = self.distance_and_labels();
+        RangeSearchIter {
+            lims: self.lims(),
+            distances,
+            labels,
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a RangeSearchResult {
+    type Item = RangeQueryHits<'a>;
+    type IntoIter = RangeSearchIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the hits of each query of a range search result, created
+/// by `RangeSearchResult::iter`.
+#[derive(Debug, Clone)]
+pub struct RangeSearchIter<'a> {
+    lims: &'a [usize],
+    distances: &'a [f32],
+    labels: &'a [Idx],
+}
+
+impl<'a> Iterator for RangeSearchIter<'a> {
+    type Item = RangeQueryHits<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.lims.len() < 2 {
+            return None;
+        }
+        let (start, end) = (self.lims[0], self.lims[1]);
+        self.lims = &self.lims[1..];
+        Some(RangeQueryHits {
+            distances: &self.distances[start..end],
+            labels: &self.labels[start..end],
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.lims.len().saturating_sub(1);
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for RangeSearchIter<'_> {}
+
+/// The hits of a single query of a range search, borrowed from the result.
+/// They are not sorted, unless the result was sorted beforehand.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RangeQueryHits<'a> {
+    distances: &'a [f32],
+    labels: &'a [Idx],
+}
+
+impl<'a> RangeQueryHits<'a> {
+    /// The number of hits of the query.
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Whether the query has no hits.
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// The labels of the hits.
+    pub fn labels(&self) -> &'a [Idx] {
+        self.labels
+    }
+
+    /// The distances of the hits, in the same order as the labels.
+    pub fn distances(&self) -> &'a [f32] {
+        self.distances
+    }
 }
 
 impl Drop for RangeSearchResult {
@@ -477,6 +1505,23 @@ impl IndexImpl {
     pub fn inner_ptr(&self) -> *mut FaissIndex {
         self.inner
     }
+
+    /// Set the size of the candidate list explored by searches in an HNSW
+    /// index, or in the HNSW quantizer of an IVF index. Larger values make
+    /// searches slower and more accurate, and values below `k` are raised
+    /// to `k` by the native search.
+    ///
+    /// The C API does not expose HNSW indexes, so other HNSW search options
+    /// such as the bounded queue cannot be set.
+    ///
+    /// # Errors
+    ///
+    /// Results in a native error if the index does not use HNSW.
+    pub fn set_ef_search(&mut self, ef_search: usize) -> Result<()> {
+        // not exposed by the C API,
+        // but supported by the native parameter space
+        autotune::ParameterSpace::new()?.set_index_parameter(self, "efSearch", ef_search as f64)
+    }
 }
 
 impl NativeIndex for IndexImpl {
@@ -549,6 +1594,11 @@ impl TryClone for IndexImpl {
 /// vectors. `description` should follow the exact guidelines as the native Faiss interface
 /// (see the [Faiss wiki](https://github.com/facebookresearch/faiss/wiki/Faiss-indexes) for examples).
 ///
+/// If the created index is an IVF index and the `FAISS_DEFAULT_NPROBE`
+/// environment variable holds a positive integer, it is used as the initial
+/// number of probes of the index, which is convenient for quick experiments.
+/// This can still be overridden by setting `nprobe` on the index.
+///
 /// # Error
 ///
 /// This function returns an error if the description contains any byte with the value `\0` (since
@@ -557,7 +1607,7 @@ pub fn index_factory<D>(d: u32, description: D, metric: MetricType) -> Result<In
 where
     D: AsRef<str>,
 {
-    unsafe {
+    let index = unsafe {
         let metric = metric as c_uint;
         let description =
             CString::new(description.as_ref()).map_err(|_| Error::IndexDescription)?;
@@ -568,14 +1618,141 @@ where
             description.as_ptr(),
             metric,
         ))?;
-        Ok(IndexImpl { inner: index_ptr })
+        IndexImpl { inner: index_ptr }
+    };
+    apply_default_nprobe(&index, DEFAULT_NPROBE_VAR);
+    Ok(index)
+}
+
+/// The environment variable holding the default number of probes of IVF
+/// indexes created by `index_factory`.
+pub const DEFAULT_NPROBE_VAR: &str = "FAISS_DEFAULT_NPROBE";
+
+/// Set the number of probes of an IVF index from the given environment
+/// variable, if it holds a positive integer.
+fn apply_default_nprobe(index: &IndexImpl, var: &str) {
+    let nprobe = std::env::var(var)
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .filter(|&nprobe| nprobe > 0);
+    if let Some(nprobe) = nprobe {
+        unsafe {
+            let ivf = faiss_IndexIVF_cast(index.inner);
+            if !ivf.is_null() {
+                faiss_IndexIVF_set_nprobe(ivf, nprobe);
+            }
+        }
+    }
+}
+
+/// The distance of the entries padding a search result, which is infinite
+/// in the direction of the worst possible result, as done by `search`.
+fn padding_distance(similarity: bool) -> f32 {
+    if similarity {
+        f32::NEG_INFINITY
+    } else {
+        f32::INFINITY
     }
 }
 
+/// Check whether two indexes hold the same contents, for testing purposes.
+/// The indexes are equivalent if they have the same dimensionality, metric
+/// and number of vectors, and if their stored vectors are equal within `eps`
+/// of each other. Stored vectors are only compared if both indexes support
+/// reconstruction; otherwise, they are equivalent only if neither does.
+pub fn indexes_equivalent<A, B>(a: &A, b: &B, eps: f32) -> bool
+where
+    A: ?Sized + Index,
+    B: ?Sized + Index,
+{
+    if a.d() != b.d() || a.metric_type() != b.metric_type() || a.ntotal() != b.ntotal() {
+        return false;
+    }
+    let ntotal = a.ntotal() as usize;
+    if ntotal == 0 {
+        return true;
+    }
+    match (
+        a.reconstruct_n(Idx::new(0), ntotal),
+        b.reconstruct_n(Idx::new(0), ntotal),
+    ) {
+        (Ok(x), Ok(y)) => x.iter().zip(&y).all(|(u, v)| (u - v).abs() <= eps),
+        (Err(_), Err(_)) => true,
+        _ => false,
+    }
+}
+
+/// Merge the results of searches for the `k` closest vectors to the same
+/// queries in several indexes into the global `k` closest vectors of each
+/// query. The best distances are the smallest ones, or the largest ones for
+/// similarity metrics such as the inner product. Missing entries are filled
+/// with padding, with the same infinite distance as in `search`.
+///
+/// Labels are kept as they are, so the indexes should assign distinct IDs
+/// to their vectors.
+///
+/// # Errors
+///
+/// Returns `Error::BadDimension` if the results do not all hold the same
+/// number of queries with `k` entries each.
+pub fn merge_search_results(
+    results: &[SearchResult],
+    k: usize,
+    metric: MetricType,
+) -> Result<SearchResult> {
+    let len = results.first().map_or(0, |r| r.labels.len());
+    let consistent = results
+        .iter()
+        .all(|r| r.labels.len() == len && r.distances.len() == len);
+    let whole_rows = len == 0 || (k != 0 && len / k * k == len);
+    if !consistent || !whole_rows {
+        return Err(Error::BadDimension);
+    }
+    let similarity = metric.is_similarity();
+    let padding = padding_distance(similarity);
+    let mut distances = Vec::with_capacity(len);
+    let mut labels = Vec::with_capacity(len);
+    let mut hits = Vec::with_capacity(results.len() * k);
+    for query in 0..len.checked_div(k).unwrap_or(0) {
+        let row = query * k..(query + 1) * k;
+        hits.clear();
+        for r in results {
+            hits.extend(
+                r.distances[row.clone()]
+                    .iter()
+                    .copied()
+                    .zip(r.labels[row.clone()].iter().copied())
+                    .filter(|(_, l)| l.is_some()),
+            );
+        }
+        hits.sort_by(|a, b| {
+            let ord = a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal);
+            if similarity {
+                ord.reverse()
+            } else {
+                ord
+            }
+        });
+        hits.resize(k, (padding, Idx::none()));
+        distances.extend(hits.iter().map(|h| h.0));
+        labels.extend(hits.iter().map(|h| h.1));
+    }
+    Ok(SearchResult { distances, labels })
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{index_factory, Idx, Index, TryClone};
+    use super::{
+        apply_default_nprobe, checked_idx_t, checked_result_len, index_factory, indexes_equivalent,
+        merge_search_results, Capability, Idx, Index, IndexExt, NativeIndex, Neighbor,
+        SearchPolicy, SearchResult, TryClone,
+    };
+    use super::{AssignSearchResult, RangeSearchResult};
+    use crate::error::{Error, Result};
+    use crate::index::flat::FlatIndexImpl;
+    use crate::index::id_map::IdMap;
     use crate::metric::MetricType;
+    use crate::selector::IdSelector;
 
     #[test]
     fn index_factory_flat() {
@@ -631,6 +1808,34 @@ mod tests {
         assert_eq!(index.ntotal(), 0);
     }
 
+    #[test]
+    fn hnsw_set_ef_search() {
+        const D: u32 = 8;
+        let some_data: Vec<f32> = (0..500 * D).map(|i| ((i * 7919) % 1009) as f32).collect();
+        let my_query = &some_data[..20 * D as usize];
+        let truth = crate::utils::ground_truth(&some_data, my_query, D, 5, MetricType::L2).unwrap();
+
+        let mut index = index_factory(D, "HNSW4", MetricType::L2).unwrap();
+        index.add(&some_data).unwrap();
+        index.set_ef_search(1).unwrap();
+        let narrow = index.search(my_query, 5).unwrap();
+        index.set_ef_search(500).unwrap();
+        let wide = index.search(my_query, 5).unwrap();
+        assert!(narrow
+            .labels
+            .iter()
+            .chain(&wide.labels)
+            .all(|l| l.is_some()));
+
+        let narrow_recall = crate::utils::recall_at_k(&narrow, &truth, 5).unwrap();
+        let wide_recall = crate::utils::recall_at_k(&wide, &truth, 5).unwrap();
+        assert!(wide_recall >= narrow_recall);
+        assert!(wide_recall > 0.9);
+
+        let mut flat = index_factory(D, "Flat", MetricType::L2).unwrap();
+        assert!(flat.set_ef_search(16).is_err());
+    }
+
     #[test]
     fn bad_index_factory_description() {
         let r = index_factory(64, "fdnoyq", MetricType::L2);
@@ -639,6 +1844,207 @@ mod tests {
         assert!(r.is_err());
     }
 
+    #[test]
+    fn checked_conversions() {
+        assert_eq!(checked_idx_t(0), Ok(0));
+        assert_eq!(checked_idx_t(1 << 40), Ok(1 << 40));
+        assert_eq!(checked_idx_t(usize::MAX), Err(Error::Overflow));
+        assert_eq!(checked_result_len(16, 5), Ok(80));
+        assert_eq!(checked_result_len(0, usize::MAX), Ok(0));
+        assert_eq!(checked_result_len(2, usize::MAX), Err(Error::Overflow));
+        assert_eq!(checked_result_len(1, usize::MAX / 2), Err(Error::Overflow));
+    }
+
+    #[test]
+    fn search_oversized_k() {
+        let mut index = index_factory(8, "Flat", MetricType::L2).unwrap();
+        let some_data = &[
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 1., 1., 1., -1., 0., 0.,
+            0., 1., 1., 0., 0., -1., 100., 100., 100., 100., -100., 100., 100., 100., 120., 100.,
+            100., 105., -100., 100., 100., 105.,
+        ];
+        index.add(some_data).unwrap();
+
+        let my_query = [0.; 8];
+        let r = index.search(&my_query, usize::MAX);
+        assert_eq!(r.unwrap_err(), Error::Overflow);
+        let r = index.assign(&my_query, usize::MAX);
+        assert_eq!(r.unwrap_err(), Error::Overflow);
+        // no queries, but `k` still does not fit
+        let r = index.search(&[], usize::MAX);
+        assert_eq!(r.unwrap_err(), Error::Overflow);
+    }
+
+    #[test]
+    fn flat_index_extend_from_vectors() {
+        let mut index = index_factory(4, "Flat", MetricType::L2).unwrap();
+        let vectors: Vec<Vec<f32>> = (0..2500).map(|i| vec![i as f32; 4]).collect();
+        index.extend_from_vectors(vectors).unwrap();
+        assert_eq!(index.ntotal(), 2500);
+        assert_eq!(index.reconstruct(Idx(2499)).unwrap(), vec![2499.; 4]);
+
+        let r = index.extend_from_vectors(vec![vec![0.; 4], vec![0.; 3]]);
+        assert_eq!(r, Err(Error::BadDimension));
+        assert_eq!(index.ntotal(), 2500);
+    }
+
+    #[test]
+    fn flat_index_add_from_reader() {
+        let mut index = index_factory(8, "Flat", MetricType::L2).unwrap();
+        let some_data = &[
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 1., 1., 1., -1., 0., 0.,
+            0., 1., 1., 0., 0., -1., 100., 100., 100., 100., -100., 100., 100., 100., 120., 100.,
+            100., 105., -100., 100., 100., 105.,
+        ];
+        let bytes: Vec<u8> = some_data.iter().flat_map(|x| x.to_le_bytes()).collect();
+
+        let added = index.add_from_reader(&bytes[..], 2).unwrap();
+        assert_eq!(added, 5);
+        assert_eq!(index.ntotal(), 5);
+        let result = index.search(&[0.; 8], 5).unwrap();
+        assert_eq!(result.labels, vec![Idx(2), Idx(1), Idx(0), Idx(3), Idx(4)]);
+
+        // a truncated final vector is an error
+        index.reset().unwrap();
+        let r = index.add_from_reader(&bytes[..bytes.len() - 4], 10);
+        assert_eq!(r, Err(Error::BadDimension));
+        assert_eq!(index.ntotal(), 0);
+    }
+
+    #[test]
+    fn search_result_best_worst() {
+        let mut index = index_factory(8, "Flat", MetricType::L2).unwrap();
+        let some_data = &[
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 1., 1., 1., -1., 0., 0.,
+            0., 1., 1., 0., 0., -1., 100., 100., 100., 100., -100., 100., 100., 100., 120., 100.,
+            100., 105., -100., 100., 100., 105.,
+        ];
+        index.add(some_data).unwrap();
+
+        let my_query = vec![
+            0., 0., 0., 0., 0., 0., 0., 0., 100., 100., 100., 100., 100., 100., 100., 100.,
+        ];
+        let result = index.search(&my_query, 5).unwrap();
+        for (i, row) in result.distances.chunks(5).enumerate() {
+            let min = row.iter().cloned().fold(f32::INFINITY, f32::min);
+            let max = row.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+            assert_eq!(result.best(i, 5), Some(min));
+            assert_eq!(result.worst(i, 5), Some(max));
+        }
+        let all_min = result
+            .distances
+            .iter()
+            .cloned()
+            .fold(f32::INFINITY, f32::min);
+        let all_max = result
+            .distances
+            .iter()
+            .cloned()
+            .fold(f32::NEG_INFINITY, f32::max);
+        assert_eq!(result.distance_range(), Some((all_min, all_max)));
+    }
+
+    #[test]
+    fn search_result_padding() {
+        let result = SearchResult {
+            distances: vec![1., 2., f32::MAX, f32::MAX, f32::MAX, f32::MAX],
+            labels: vec![
+                Idx(4),
+                Idx(2),
+                Idx::none(),
+                Idx::none(),
+                Idx::none(),
+                Idx::none(),
+            ],
+        };
+        assert_eq!(result.best(0, 3), Some(1.));
+        assert_eq!(result.worst(0, 3), Some(2.));
+        assert_eq!(result.best(1, 3), None);
+        assert_eq!(result.worst(1, 3), None);
+        assert_eq!(result.distance_range(), Some((1., 2.)));
+    }
+
+    #[test]
+    fn assign_result_padding() {
+        let mut index = index_factory(2, "Flat", MetricType::L2).unwrap();
+        index.add(&[0., 0., 10., 10.]).unwrap();
+        let result = index.assign(&[1., 1., 9., 9.], 4).unwrap();
+        assert_eq!(result.labels.len(), 8);
+        assert_eq!(result.valid_counts(4), vec![2, 2]);
+        assert_eq!(
+            result.valid_labels(4),
+            vec![vec![Idx(0), Idx(1)], vec![Idx(1), Idx(0)]]
+        );
+        assert!(result.labels[2..4].iter().all(|l| l.is_none()));
+    }
+
+    #[test]
+    fn flat_index_search_bounded() {
+        let mut index = index_factory(2, "Flat", MetricType::L2).unwrap();
+        index.add(&[0., 0., 1., 0., 3., 0., 10., 0.]).unwrap();
+        let result = index.search_bounded(&[0., 0.], 3, 4.).unwrap();
+        assert_eq!(result.labels, vec![Idx(0), Idx(1), Idx::none()]);
+        assert_eq!(result.distances, vec![0., 1., f32::INFINITY]);
+
+        let mut index = index_factory(2, "Flat", MetricType::InnerProduct).unwrap();
+        index.add(&[1., 0., 2., 0., -1., 0.]).unwrap();
+        let result = index.search_bounded(&[1., 0.], 3, 0.).unwrap();
+        assert_eq!(result.labels, vec![Idx(1), Idx(0), Idx::none()]);
+        assert_eq!(result.distances[2], f32::NEG_INFINITY);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "null native pointer")]
+    fn null_index_getter_panics() {
+        let index = super::IndexImpl {
+            inner: std::ptr::null_mut(),
+        };
+        index.d();
+    }
+
+    #[test]
+    fn index_by_mut_ref() {
+        fn count(index: impl Index) -> u64 {
+            index.ntotal()
+        }
+
+        fn add_some(mut index: impl Index, x: &[f32]) {
+            index.add(x).unwrap();
+        }
+
+        let mut index = crate::index::flat::FlatIndex::new_l2(4).unwrap();
+        add_some(&mut index, &[0., 1., 2., 3., 4., 5., 6., 7.]);
+        assert_eq!(count(&mut index), 2);
+        assert_eq!(index.ntotal(), 2);
+        assert_eq!(count(index), 2);
+    }
+
+    #[test]
+    fn flat_index_reconstruct() {
+        let mut index = index_factory(8, "Flat", MetricType::L2).unwrap();
+        let some_data = &[
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 1., 1., 1., -1., 0., 0.,
+            0., 1., 1., 0., 0., -1., 100., 100., 100., 100., -100., 100., 100., 100., 120., 100.,
+            100., 105., -100., 100., 100., 105.,
+        ];
+        index.add(some_data).unwrap();
+
+        assert_eq!(index.reconstruct(Idx(3)).unwrap(), &some_data[24..32]);
+        assert_eq!(index.reconstruct_n(Idx(1), 3).unwrap(), &some_data[8..32]);
+
+        let batch = index.reconstruct_batch(&[Idx(0), Idx(2), Idx(4)]).unwrap();
+        assert_eq!(batch.len(), 3 * 8);
+        assert_eq!(&batch[..8], &some_data[..8]);
+        assert_eq!(&batch[8..16], &some_data[16..24]);
+        assert_eq!(&batch[16..], &some_data[32..]);
+
+        // order follows the given IDs
+        let batch = index.reconstruct_batch(&[Idx(4), Idx(0)]).unwrap();
+        assert_eq!(&batch[..8], &some_data[32..]);
+        assert_eq!(&batch[8..], &some_data[..8]);
+    }
+
     #[test]
     fn index_clone() {
         let mut index = index_factory(4, "Flat", MetricType::L2).unwrap();
@@ -773,4 +2179,563 @@ mod tests {
         assert!(labels == &[Idx(1), Idx(2)] || labels == &[Idx(2), Idx(1)]);
         assert!(distances.iter().all(|x| *x > 0.));
     }
+
+    #[test]
+    fn flat_index_search_timed() {
+        let mut index = index_factory(8, "Flat", MetricType::L2).unwrap();
+        let some_data = &[
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 1., 1., 1., -1., 0., 0.,
+            0., 1., 1., 0., 0., -1., 100., 100., 100., 100., -100., 100., 100., 100., 120., 100.,
+            100., 105., -100., 100., 100., 105.,
+        ];
+        index.add(some_data).unwrap();
+
+        let my_query = [0.; 8];
+        // a search this small may take less than the resolution of the clock
+        let (result, _elapsed) = index.search_timed(&my_query, 5).unwrap();
+        assert_eq!(result, index.search(&my_query, 5).unwrap());
+    }
+
+    #[test]
+    fn flat_index_search_with_policy() {
+        let mut index = index_factory(2, "Flat", MetricType::L2).unwrap();
+        index.add(&[0., 0., 3., 0.]).unwrap();
+        let my_query = [1., 0.];
+
+        let result = index
+            .search_with_policy(&my_query, 5, SearchPolicy::default())
+            .unwrap();
+        assert_eq!(result, index.search(&my_query, 5).unwrap());
+        assert_eq!(result.labels.len(), 5);
+        assert_eq!(&result.labels[2..], &[Idx::none(); 3]);
+
+        let result = index
+            .search_with_policy(&my_query, 5, SearchPolicy::Truncate)
+            .unwrap();
+        assert_eq!(result.labels, vec![Idx::new(0), Idx::new(1)]);
+        assert_eq!(result.distances, vec![1., 4.]);
+
+        let result = index.search_with_policy(&my_query, 5, SearchPolicy::Fail);
+        assert_eq!(result, Err(Error::NotEnoughResults));
+        let result = index
+            .search_with_policy(&my_query, 2, SearchPolicy::Fail)
+            .unwrap();
+        assert_eq!(result.labels, vec![Idx::new(0), Idx::new(1)]);
+    }
+
+    #[test]
+    fn flat_index_range_search_iter() {
+        let mut index = index_factory(2, "Flat", MetricType::L2).unwrap();
+        index.add(&[0., 0., 1., 0., 2., 0., 10., 0.]).unwrap();
+        let queries = [0., 0., 10., 0., 50., 0.];
+        let result = index.range_search(&queries, 5.).unwrap();
+
+        let counts: Vec<usize> = result.iter().map(|hits| hits.len()).collect();
+        assert_eq!(counts, vec![3, 1, 0]);
+        assert_eq!(result.iter().len(), 3);
+        let total: usize = (&result).into_iter().map(|hits| hits.len()).sum();
+        assert_eq!(total, result.labels().len());
+
+        let hits = result.iter().nth(1).unwrap();
+        assert_eq!(hits.labels(), &[Idx::new(3)]);
+        assert_eq!(hits.distances(), &[0.]);
+        assert!(result.iter().last().unwrap().is_empty());
+    }
+
+    #[test]
+    fn flat_index_neighbor_lists() {
+        let mut index = index_factory(2, "Flat", MetricType::L2).unwrap();
+        index.add(&[0., 0., 2., 0., 5., 0.]).unwrap();
+        let result = index.search(&[4., 0., 0., 7.], 4).unwrap();
+
+        let lists = result.to_neighbor_lists(4);
+        assert_eq!(lists.len(), 2);
+        let ids: Vec<Vec<Idx>> = lists
+            .iter()
+            .map(|l| l.iter().map(|n| n.id).collect())
+            .collect();
+        assert_eq!(
+            ids,
+            vec![
+                vec![Idx::new(2), Idx::new(1), Idx::new(0)],
+                vec![Idx::new(0), Idx::new(1), Idx::new(2)],
+            ]
+        );
+        assert_eq!(
+            lists[0][0],
+            Neighbor {
+                id: Idx::new(2),
+                distance: 1.
+            }
+        );
+        assert!(lists
+            .iter()
+            .all(|l| l.windows(2).all(|w| w[0].distance <= w[1].distance)));
+    }
+
+    #[test]
+    fn flat_index_search_recentered() {
+        let mut index = index_factory(2, "Flat", MetricType::L2).unwrap();
+        // centered on the anchor [10, 10]
+        index.add(&[0., 0., -10., -10.]).unwrap();
+        let anchor = [10., 10.];
+
+        let result = index.search(&[1., 1.], 1).unwrap();
+        assert_eq!(result.labels, vec![Idx::new(0)]);
+        let result = index.search_recentered(&[1., 1.], &anchor, 1).unwrap();
+        assert_eq!(result.labels, vec![Idx::new(1)]);
+        assert_eq!(result.distances, vec![2.]);
+
+        let result = index
+            .search_recentered(&[1., 1., 9., 9.], &anchor, 1)
+            .unwrap();
+        assert_eq!(result.labels, vec![Idx::new(1), Idx::new(0)]);
+
+        let result = index.search_recentered(&[1., 1.], &[10.], 1);
+        assert_eq!(result, Err(Error::BadDimension));
+    }
+
+    #[test]
+    fn index_supports() {
+        let index = index_factory(4, "Flat", MetricType::L2).unwrap();
+        assert!(!index.supports(Capability::AddWithIds));
+        assert!(index.supports(Capability::RemoveIds));
+        assert!(index.supports(Capability::Reconstruct));
+        assert!(index.supports(Capability::RangeSearch));
+
+        let index = IdMap::new(FlatIndexImpl::new_l2(4).unwrap()).unwrap();
+        assert!(index.supports(Capability::AddWithIds));
+        assert!(index.supports(Capability::RemoveIds));
+        assert!(!index.supports(Capability::Reconstruct));
+        assert!(index.supports(Capability::RangeSearch));
+
+        let index = index_factory(4, "IVF2,Flat", MetricType::L2).unwrap();
+        assert!(index.supports(Capability::AddWithIds));
+        assert!(!index.supports(Capability::Reconstruct));
+    }
+
+    #[test]
+    fn index_factory_default_nprobe() {
+        // a separate variable, not to affect the other tests of IVF indexes
+        const VAR: &str = "FAISS_DEFAULT_NPROBE_TEST";
+        let index = index_factory(4, "IVF8,Flat", MetricType::L2).unwrap();
+        std::env::set_var(VAR, "6");
+        apply_default_nprobe(&index, VAR);
+        let mut index = index.into_ivf_flat().unwrap();
+        assert_eq!(index.nprobe(), 6);
+        index.set_nprobe(2);
+        assert_eq!(index.nprobe(), 2);
+
+        std::env::set_var(VAR, "none");
+        let index = index_factory(4, "IVF8,Flat", MetricType::L2).unwrap();
+        apply_default_nprobe(&index, VAR);
+        assert_eq!(index.into_ivf_flat().unwrap().nprobe(), 1);
+        std::env::remove_var(VAR);
+    }
+
+    #[test]
+    fn ivf_index_clone_empty_trained() {
+        let mut index = index_factory(2, "IVF2,Flat", MetricType::L2).unwrap();
+        let some_data = [0., 0., 1., 0., 100., 0., 101., 0.];
+        index.train(&some_data).unwrap();
+        index.add(&some_data).unwrap();
+
+        let mut shard = index.clone_empty_trained().unwrap();
+        assert!(shard.is_trained());
+        assert_eq!(shard.ntotal(), 0);
+        assert_eq!(index.ntotal(), 4);
+
+        shard.add(&[100.5, 0.]).unwrap();
+        let result = shard.search(&[100., 0.], 1).unwrap();
+        assert_eq!(result.labels, vec![Idx::new(0)]);
+        assert_eq!(result.distances, vec![0.25]);
+    }
+
+    #[test]
+    fn flat_index_merge_search_results() {
+        let mut shard_a = index_factory(1, "IDMap,Flat", MetricType::L2).unwrap();
+        let mut shard_b = index_factory(1, "IDMap,Flat", MetricType::L2).unwrap();
+        shard_a
+            .add_with_ids(&[1., 4., 9.], &[Idx::new(1), Idx::new(4), Idx::new(9)])
+            .unwrap();
+        shard_b
+            .add_with_ids(&[2., 3.], &[Idx::new(2), Idx::new(3)])
+            .unwrap();
+        let queries = [0., 10.];
+        let results = [
+            shard_a.search(&queries, 3).unwrap(),
+            shard_b.search(&queries, 3).unwrap(),
+        ];
+
+        let merged = merge_search_results(&results, 3, MetricType::L2).unwrap();
+        let ids = |labels: &[Idx]| labels.iter().map(|l| l.get()).collect::<Vec<_>>();
+        assert_eq!(
+            ids(&merged.labels),
+            vec![Some(1), Some(2), Some(3), Some(9), Some(4), Some(3)]
+        );
+        assert_eq!(merged.distances, vec![1., 4., 9., 1., 36., 49.]);
+
+        // the inner product favors larger values
+        let ip_results = [
+            SearchResult {
+                distances: vec![0.9, 0.2],
+                labels: vec![Idx::new(1), Idx::new(2)],
+            },
+            SearchResult {
+                distances: vec![0.5, 0.],
+                labels: vec![Idx::new(3), Idx::none()],
+            },
+        ];
+        let merged = merge_search_results(&ip_results, 2, MetricType::InnerProduct).unwrap();
+        assert_eq!(merged.labels, vec![Idx::new(1), Idx::new(3)]);
+        assert_eq!(merged.distances, vec![0.9, 0.5]);
+        let merged = merge_search_results(&ip_results[1..], 2, MetricType::InnerProduct).unwrap();
+        assert_eq!(merged.labels, vec![Idx::new(3), Idx::none()]);
+        assert_eq!(merged.distances, vec![0.5, f32::NEG_INFINITY]);
+
+        let truncated = SearchResult {
+            distances: vec![0.; 2],
+            labels: vec![Idx::new(0); 2],
+        };
+        assert_eq!(
+            merge_search_results(&[results[0].clone(), truncated], 3, MetricType::L2),
+            Err(Error::BadDimension)
+        );
+    }
+
+    #[test]
+    fn flat_index_as_euclidean() {
+        let mut index = index_factory(2, "Flat", MetricType::L2).unwrap();
+        index.add(&[3., 4., 1., 1.]).unwrap();
+        let result = index.search(&[0., 0.], 3).unwrap();
+        assert_eq!(&result.distances[..2], &[2., 25.]);
+
+        let euclidean = result.as_euclidean(MetricType::L2).unwrap();
+        assert_eq!(euclidean.labels, result.labels);
+        assert_eq!(&euclidean.distances[..2], &[2_f32.sqrt(), 5.]);
+        assert_eq!(euclidean.distances[2], result.distances[2]);
+
+        let mut index = index_factory(2, "Flat", MetricType::InnerProduct).unwrap();
+        index.add(&[3., 4.]).unwrap();
+        let result = index.search(&[1., 0.], 1).unwrap();
+        assert!(matches!(
+            result.as_euclidean(index.metric_type()),
+            Err(Error::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn flat_index_add_rows() {
+        let mut index = index_factory(4, "Flat", MetricType::L2).unwrap();
+        let rows = [[0., 0., 0., 0.], [1., 2., 3., 4.], [5., 5., 5., 5.]];
+        index.add_rows(&rows).unwrap();
+        assert_eq!(index.ntotal(), 3);
+        assert_eq!(index.reconstruct_n(Idx::new(0), 3).unwrap(), rows.concat());
+
+        let result = index.search_row(&[1., 2., 3., 5.], 2).unwrap();
+        assert_eq!(result.labels, vec![Idx::new(1), Idx::new(2)]);
+        assert_eq!(result.distances, vec![1., 29.]);
+
+        assert_eq!(index.add_rows(&[[0.; 3]]), Err(Error::BadDimension));
+        assert_eq!(index.search_row(&[0.; 5], 1), Err(Error::BadDimension));
+    }
+
+    #[test]
+    fn sq_index_encode_query() {
+        let mut index = index_factory(4, "SQ8", MetricType::L2).unwrap();
+        let some_data = [0., 0., 0., 0., 1., 1., 1., 1., 0.5, 0.25, 0.75, 1.];
+        index.train(&some_data).unwrap();
+        index.add(&some_data).unwrap();
+
+        let my_query = [0.5, 0.25, 0.75, 0.9];
+        let codes = index.encode_query(&my_query).unwrap();
+        assert_eq!(codes.len(), 4);
+        let decoded = index.sa_decode(&codes).unwrap();
+        assert!(decoded
+            .iter()
+            .zip(&my_query)
+            .all(|(a, b)| (a - b).abs() < 0.01));
+
+        let result = index.search_encoded(&codes, 1).unwrap();
+        assert_eq!(result.labels, index.search(&my_query, 1).unwrap().labels);
+        assert_eq!(index.encode_query(&my_query[..3]), Err(Error::BadDimension));
+        assert_eq!(
+            index.search_encoded(&codes[..3], 1),
+            Err(Error::BadCodeSize)
+        );
+    }
+
+    /// An index of the given dimensionality implementing only the required
+    /// methods of `Index`.
+    struct EmptyIndex(u32);
+
+    impl Index for EmptyIndex {
+        fn is_trained(&self) -> bool {
+            true
+        }
+        fn ntotal(&self) -> u64 {
+            0
+        }
+        fn d(&self) -> u32 {
+            self.0
+        }
+        fn metric_type(&self) -> MetricType {
+            MetricType::L2
+        }
+        fn add(&mut self, _x: &[f32]) -> Result<()> {
+            Ok(())
+        }
+        fn add_with_ids(&mut self, _x: &[f32], _xids: &[Idx]) -> Result<()> {
+            Ok(())
+        }
+        fn train(&mut self, _x: &[f32]) -> Result<()> {
+            Ok(())
+        }
+        fn assign(&mut self, _q: &[f32], _k: usize) -> Result<AssignSearchResult> {
+            Ok(Default::default())
+        }
+        fn search(&mut self, _q: &[f32], _k: usize) -> Result<SearchResult> {
+            Ok(Default::default())
+        }
+        fn range_search(&mut self, _q: &[f32], _radius: f32) -> Result<RangeSearchResult> {
+            Err(Error::Unsupported("range search"))
+        }
+        fn reset(&mut self) -> Result<()> {
+            Ok(())
+        }
+        fn remove_ids(&mut self, _sel: &IdSelector) -> Result<usize> {
+            Ok(0)
+        }
+        fn verbose(&self) -> bool {
+            false
+        }
+        fn set_verbose(&mut self, _value: bool) {}
+    }
+
+    #[test]
+    fn custom_index_default_reconstruct() {
+        let index = EmptyIndex(2);
+        assert!(matches!(
+            index.reconstruct(Idx::new(0)),
+            Err(Error::Unsupported(_))
+        ));
+        assert!(matches!(
+            index.reconstruct_n(Idx::new(0), 1),
+            Err(Error::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn custom_index_add_from_reader_zero_dimensions() {
+        let mut index = EmptyIndex(0);
+        let bytes = [0_u8; 8];
+        assert_eq!(
+            index.add_from_reader(&bytes[..], 2),
+            Err(Error::BadDimension)
+        );
+    }
+
+    #[test]
+    fn custom_index_default_codec() {
+        let index = EmptyIndex(2);
+        assert!(matches!(index.sa_code_size(), Err(Error::Unsupported(_))));
+        assert!(matches!(
+            index.sa_encode(&[0., 0.]),
+            Err(Error::Unsupported(_))
+        ));
+        assert!(matches!(
+            index.sa_decode_into(&[], &mut []),
+            Err(Error::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn reset_keeps_training() {
+        let some_data: Vec<f32> = (0..256 * 8).map(|i| ((i * 17) % 41) as f32).collect();
+        let my_query = &some_data[8..16];
+        for description in &["IVF4,Flat", "IVF4,SQ8", "PQ2x4", "IVF4,PQ2x4"] {
+            let mut index = index_factory(8, description, MetricType::L2).unwrap();
+            index.train(&some_data).unwrap();
+            index.add(&some_data).unwrap();
+            let before = index.search(my_query, 3).unwrap();
+
+            index.reset().unwrap();
+            assert!(index.is_trained(), "{} lost its training", description);
+            assert_eq!(index.ntotal(), 0);
+
+            index.add(&some_data).unwrap();
+            assert_eq!(index.search(my_query, 3).unwrap(), before);
+        }
+    }
+
+    #[test]
+    fn flat_index_nearest() {
+        let mut index = index_factory(2, "Flat", MetricType::L2).unwrap();
+        assert_eq!(index.nearest(&[0., 0.]).unwrap(), None);
+
+        index.add(&[5., 5., 1., 2., -3., 0.]).unwrap();
+        let result = index.search(&[0., 1.], 1).unwrap();
+        assert_eq!(
+            index.nearest(&[0., 1.]).unwrap(),
+            Some((result.labels[0], result.distances[0]))
+        );
+        assert_eq!(index.nearest(&[0., 1.]).unwrap(), Some((Idx::new(1), 2.)));
+        assert_eq!(index.nearest(&[0., 1., 2.]), Err(Error::BadDimension));
+    }
+
+    #[test]
+    fn flat_index_memory_usage() {
+        let mut index = index_factory(128, "Flat", MetricType::L2).unwrap();
+        assert_eq!(index.memory_usage(), 0);
+        index.add(&vec![0.5; 1000 * 128]).unwrap();
+        assert_eq!(index.memory_usage(), 512_000);
+
+        let mut index = index_factory(128, "SQ8", MetricType::L2).unwrap();
+        index.train(&vec![0.5; 10 * 128]).unwrap();
+        index.add(&vec![0.5; 1000 * 128]).unwrap();
+        assert_eq!(index.memory_usage(), 128_000);
+    }
+
+    #[test]
+    fn ivf_flat_evaluate_recall() {
+        use crate::index::flat::FlatIndex;
+        use crate::index::ivf_flat::IVFFlatIndexImpl;
+
+        let some_data: Vec<f32> = (0..10).flat_map(|i| vec![i as f32 + 0.5, 0.]).collect();
+        let mut truth = FlatIndex::new_l2(2).unwrap();
+        truth.add(&some_data).unwrap();
+        let mut exact = truth.try_clone().unwrap();
+
+        // two lists, holding the vectors on either side of x = 5
+        let mut quantizer = FlatIndex::new_l2(2).unwrap();
+        quantizer.add(&[0., 0., 10., 0.]).unwrap();
+        let mut ivf = IVFFlatIndexImpl::new_l2(quantizer, 2, 2).unwrap();
+        ivf.train(&some_data).unwrap();
+        ivf.add(&some_data).unwrap();
+
+        let queries = [4.9, 0., 1., 0.];
+        let recall = exact.evaluate_recall(&queries, 3, &mut truth).unwrap();
+        assert_eq!(recall, 1.);
+        // the first query misses its neighbor across the list boundary
+        let recall = ivf.evaluate_recall(&queries, 3, &mut truth).unwrap();
+        assert!((recall - 5. / 6.).abs() < 1e-6);
+        ivf.set_nprobe(2);
+        let recall = ivf.evaluate_recall(&queries, 3, &mut truth).unwrap();
+        assert_eq!(recall, 1.);
+    }
+
+    #[test]
+    fn flat_index_add_scaled() {
+        let mut index = index_factory(4, "Flat", MetricType::InnerProduct).unwrap();
+        let some_data = &[1., 2., 0., -1., 0.5, 0.5, 0.5, 0.5];
+        index.add(some_data).unwrap();
+        index.add_scaled(some_data, &[3., 0.5]).unwrap();
+        assert_eq!(index.ntotal(), 4);
+
+        let my_query = [1., 1., 1., 0.];
+        let result = index.search(&my_query, 4).unwrap();
+        let score = |label: u64| {
+            let i = result
+                .labels
+                .iter()
+                .position(|&l| l == Idx::new(label))
+                .unwrap();
+            result.distances[i]
+        };
+        assert!((score(2) - 3. * score(0)).abs() < 1e-5);
+        assert!((score(3) - 0.5 * score(1)).abs() < 1e-5);
+
+        assert_eq!(index.add_scaled(some_data, &[1.]), Err(Error::BadDimension));
+    }
+
+    #[test]
+    fn flat_index_add_returning_ids() {
+        let mut index = index_factory(8, "Flat", MetricType::L2).unwrap();
+        let ids = index.add_returning_ids(&[1.; 16]).unwrap();
+        assert_eq!(ids, Idx::new(0)..Idx::new(2));
+        let ids = index.add_returning_ids(&[2.; 24]).unwrap();
+        assert_eq!(ids, Idx::new(2)..Idx::new(5));
+        assert_eq!(index.ntotal(), 5);
+
+        let result = index.search(&[2.; 8], 1).unwrap();
+        assert!(ids.contains(&result.labels[0]));
+    }
+
+    #[test]
+    fn flat_index_range_search_sorted() {
+        let mut index = index_factory(8, "Flat", MetricType::L2).unwrap();
+        let some_data = &[
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 1., 1., 1., -1., 0., 0.,
+            0., 1., 1., 0., 0., -1., 100., 100., 100., 100., -100., 100., 100., 100., 120., 100.,
+            100., 105., -100., 100., 100., 105.,
+        ];
+        index.add(some_data).unwrap();
+
+        let my_query = [
+            0., 0., 0., 0., 0., 0., 0., 0., 100., 100., 100., 100., 100., 100., 100., 100.,
+        ];
+        let unsorted = index.range_search(&my_query, 100_000.).unwrap();
+        let sorted = index.range_search_sorted(&my_query, 100_000.).unwrap();
+        assert_eq!(sorted.lims(), unsorted.lims());
+        let lims = sorted.lims();
+        for w in lims.windows(2) {
+            let (start, end) = (w[0], w[1]);
+            let distances = &sorted.distances()[start..end];
+            assert!(distances.windows(2).all(|d| d[0] <= d[1]));
+            let mut labels = sorted.labels()[start..end].to_vec();
+            let mut expected = unsorted.labels()[start..end].to_vec();
+            labels.sort_by_key(|l| l.get());
+            expected.sort_by_key(|l| l.get());
+            assert_eq!(labels, expected);
+        }
+        assert_eq!(&sorted.labels()[..3], &[Idx(2), Idx(1), Idx(0)]);
+    }
+
+    #[test]
+    fn flat_index_range_count() {
+        let mut index = index_factory(8, "Flat", MetricType::L2).unwrap();
+        let some_data = &[
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 1., 1., 1., -1., 0., 0.,
+            0., 1., 1., 0., 0., -1., 100., 100., 100., 100., -100., 100., 100., 100., 120., 100.,
+            100., 105., -100., 100., 100., 105.,
+        ];
+        index.add(some_data).unwrap();
+
+        let my_query = [
+            0., 0., 0., 0., 0., 0., 0., 0., 100., 100., 100., 100., 100., 100., 100., 100.,
+        ];
+        let counts = index.range_count(&my_query, 8.125).unwrap();
+        assert_eq!(counts, vec![2, 0]);
+
+        let result = index.range_search(&my_query, 8.125).unwrap();
+        let lims = result.lims();
+        for (i, count) in counts.iter().enumerate() {
+            assert_eq!(*count, result.labels()[lims[i]..lims[i + 1]].len());
+        }
+    }
+
+    #[test]
+    fn flat_index_equivalent() {
+        use crate::index::io::{read_index_from_reader, write_index_to_writer};
+
+        let mut index = index_factory(4, "Flat", MetricType::L2).unwrap();
+        index
+            .add(&[1., 2., 3., 4., -1., 0., 0.5, 8., 0., 0., 0., 1.])
+            .unwrap();
+
+        let clone = index.try_clone().unwrap();
+        assert!(indexes_equivalent(&index, &clone, 0.));
+
+        let mut buf = Vec::new();
+        write_index_to_writer(&index, &mut buf).unwrap();
+        let loaded = read_index_from_reader(&buf[..]).unwrap();
+        assert!(indexes_equivalent(&index, &loaded, 1e-6));
+
+        let mut extended = index.try_clone().unwrap();
+        extended.add(&[0.; 4]).unwrap();
+        assert!(!indexes_equivalent(&index, &extended, 1e-6));
+
+        let other = index_factory(4, "Flat", MetricType::InnerProduct).unwrap();
+        assert!(!indexes_equivalent(&index, &other, 1e-6));
+    }
 }
@@ -0,0 +1,245 @@
+//! Detection of non-normalized vectors added to inner product indexes.
+//!
+//! Searching an inner product index only yields cosine similarities if all
+//! vectors are L2-normalized, and forgetting to normalize them is a common
+//! mistake. The [`NormCheckIndex`] wrapper can be enabled to sample the norms
+//! of the vectors added to such an index, and report those which deviate
+//! noticeably from 1.
+//!
+//! [`NormCheckIndex`]: struct.NormCheckIndex.html
+
+use super::*;
+
+use std::fmt::Debug;
+
+/// A report of a vector added to an inner product index without being
+/// L2-normalized.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NormWarning {
+    /// The position of the vector in the added batch
+    pub position: usize,
+    /// The L2 norm of the vector
+    pub norm: f32,
+}
+
+type WarningHandler = Box<dyn Fn(&NormWarning) + Send + Sync>;
+
+/// An index wrapper which can check that the vectors added to an inner
+/// product index are L2-normalized. The check is disabled by default, and
+/// makes no difference for indexes with other metrics.
+///
+/// When enabled, up to a fixed number of vectors of each added batch are
+/// sampled, and the first one with a norm deviating from 1 by more than the
+/// tolerance is reported to the handler given on construction.
+pub struct NormCheckIndex<I> {
+    index: I,
+    enabled: bool,
+    tolerance: f32,
+    sample_size: usize,
+    on_warning: WarningHandler,
+}
+
+impl<I: Debug> Debug for NormCheckIndex<I> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("NormCheckIndex")
+            .field("index", &self.index)
+            .field("enabled", &self.enabled)
+            .field("tolerance", &self.tolerance)
+            .field("sample_size", &self.sample_size)
+            .finish()
+    }
+}
+
+impl<I> NormCheckIndex<I>
+where
+    I: Index,
+{
+    /// Wrap the given index, with the check disabled. Once enabled, the
+    /// reports of non-normalized vectors are passed to `on_warning`.
+    pub fn new<F>(index: I, on_warning: F) -> Self
+    where
+        F: Fn(&NormWarning) + Send + Sync + 'static,
+    {
+        NormCheckIndex {
+            index,
+            enabled: false,
+            tolerance: 1e-3,
+            sample_size: 16,
+            on_warning: Box::new(on_warning),
+        }
+    }
+
+    /// Enable or disable the check for non-normalized vectors.
+    pub fn warn_if_unnormalized(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// Set the maximum deviation of the norm from 1 before a vector is
+    /// reported. The default is `1e-3`.
+    pub fn tolerance(mut self, tolerance: f32) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Set the maximum number of vectors sampled from each added batch.
+    /// The default is 16.
+    pub fn sample_size(mut self, sample_size: usize) -> Self {
+        self.sample_size = sample_size;
+        self
+    }
+
+    /// Obtain a reference to the wrapped index.
+    pub fn inner(&self) -> &I {
+        &self.index
+    }
+
+    /// Obtain a mutable reference to the wrapped index.
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.index
+    }
+
+    /// Discard the wrapper, recovering the wrapped index.
+    pub fn into_inner(self) -> I {
+        self.index
+    }
+
+    fn check(&self, x: &[f32]) {
+        if !self.enabled || !self.index.metric_type().is_similarity() || self.sample_size == 0 {
+            return;
+        }
+        let d = self.index.d() as usize;
+        if d == 0 {
+            return;
+        }
+        let n = x.len() / d;
+        let step = n / self.sample_size + 1;
+        let warning = (0..n)
+            .step_by(step)
+            .map(|position| {
+                let row = &x[position * d..(position + 1) * d];
+                let norm = row.iter().map(|v| v * v).sum::<f32>().sqrt();
+                NormWarning { position, norm }
+            })
+            .find(|w| (w.norm - 1.).abs() > self.tolerance);
+        if let Some(warning) = warning {
+            (self.on_warning)(&warning);
+        }
+    }
+}
+
+impl<I> Index for NormCheckIndex<I>
+where
+    I: Index,
+{
+    forward_index_methods! {
+        inner, inner_mut;
+        is_trained,
+        ntotal,
+        d,
+        metric_type,
+        train,
+        assign,
+        search,
+        range_search,
+        reset,
+        remove_ids,
+        verbose,
+        set_verbose,
+        sa_code_size,
+        sa_encode,
+        sa_decode_into,
+        reconstruct,
+        reconstruct_n,
+    }
+
+    fn add(&mut self, x: &[f32]) -> Result<()> {
+        self.check(x);
+        self.index.add(x)
+    }
+
+    fn add_with_ids(&mut self, x: &[f32], xids: &[Idx]) -> Result<()> {
+        self.check(x);
+        self.index.add_with_ids(x, xids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NormCheckIndex, NormWarning};
+    use crate::index::flat::FlatIndexImpl;
+    use crate::index::Index;
+    use std::sync::{Arc, Mutex};
+
+    const D: u32 = 4;
+
+    fn capturing(
+        enabled: bool,
+        ip: bool,
+    ) -> (NormCheckIndex<FlatIndexImpl>, Arc<Mutex<Vec<NormWarning>>>) {
+        let warnings = Arc::new(Mutex::new(Vec::new()));
+        let sink = warnings.clone();
+        let flat = if ip {
+            FlatIndexImpl::new_ip(D).unwrap()
+        } else {
+            FlatIndexImpl::new_l2(D).unwrap()
+        };
+        let index = NormCheckIndex::new(flat, move |w| sink.lock().unwrap().push(*w))
+            .warn_if_unnormalized(enabled);
+        (index, warnings)
+    }
+
+    #[test]
+    fn norm_check_unnormalized() {
+        let (mut index, warnings) = capturing(true, true);
+        index
+            .add(&[1., 0., 0., 0., 0., 0.5, 0.5, 0.5, 0., 2., 0., 0.])
+            .unwrap();
+        assert_eq!(index.ntotal(), 3);
+        let warnings = warnings.lock().unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].position, 1);
+        assert!((warnings[0].norm - 0.75_f32.sqrt()).abs() < 1e-6);
+    }
+
+    #[test]
+    fn norm_check_normalized_or_disabled() {
+        let data = &[1., 0., 0., 0., 0., 0.5, 0.5, 0.5];
+
+        let (mut index, warnings) = capturing(true, true);
+        index.add(&data[..4]).unwrap();
+        assert!(warnings.lock().unwrap().is_empty());
+
+        // disabled by default
+        let (mut index, warnings) = capturing(false, true);
+        index.add(data).unwrap();
+        assert!(warnings.lock().unwrap().is_empty());
+
+        // no check for other metrics
+        let (mut index, warnings) = capturing(true, false);
+        index.add(data).unwrap();
+        assert!(warnings.lock().unwrap().is_empty());
+        assert_eq!(index.into_inner().ntotal(), 2);
+    }
+
+    #[test]
+    fn norm_check_sampling_and_tolerance() {
+        // one vector sampled out of three, the first one only
+        let (index, warnings) = capturing(true, true);
+        let mut index = index.sample_size(1);
+        index
+            .add(&[1., 0., 0., 0., 0., 2., 0., 0., 0., 0., 3., 0.])
+            .unwrap();
+        assert!(warnings.lock().unwrap().is_empty());
+
+        let nearly_normalized = &[1.0005, 0., 0., 0.];
+        let (mut index, warnings) = capturing(true, true);
+        index.add(nearly_normalized).unwrap();
+        assert!(warnings.lock().unwrap().is_empty());
+
+        let (index, warnings) = capturing(true, true);
+        let mut index = index.tolerance(1e-4);
+        index.add(nearly_normalized).unwrap();
+        assert_eq!(warnings.lock().unwrap().len(), 1);
+    }
+}
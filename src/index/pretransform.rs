@@ -61,12 +61,18 @@ where
         }
     }
 
+    /// Add a transformation to the front of the chain, so that it is applied
+    /// before all existing transformations. The output dimension of `ltrans`
+    /// must match the current input dimension of the index, which then
+    /// becomes the input dimension of `ltrans`. The index takes ownership of
+    /// the transformation.
     pub fn prepend_transform<LT: NativeVectorTransform>(&mut self, ltrans: LT) -> Result<()> {
         unsafe {
             faiss_try(faiss_IndexPreTransform_prepend_transform(
                 self.inner,
                 ltrans.inner_ptr(),
             ))?;
+            mem::forget(ltrans);
 
             Ok(())
         }
@@ -107,35 +113,38 @@ impl FromInnerPtr for PreTransformIndexImpl<IndexImpl> {
 
 impl<I> Index for PreTransformIndexImpl<I> {
     fn is_trained(&self) -> bool {
-        unsafe { faiss_Index_is_trained(self.inner_ptr()) != 0 }
+        unsafe { faiss_Index_is_trained(live_ptr(self.inner_ptr())) != 0 }
     }
 
     fn ntotal(&self) -> u64 {
-        unsafe { faiss_Index_ntotal(self.inner_ptr()) as u64 }
+        unsafe { faiss_Index_ntotal(live_ptr(self.inner_ptr())) as u64 }
     }
 
     fn d(&self) -> u32 {
-        unsafe { faiss_Index_d(self.inner_ptr()) as u32 }
+        unsafe { faiss_Index_d(live_ptr(self.inner_ptr())) as u32 }
     }
 
     fn metric_type(&self) -> MetricType {
-        unsafe { MetricType::from_code(faiss_Index_metric_type(self.inner_ptr()) as u32).unwrap() }
+        unsafe {
+            MetricType::from_code(faiss_Index_metric_type(live_ptr(self.inner_ptr())) as u32)
+                .unwrap()
+        }
     }
 
     fn add(&mut self, x: &[f32]) -> Result<()> {
         unsafe {
-            let n = x.len() / self.d() as usize;
-            faiss_try(faiss_Index_add(self.inner_ptr(), n as i64, x.as_ptr()))?;
+            let n = checked_idx_t(x.len() / self.d() as usize)?;
+            faiss_try(faiss_Index_add(self.inner_ptr(), n, x.as_ptr()))?;
             Ok(())
         }
     }
 
     fn add_with_ids(&mut self, x: &[f32], xids: &[Idx]) -> Result<()> {
         unsafe {
-            let n = x.len() / self.d() as usize;
+            let n = checked_idx_t(x.len() / self.d() as usize)?;
             faiss_try(faiss_Index_add_with_ids(
                 self.inner_ptr(),
-                n as i64,
+                n,
                 x.as_ptr(),
                 xids.as_ptr() as *const _,
             ))?;
@@ -144,35 +153,42 @@ impl<I> Index for PreTransformIndexImpl<I> {
     }
     fn train(&mut self, x: &[f32]) -> Result<()> {
         unsafe {
-            let n = x.len() / self.d() as usize;
-            faiss_try(faiss_Index_train(self.inner_ptr(), n as i64, x.as_ptr()))?;
+            let n = checked_idx_t(x.len() / self.d() as usize)?;
+            faiss_try(faiss_Index_train(self.inner_ptr(), n, x.as_ptr()))?;
             Ok(())
         }
     }
     fn assign(&mut self, query: &[f32], k: usize) -> Result<AssignSearchResult> {
+        if k == 0 {
+            return Ok(Default::default());
+        }
         unsafe {
             let nq = query.len() / self.d() as usize;
-            let mut out_labels = vec![Idx::none(); k * nq];
+            let mut out_labels = vec![Idx::none(); checked_result_len(nq, k)?];
             faiss_try(faiss_Index_assign(
                 self.inner_ptr(),
-                nq as idx_t,
+                checked_idx_t(nq)?,
                 query.as_ptr(),
                 out_labels.as_mut_ptr() as *mut _,
-                k as i64,
+                checked_idx_t(k)?,
             ))?;
             Ok(AssignSearchResult { labels: out_labels })
         }
     }
     fn search(&mut self, query: &[f32], k: usize) -> Result<SearchResult> {
+        if k == 0 {
+            return Ok(Default::default());
+        }
         unsafe {
             let nq = query.len() / self.d() as usize;
-            let mut distances = vec![0_f32; k * nq];
-            let mut labels = vec![Idx::none(); k * nq];
+            let len = checked_result_len(nq, k)?;
+            let mut distances = vec![0_f32; len];
+            let mut labels = vec![Idx::none(); len];
             faiss_try(faiss_Index_search(
                 self.inner_ptr(),
-                nq as idx_t,
+                checked_idx_t(nq)?,
                 query.as_ptr(),
-                k as idx_t,
+                checked_idx_t(k)?,
                 distances.as_mut_ptr(),
                 labels.as_mut_ptr() as *mut _,
             ))?;
@@ -181,7 +197,7 @@ impl<I> Index for PreTransformIndexImpl<I> {
     }
     fn range_search(&mut self, query: &[f32], radius: f32) -> Result<RangeSearchResult> {
         unsafe {
-            let nq = (query.len() / self.d() as usize) as idx_t;
+            let nq = checked_idx_t(query.len() / self.d() as usize)?;
             let mut p_res: *mut FaissRangeSearchResult = ::std::ptr::null_mut();
             faiss_try(faiss_RangeSearchResult_new(&mut p_res, nq))?;
             faiss_try(faiss_Index_range_search(
@@ -223,6 +239,66 @@ impl<I> Index for PreTransformIndexImpl<I> {
             faiss_Index_set_verbose(self.inner, std::os::raw::c_int::from(value));
         }
     }
+
+    fn sa_code_size(&self) -> Result<usize> {
+        unsafe {
+            let mut size = 0;
+            faiss_try(faiss_Index_sa_code_size(self.inner_ptr(), &mut size))?;
+            Ok(size)
+        }
+    }
+
+    fn sa_encode(&self, x: &[f32]) -> Result<Vec<u8>> {
+        unsafe {
+            let n = x.len() / self.d() as usize;
+            let mut codes = vec![0_u8; checked_result_len(n, self.sa_code_size()?)?];
+            faiss_try(faiss_Index_sa_encode(
+                self.inner_ptr(),
+                checked_idx_t(n)?,
+                x.as_ptr(),
+                codes.as_mut_ptr(),
+            ))?;
+            Ok(codes)
+        }
+    }
+
+    fn sa_decode_into(&self, codes: &[u8], out: &mut [f32]) -> Result<()> {
+        unsafe {
+            let n = checked_decode_len(self.sa_code_size()?, self.d(), codes.len(), out.len())?;
+            faiss_try(faiss_Index_sa_decode(
+                self.inner_ptr(),
+                n,
+                codes.as_ptr(),
+                out.as_mut_ptr(),
+            ))?;
+            Ok(())
+        }
+    }
+
+    fn reconstruct(&self, key: Idx) -> Result<Vec<f32>> {
+        unsafe {
+            let mut output = vec![0_f32; self.d() as usize];
+            faiss_try(faiss_Index_reconstruct(
+                self.inner_ptr(),
+                key.to_native(),
+                output.as_mut_ptr(),
+            ))?;
+            Ok(output)
+        }
+    }
+
+    fn reconstruct_n(&self, first_key: Idx, count: usize) -> Result<Vec<f32>> {
+        unsafe {
+            let mut output = vec![0_f32; checked_result_len(count, self.d() as usize)?];
+            faiss_try(faiss_Index_reconstruct_n(
+                self.inner_ptr(),
+                first_key.to_native(),
+                checked_idx_t(count)?,
+                output.as_mut_ptr(),
+            ))?;
+            Ok(output)
+        }
+    }
 }
 
 impl<I> TryClone for PreTransformIndexImpl<I> {
@@ -246,29 +322,36 @@ where
     I: ConcurrentIndex,
 {
     fn assign(&self, query: &[f32], k: usize) -> Result<AssignSearchResult> {
+        if k == 0 {
+            return Ok(Default::default());
+        }
         unsafe {
             let nq = query.len() / self.d() as usize;
-            let mut out_labels = vec![Idx::none(); k * nq];
+            let mut out_labels = vec![Idx::none(); checked_result_len(nq, k)?];
             faiss_try(faiss_Index_assign(
                 self.inner,
-                nq as idx_t,
+                checked_idx_t(nq)?,
                 query.as_ptr(),
                 out_labels.as_mut_ptr() as *mut _,
-                k as i64,
+                checked_idx_t(k)?,
             ))?;
             Ok(AssignSearchResult { labels: out_labels })
         }
     }
     fn search(&self, query: &[f32], k: usize) -> Result<SearchResult> {
+        if k == 0 {
+            return Ok(Default::default());
+        }
         unsafe {
             let nq = query.len() / self.d() as usize;
-            let mut distances = vec![0_f32; k * nq];
-            let mut labels = vec![Idx::none(); k * nq];
+            let len = checked_result_len(nq, k)?;
+            let mut distances = vec![0_f32; len];
+            let mut labels = vec![Idx::none(); len];
             faiss_try(faiss_Index_search(
                 self.inner,
-                nq as idx_t,
+                checked_idx_t(nq)?,
                 query.as_ptr(),
-                k as idx_t,
+                checked_idx_t(k)?,
                 distances.as_mut_ptr(),
                 labels.as_mut_ptr() as *mut _,
             ))?;
@@ -277,7 +360,7 @@ where
     }
     fn range_search(&self, query: &[f32], radius: f32) -> Result<RangeSearchResult> {
         unsafe {
-            let nq = (query.len() / self.d() as usize) as idx_t;
+            let nq = checked_idx_t(query.len() / self.d() as usize)?;
             let mut p_res: *mut FaissRangeSearchResult = ptr::null_mut();
             faiss_try(faiss_RangeSearchResult_new(&mut p_res, nq))?;
             faiss_try(faiss_Index_range_search(
@@ -299,7 +382,7 @@ mod tests {
     use crate::metric::MetricType;
     use crate::{
         index::{index_factory, ConcurrentIndex, Idx, Index},
-        vector_transform::PCAMatrixImpl,
+        vector_transform::{NormalizationTransformImpl, PCAMatrixImpl},
     };
 
     const D: u32 = 8;
@@ -377,4 +460,39 @@ mod tests {
         pre_transform_index.reset().unwrap();
         assert_eq!(pre_transform_index.ntotal(), 0);
     }
+
+    #[test]
+    fn pre_transform_index_prepend_transform() {
+        const D_OUT: u32 = D / 2;
+        let index = crate::index::flat::FlatIndexImpl::new_l2(D_OUT).unwrap();
+        let some_data = &[
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 1., 1., 1., -1., 0., 0.,
+            0., 1., 1., 0., 0., -1., 100., 100., 100., 100., -100., 100., 100., 100., 120., 100.,
+            100., 105., -100., 100., 100., 105.,
+        ];
+
+        let norm = NormalizationTransformImpl::new(D_OUT, 2.).unwrap();
+        let mut pre_transform_index = PreTransformIndexImpl::new(norm, index).unwrap();
+        assert_eq!(pre_transform_index.d(), D_OUT);
+
+        // PCA runs first, then the normalization
+        let pca = PCAMatrixImpl::new(D, D_OUT, 0f32, false).unwrap();
+        pre_transform_index.prepend_transform(pca).unwrap();
+        assert_eq!(pre_transform_index.d(), D);
+
+        if !pre_transform_index.is_trained() {
+            pre_transform_index.train(some_data).unwrap();
+        }
+        pre_transform_index.add(some_data).unwrap();
+        assert_eq!(pre_transform_index.ntotal(), 5);
+
+        // all vectors reach the flat index reduced and normalized, so
+        // the squared distances between them are at most 4
+        let result = pre_transform_index
+            .search(&some_data[..D as usize], 5)
+            .unwrap();
+        assert_eq!(result.labels[0], Idx::new(0));
+        assert!(result.distances[0].abs() < 1e-4);
+        assert!(result.distances.iter().all(|&x| x <= 4. + 1e-4));
+    }
 }
@@ -1,5 +1,6 @@
 //! Interface and implementation to RefineFlat index type.
 
+use super::autotune::ParameterSpace;
 use super::*;
 
 use std::marker::PhantomData;
@@ -59,6 +60,19 @@ impl<BI: NativeIndex> RefineFlatIndexImpl<BI> {
     pub fn k_factor(&self) -> f32 {
         unsafe { faiss_IndexRefineFlat_k_factor(self.inner_ptr()) }
     }
+
+    /// Set a search parameter of the base index, such as `nprobe` for an
+    /// IVF base index, which the refinement step would otherwise hide.
+    /// Parameter names are the same as those accepted by
+    /// [`ParameterSpace`](../autotune/struct.ParameterSpace.html).
+    pub fn set_base_parameter<V>(&mut self, name: &str, value: V) -> Result<()>
+    where
+        V: Into<f64>,
+    {
+        // the native parameter space forwards any parameter other than
+        // `k_factor_rf` to the base index of a refine index
+        ParameterSpace::new()?.set_index_parameter(self, name, value)
+    }
 }
 
 impl<BI> NativeIndex for RefineFlatIndexImpl<BI> {
@@ -100,35 +114,38 @@ impl TryFromInnerPtr for RefineFlatIndexImpl<IndexImpl> {
 
 impl<BI> Index for RefineFlatIndexImpl<BI> {
     fn is_trained(&self) -> bool {
-        unsafe { faiss_Index_is_trained(self.inner_ptr()) != 0 }
+        unsafe { faiss_Index_is_trained(live_ptr(self.inner_ptr())) != 0 }
     }
 
     fn ntotal(&self) -> u64 {
-        unsafe { faiss_Index_ntotal(self.inner_ptr()) as u64 }
+        unsafe { faiss_Index_ntotal(live_ptr(self.inner_ptr())) as u64 }
     }
 
     fn d(&self) -> u32 {
-        unsafe { faiss_Index_d(self.inner_ptr()) as u32 }
+        unsafe { faiss_Index_d(live_ptr(self.inner_ptr())) as u32 }
     }
 
     fn metric_type(&self) -> MetricType {
-        unsafe { MetricType::from_code(faiss_Index_metric_type(self.inner_ptr()) as u32).unwrap() }
+        unsafe {
+            MetricType::from_code(faiss_Index_metric_type(live_ptr(self.inner_ptr())) as u32)
+                .unwrap()
+        }
     }
 
     fn add(&mut self, x: &[f32]) -> Result<()> {
         unsafe {
-            let n = x.len() / self.d() as usize;
-            faiss_try(faiss_Index_add(self.inner_ptr(), n as i64, x.as_ptr()))?;
+            let n = checked_idx_t(x.len() / self.d() as usize)?;
+            faiss_try(faiss_Index_add(self.inner_ptr(), n, x.as_ptr()))?;
             Ok(())
         }
     }
 
     fn add_with_ids(&mut self, x: &[f32], xids: &[Idx]) -> Result<()> {
         unsafe {
-            let n = x.len() / self.d() as usize;
+            let n = checked_idx_t(x.len() / self.d() as usize)?;
             faiss_try(faiss_Index_add_with_ids(
                 self.inner_ptr(),
-                n as i64,
+                n,
                 x.as_ptr(),
                 xids.as_ptr() as *const _,
             ))?;
@@ -137,35 +154,42 @@ impl<BI> Index for RefineFlatIndexImpl<BI> {
     }
     fn train(&mut self, x: &[f32]) -> Result<()> {
         unsafe {
-            let n = x.len() / self.d() as usize;
-            faiss_try(faiss_Index_train(self.inner_ptr(), n as i64, x.as_ptr()))?;
+            let n = checked_idx_t(x.len() / self.d() as usize)?;
+            faiss_try(faiss_Index_train(self.inner_ptr(), n, x.as_ptr()))?;
             Ok(())
         }
     }
     fn assign(&mut self, query: &[f32], k: usize) -> Result<AssignSearchResult> {
+        if k == 0 {
+            return Ok(Default::default());
+        }
         unsafe {
             let nq = query.len() / self.d() as usize;
-            let mut out_labels = vec![Idx::none(); k * nq];
+            let mut out_labels = vec![Idx::none(); checked_result_len(nq, k)?];
             faiss_try(faiss_Index_assign(
                 self.inner_ptr(),
-                nq as idx_t,
+                checked_idx_t(nq)?,
                 query.as_ptr(),
                 out_labels.as_mut_ptr() as *mut _,
-                k as i64,
+                checked_idx_t(k)?,
             ))?;
             Ok(AssignSearchResult { labels: out_labels })
         }
     }
     fn search(&mut self, query: &[f32], k: usize) -> Result<SearchResult> {
+        if k == 0 {
+            return Ok(Default::default());
+        }
         unsafe {
             let nq = query.len() / self.d() as usize;
-            let mut distances = vec![0_f32; k * nq];
-            let mut labels = vec![Idx::none(); k * nq];
+            let len = checked_result_len(nq, k)?;
+            let mut distances = vec![0_f32; len];
+            let mut labels = vec![Idx::none(); len];
             faiss_try(faiss_Index_search(
                 self.inner_ptr(),
-                nq as idx_t,
+                checked_idx_t(nq)?,
                 query.as_ptr(),
-                k as idx_t,
+                checked_idx_t(k)?,
                 distances.as_mut_ptr(),
                 labels.as_mut_ptr() as *mut _,
             ))?;
@@ -174,7 +198,7 @@ impl<BI> Index for RefineFlatIndexImpl<BI> {
     }
     fn range_search(&mut self, query: &[f32], radius: f32) -> Result<RangeSearchResult> {
         unsafe {
-            let nq = (query.len() / self.d() as usize) as idx_t;
+            let nq = checked_idx_t(query.len() / self.d() as usize)?;
             let mut p_res: *mut FaissRangeSearchResult = ::std::ptr::null_mut();
             faiss_try(faiss_RangeSearchResult_new(&mut p_res, nq))?;
             faiss_try(faiss_Index_range_search(
@@ -216,6 +240,66 @@ impl<BI> Index for RefineFlatIndexImpl<BI> {
             faiss_Index_set_verbose(self.inner, std::os::raw::c_int::from(value));
         }
     }
+
+    fn sa_code_size(&self) -> Result<usize> {
+        unsafe {
+            let mut size = 0;
+            faiss_try(faiss_Index_sa_code_size(self.inner_ptr(), &mut size))?;
+            Ok(size)
+        }
+    }
+
+    fn sa_encode(&self, x: &[f32]) -> Result<Vec<u8>> {
+        unsafe {
+            let n = x.len() / self.d() as usize;
+            let mut codes = vec![0_u8; checked_result_len(n, self.sa_code_size()?)?];
+            faiss_try(faiss_Index_sa_encode(
+                self.inner_ptr(),
+                checked_idx_t(n)?,
+                x.as_ptr(),
+                codes.as_mut_ptr(),
+            ))?;
+            Ok(codes)
+        }
+    }
+
+    fn sa_decode_into(&self, codes: &[u8], out: &mut [f32]) -> Result<()> {
+        unsafe {
+            let n = checked_decode_len(self.sa_code_size()?, self.d(), codes.len(), out.len())?;
+            faiss_try(faiss_Index_sa_decode(
+                self.inner_ptr(),
+                n,
+                codes.as_ptr(),
+                out.as_mut_ptr(),
+            ))?;
+            Ok(())
+        }
+    }
+
+    fn reconstruct(&self, key: Idx) -> Result<Vec<f32>> {
+        unsafe {
+            let mut output = vec![0_f32; self.d() as usize];
+            faiss_try(faiss_Index_reconstruct(
+                self.inner_ptr(),
+                key.to_native(),
+                output.as_mut_ptr(),
+            ))?;
+            Ok(output)
+        }
+    }
+
+    fn reconstruct_n(&self, first_key: Idx, count: usize) -> Result<Vec<f32>> {
+        unsafe {
+            let mut output = vec![0_f32; checked_result_len(count, self.d() as usize)?];
+            faiss_try(faiss_Index_reconstruct_n(
+                self.inner_ptr(),
+                first_key.to_native(),
+                checked_idx_t(count)?,
+                output.as_mut_ptr(),
+            ))?;
+            Ok(output)
+        }
+    }
 }
 
 impl<I> TryClone for RefineFlatIndexImpl<I> {
@@ -239,29 +323,36 @@ where
     BI: ConcurrentIndex,
 {
     fn assign(&self, query: &[f32], k: usize) -> Result<AssignSearchResult> {
+        if k == 0 {
+            return Ok(Default::default());
+        }
         unsafe {
             let nq = query.len() / self.d() as usize;
-            let mut out_labels = vec![Idx::none(); k * nq];
+            let mut out_labels = vec![Idx::none(); checked_result_len(nq, k)?];
             faiss_try(faiss_Index_assign(
                 self.inner,
-                nq as idx_t,
+                checked_idx_t(nq)?,
                 query.as_ptr(),
                 out_labels.as_mut_ptr() as *mut _,
-                k as i64,
+                checked_idx_t(k)?,
             ))?;
             Ok(AssignSearchResult { labels: out_labels })
         }
     }
     fn search(&self, query: &[f32], k: usize) -> Result<SearchResult> {
+        if k == 0 {
+            return Ok(Default::default());
+        }
         unsafe {
             let nq = query.len() / self.d() as usize;
-            let mut distances = vec![0_f32; k * nq];
-            let mut labels = vec![Idx::none(); k * nq];
+            let len = checked_result_len(nq, k)?;
+            let mut distances = vec![0_f32; len];
+            let mut labels = vec![Idx::none(); len];
             faiss_try(faiss_Index_search(
                 self.inner,
-                nq as idx_t,
+                checked_idx_t(nq)?,
                 query.as_ptr(),
-                k as idx_t,
+                checked_idx_t(k)?,
                 distances.as_mut_ptr(),
                 labels.as_mut_ptr() as *mut _,
             ))?;
@@ -270,7 +361,7 @@ where
     }
     fn range_search(&self, query: &[f32], radius: f32) -> Result<RangeSearchResult> {
         unsafe {
-            let nq = (query.len() / self.d() as usize) as idx_t;
+            let nq = checked_idx_t(query.len() / self.d() as usize)?;
             let mut p_res: *mut FaissRangeSearchResult = ptr::null_mut();
             faiss_try(faiss_RangeSearchResult_new(&mut p_res, nq))?;
             faiss_try(faiss_Index_range_search(
@@ -288,6 +379,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::RefineFlatIndexImpl;
+    use crate::index::ivf_flat::IVFFlatIndexImpl;
     use crate::index::{flat::FlatIndexImpl, ConcurrentIndex, Idx, Index, UpcastIndex};
 
     const D: u32 = 8;
@@ -345,4 +437,27 @@ mod tests {
         let index_impl = refine.upcast();
         assert_eq!(index_impl.d(), D);
     }
+
+    #[test]
+    fn refine_flat_index_base_nprobe() {
+        // a quantizer with fixed centroids, so that the lists are known
+        let mut quantizer = FlatIndexImpl::new_l2(2).unwrap();
+        quantizer.add(&[0., 0., 10., 0.]).unwrap();
+        let base = IVFFlatIndexImpl::new_l2(quantizer, 2, 2).unwrap();
+        let mut refine = RefineFlatIndexImpl::new(base).unwrap();
+        let some_data = &[1., 0., 6., 0.];
+        refine.train(some_data).unwrap();
+        refine.add(some_data).unwrap();
+
+        // the query is closest to the first centroid,
+        // but its nearest neighbor is in the second list
+        let my_query = [4., 0.];
+        refine.set_base_parameter("nprobe", 1).unwrap();
+        let result = refine.search(&my_query, 1).unwrap();
+        assert_eq!(result.labels, vec![Idx::new(0)]);
+
+        refine.set_base_parameter("nprobe", 2).unwrap();
+        let result = refine.search(&my_query, 1).unwrap();
+        assert_eq!(result.labels, vec![Idx::new(1)]);
+    }
 }
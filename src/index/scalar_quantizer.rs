@@ -176,7 +176,7 @@ where
         encode_residual: Option<bool>,
     ) -> Result<Self> {
         let index = IVFScalarQuantizerIndexImpl::new_helper(
-            &quantizer,
+            quantizer.inner_ptr(),
             d,
             qt,
             nlist,
@@ -207,9 +207,34 @@ where
             None,
         )
     }
+}
+
+impl<Q> IVFScalarQuantizerIndexImpl<Q> {
+    /// Get number of possible key values
+    pub fn nlist(&self) -> u32 {
+        unsafe { faiss_IndexIVFScalarQuantizer_nlist(self.inner_ptr()) as u32 }
+    }
+
+    /// Get number of probes at query time
+    pub fn nprobe(&self) -> u32 {
+        unsafe { faiss_IndexIVFScalarQuantizer_nprobe(self.inner_ptr()) as u32 }
+    }
+
+    /// Set number of probes at query time
+    pub fn set_nprobe(&mut self, value: u32) {
+        unsafe {
+            faiss_IndexIVFScalarQuantizer_set_nprobe(self.inner_ptr(), value as usize);
+        }
+    }
+
+    /// Set number of probes at query time, returning the index for chaining
+    pub fn with_nprobe(mut self, value: u32) -> Self {
+        self.set_nprobe(value);
+        self
+    }
 
     fn new_helper(
-        quantizer: &Q,
+        quantizer: *mut FaissIndex,
         d: u32,
         qt: QuantizerType,
         nlist: u32,
@@ -221,11 +246,10 @@ where
             let metric_ = metric as c_uint;
             let qt_ = qt as c_uint;
             let mut inner = ptr::null_mut();
-            let quantizer_ = quantizer.inner_ptr();
             let encode_residual_ = c_int::from(encode_residual.unwrap_or(true));
             faiss_try(faiss_IndexIVFScalarQuantizer_new_with_metric(
                 &mut inner,
-                quantizer_,
+                quantizer,
                 d as usize,
                 nlist as usize,
                 qt_,
@@ -240,22 +264,42 @@ where
             })
         }
     }
+}
 
-    /// Get number of possible key values
-    pub fn nlist(&self) -> u32 {
-        unsafe { faiss_IndexIVFScalarQuantizer_nlist(self.inner_ptr()) as u32 }
-    }
-
-    /// Get number of probes at query time
-    pub fn nprobe(&self) -> u32 {
-        unsafe { faiss_IndexIVFScalarQuantizer_nprobe(self.inner_ptr()) as u32 }
-    }
-
-    /// Set number of probes at query time
-    pub fn set_nprobe(&mut self, value: u32) {
-        unsafe {
-            faiss_IndexIVFScalarQuantizer_set_nprobe(self.inner_ptr(), value as usize);
+impl<'q, Q> IVFScalarQuantizerIndexImpl<&'q Q>
+where
+    Q: NativeIndex,
+{
+    /// Create a new IVF scalar quantizer index which borrows an already
+    /// trained quantizer, so that the same quantizer can be shared by
+    /// several indexes. Indexes built this way assign vectors to the same
+    /// inverted lists, and so they can be merged with one another.
+    /// The index does not own the quantizer.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::BadQuantizer` unless the quantizer is trained and
+    /// holds exactly `nlist` centroids, since training the index must not
+    /// modify a shared quantizer.
+    pub fn new_shared(
+        quantizer: &'q Q,
+        d: u32,
+        qt: QuantizerType,
+        nlist: u32,
+        metric: MetricType,
+    ) -> Result<Self> {
+        if !quantizer.is_trained() || quantizer.ntotal() != u64::from(nlist) {
+            return Err(Error::BadQuantizer);
         }
+        IVFScalarQuantizerIndexImpl::new_helper(
+            quantizer.inner_ptr(),
+            d,
+            qt,
+            nlist,
+            metric,
+            None,
+            false,
+        )
     }
 }
 
@@ -276,35 +320,38 @@ impl FromInnerPtr for IVFScalarQuantizerIndexImpl<IndexImpl> {
 
 impl<Q> Index for IVFScalarQuantizerIndexImpl<Q> {
     fn is_trained(&self) -> bool {
-        unsafe { faiss_Index_is_trained(self.inner_ptr()) != 0 }
+        unsafe { faiss_Index_is_trained(live_ptr(self.inner_ptr())) != 0 }
     }
 
     fn ntotal(&self) -> u64 {
-        unsafe { faiss_Index_ntotal(self.inner_ptr()) as u64 }
+        unsafe { faiss_Index_ntotal(live_ptr(self.inner_ptr())) as u64 }
     }
 
     fn d(&self) -> u32 {
-        unsafe { faiss_Index_d(self.inner_ptr()) as u32 }
+        unsafe { faiss_Index_d(live_ptr(self.inner_ptr())) as u32 }
     }
 
     fn metric_type(&self) -> MetricType {
-        unsafe { MetricType::from_code(faiss_Index_metric_type(self.inner_ptr()) as u32).unwrap() }
+        unsafe {
+            MetricType::from_code(faiss_Index_metric_type(live_ptr(self.inner_ptr())) as u32)
+                .unwrap()
+        }
     }
 
     fn add(&mut self, x: &[f32]) -> Result<()> {
         unsafe {
-            let n = x.len() / self.d() as usize;
-            faiss_try(faiss_Index_add(self.inner_ptr(), n as i64, x.as_ptr()))?;
+            let n = checked_idx_t(x.len() / self.d() as usize)?;
+            faiss_try(faiss_Index_add(self.inner_ptr(), n, x.as_ptr()))?;
             Ok(())
         }
     }
 
     fn add_with_ids(&mut self, x: &[f32], xids: &[Idx]) -> Result<()> {
         unsafe {
-            let n = x.len() / self.d() as usize;
+            let n = checked_idx_t(x.len() / self.d() as usize)?;
             faiss_try(faiss_Index_add_with_ids(
                 self.inner_ptr(),
-                n as i64,
+                n,
                 x.as_ptr(),
                 xids.as_ptr() as *const _,
             ))?;
@@ -313,35 +360,42 @@ impl<Q> Index for IVFScalarQuantizerIndexImpl<Q> {
     }
     fn train(&mut self, x: &[f32]) -> Result<()> {
         unsafe {
-            let n = x.len() / self.d() as usize;
-            faiss_try(faiss_Index_train(self.inner_ptr(), n as i64, x.as_ptr()))?;
+            let n = checked_idx_t(x.len() / self.d() as usize)?;
+            faiss_try(faiss_Index_train(self.inner_ptr(), n, x.as_ptr()))?;
             Ok(())
         }
     }
     fn assign(&mut self, query: &[f32], k: usize) -> Result<AssignSearchResult> {
+        if k == 0 {
+            return Ok(Default::default());
+        }
         unsafe {
             let nq = query.len() / self.d() as usize;
-            let mut out_labels = vec![Idx::none(); k * nq];
+            let mut out_labels = vec![Idx::none(); checked_result_len(nq, k)?];
             faiss_try(faiss_Index_assign(
                 self.inner_ptr(),
-                nq as idx_t,
+                checked_idx_t(nq)?,
                 query.as_ptr(),
                 out_labels.as_mut_ptr() as *mut _,
-                k as i64,
+                checked_idx_t(k)?,
             ))?;
             Ok(AssignSearchResult { labels: out_labels })
         }
     }
     fn search(&mut self, query: &[f32], k: usize) -> Result<SearchResult> {
+        if k == 0 {
+            return Ok(Default::default());
+        }
         unsafe {
             let nq = query.len() / self.d() as usize;
-            let mut distances = vec![0_f32; k * nq];
-            let mut labels = vec![Idx::none(); k * nq];
+            let len = checked_result_len(nq, k)?;
+            let mut distances = vec![0_f32; len];
+            let mut labels = vec![Idx::none(); len];
             faiss_try(faiss_Index_search(
                 self.inner_ptr(),
-                nq as idx_t,
+                checked_idx_t(nq)?,
                 query.as_ptr(),
-                k as idx_t,
+                checked_idx_t(k)?,
                 distances.as_mut_ptr(),
                 labels.as_mut_ptr() as *mut _,
             ))?;
@@ -350,7 +404,7 @@ impl<Q> Index for IVFScalarQuantizerIndexImpl<Q> {
     }
     fn range_search(&mut self, query: &[f32], radius: f32) -> Result<RangeSearchResult> {
         unsafe {
-            let nq = (query.len() / self.d() as usize) as idx_t;
+            let nq = checked_idx_t(query.len() / self.d() as usize)?;
             let mut p_res: *mut FaissRangeSearchResult = ::std::ptr::null_mut();
             faiss_try(faiss_RangeSearchResult_new(&mut p_res, nq))?;
             faiss_try(faiss_Index_range_search(
@@ -392,8 +446,70 @@ impl<Q> Index for IVFScalarQuantizerIndexImpl<Q> {
             faiss_Index_set_verbose(self.inner, std::os::raw::c_int::from(value));
         }
     }
+
+    fn sa_code_size(&self) -> Result<usize> {
+        unsafe {
+            let mut size = 0;
+            faiss_try(faiss_Index_sa_code_size(self.inner_ptr(), &mut size))?;
+            Ok(size)
+        }
+    }
+
+    fn sa_encode(&self, x: &[f32]) -> Result<Vec<u8>> {
+        unsafe {
+            let n = x.len() / self.d() as usize;
+            let mut codes = vec![0_u8; checked_result_len(n, self.sa_code_size()?)?];
+            faiss_try(faiss_Index_sa_encode(
+                self.inner_ptr(),
+                checked_idx_t(n)?,
+                x.as_ptr(),
+                codes.as_mut_ptr(),
+            ))?;
+            Ok(codes)
+        }
+    }
+
+    fn sa_decode_into(&self, codes: &[u8], out: &mut [f32]) -> Result<()> {
+        unsafe {
+            let n = checked_decode_len(self.sa_code_size()?, self.d(), codes.len(), out.len())?;
+            faiss_try(faiss_Index_sa_decode(
+                self.inner_ptr(),
+                n,
+                codes.as_ptr(),
+                out.as_mut_ptr(),
+            ))?;
+            Ok(())
+        }
+    }
+
+    fn reconstruct(&self, key: Idx) -> Result<Vec<f32>> {
+        unsafe {
+            let mut output = vec![0_f32; self.d() as usize];
+            faiss_try(faiss_Index_reconstruct(
+                self.inner_ptr(),
+                key.to_native(),
+                output.as_mut_ptr(),
+            ))?;
+            Ok(output)
+        }
+    }
+
+    fn reconstruct_n(&self, first_key: Idx, count: usize) -> Result<Vec<f32>> {
+        unsafe {
+            let mut output = vec![0_f32; checked_result_len(count, self.d() as usize)?];
+            faiss_try(faiss_Index_reconstruct_n(
+                self.inner_ptr(),
+                first_key.to_native(),
+                checked_idx_t(count)?,
+                output.as_mut_ptr(),
+            ))?;
+            Ok(output)
+        }
+    }
 }
 
+impl<Q> ivf::IVFIndex for IVFScalarQuantizerIndexImpl<Q> {}
+
 impl<Q> TryClone for IVFScalarQuantizerIndexImpl<Q> {
     fn try_clone(&self) -> Result<Self>
     where
@@ -415,29 +531,36 @@ where
     Q: ConcurrentIndex,
 {
     fn assign(&self, query: &[f32], k: usize) -> Result<AssignSearchResult> {
+        if k == 0 {
+            return Ok(Default::default());
+        }
         unsafe {
             let nq = query.len() / self.d() as usize;
-            let mut out_labels = vec![Idx::none(); k * nq];
+            let mut out_labels = vec![Idx::none(); checked_result_len(nq, k)?];
             faiss_try(faiss_Index_assign(
                 self.inner,
-                nq as idx_t,
+                checked_idx_t(nq)?,
                 query.as_ptr(),
                 out_labels.as_mut_ptr() as *mut _,
-                k as i64,
+                checked_idx_t(k)?,
             ))?;
             Ok(AssignSearchResult { labels: out_labels })
         }
     }
     fn search(&self, query: &[f32], k: usize) -> Result<SearchResult> {
+        if k == 0 {
+            return Ok(Default::default());
+        }
         unsafe {
             let nq = query.len() / self.d() as usize;
-            let mut distances = vec![0_f32; k * nq];
-            let mut labels = vec![Idx::none(); k * nq];
+            let len = checked_result_len(nq, k)?;
+            let mut distances = vec![0_f32; len];
+            let mut labels = vec![Idx::none(); len];
             faiss_try(faiss_Index_search(
                 self.inner,
-                nq as idx_t,
+                checked_idx_t(nq)?,
                 query.as_ptr(),
-                k as idx_t,
+                checked_idx_t(k)?,
                 distances.as_mut_ptr(),
                 labels.as_mut_ptr() as *mut _,
             ))?;
@@ -446,7 +569,7 @@ where
     }
     fn range_search(&self, query: &[f32], radius: f32) -> Result<RangeSearchResult> {
         unsafe {
-            let nq = (query.len() / self.d() as usize) as idx_t;
+            let nq = checked_idx_t(query.len() / self.d() as usize)?;
             let mut p_res: *mut FaissRangeSearchResult = ptr::null_mut();
             faiss_try(faiss_RangeSearchResult_new(&mut p_res, nq))?;
             faiss_try(faiss_Index_range_search(
@@ -482,7 +605,10 @@ impl IndexImpl {
 #[cfg(test)]
 mod tests {
     use super::{IVFScalarQuantizerIndexImpl, QuantizerType, ScalarQuantizerIndexImpl};
-    use crate::index::{flat, index_factory, ConcurrentIndex, Idx, Index, UpcastIndex};
+    use crate::error::Error;
+    use crate::index::io::write_index_to_writer;
+    use crate::index::ivf::IVFIndex;
+    use crate::index::{flat, index_factory, ConcurrentIndex, Idx, Index, IndexExt, UpcastIndex};
     use crate::metric::MetricType;
 
     const D: u32 = 8;
@@ -528,6 +654,45 @@ mod tests {
         assert_eq!(index.ntotal(), 0);
     }
 
+    #[test]
+    fn sq_index_sa_decode_into() {
+        let index =
+            ScalarQuantizerIndexImpl::new(D, QuantizerType::QT_fp16, MetricType::L2).unwrap();
+        let some_data = &[
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 1., 1., 1., -1., 0., 0.,
+            0., 1., 1., 0., 0., -1., 100., 100., 100., 100., -100., 100., 100., 100., 120., 100.,
+            100., 105., -100., 100., 100., 105.,
+        ];
+        let code_size = index.sa_code_size().unwrap();
+        assert_eq!(code_size, 2 * D as usize);
+        let codes = index.sa_encode(some_data).unwrap();
+        assert_eq!(codes.len(), 5 * code_size);
+
+        let decoded = index.sa_decode(&codes).unwrap();
+        let mut out = vec![0_f32; 5 * D as usize];
+        index.sa_decode_into(&codes, &mut out).unwrap();
+        assert_eq!(out, decoded);
+        // fp16 is exact for these values
+        assert_eq!(&out[..], &some_data[..]);
+
+        // the buffer can be reused for a smaller batch
+        let mut out = vec![0_f32; 2 * D as usize];
+        index
+            .sa_decode_into(&codes[..2 * code_size], &mut out)
+            .unwrap();
+        assert_eq!(&out[..], &decoded[..2 * D as usize]);
+
+        // partial codes and mismatching buffers are rejected
+        assert_eq!(
+            index.sa_decode_into(&codes[..code_size + 1], &mut out),
+            Err(Error::BadCodeSize)
+        );
+        assert_eq!(
+            index.sa_decode_into(&codes, &mut out),
+            Err(Error::BadDimension)
+        );
+    }
+
     #[test]
     fn ivf_sq_index_nlist() {
         let quantizer = flat::FlatIndex::new_l2(D).unwrap();
@@ -682,4 +847,117 @@ mod tests {
         let index_impl = index.upcast();
         assert_eq!(index_impl.d(), D);
     }
+
+    #[test]
+    fn ivf_sq_shared_quantizer_shards() {
+        let mut quantizer = flat::FlatIndexImpl::new_l2(D).unwrap();
+        let mut centroids = vec![0.; D as usize];
+        centroids.extend(vec![100.; D as usize]);
+        quantizer.add(&centroids).unwrap();
+
+        let new_shard = || {
+            IVFScalarQuantizerIndexImpl::new_shared(
+                &quantizer,
+                D,
+                QuantizerType::QT_fp16,
+                2,
+                MetricType::L2,
+            )
+            .unwrap()
+        };
+        let mut shard1 = new_shard();
+        let mut shard2 = new_shard();
+        assert!(shard1.is_trained());
+        shard1.set_nprobe(2);
+
+        let data1 = &[
+            0., 0., 0., 0., 0., 0., 0., 1., 100., 100., 100., 100., 100., 100., 100., 101.,
+        ];
+        let data2 = &[
+            0., 0., 0., 0., 0., 0., 0., 2., 100., 100., 100., 100., 100., 100., 100., 102.,
+        ];
+        shard1.add(data1).unwrap();
+        shard2.add(data2).unwrap();
+        shard1.merge_from(&mut shard2, 2).unwrap();
+        assert_eq!(shard1.ntotal(), 4);
+        assert_eq!(shard2.ntotal(), 0);
+
+        let my_query = [0.; D as usize];
+        let result = shard1.search(&my_query, 4).unwrap();
+        assert_eq!(
+            result.labels,
+            vec![Idx::new(0), Idx::new(2), Idx::new(1), Idx::new(3)]
+        );
+
+        // the shared quantizer cannot be modified through a shard
+        assert!(matches!(
+            shard1.train_quantizer(data1),
+            Err(Error::Unsupported(_))
+        ));
+        assert_eq!(quantizer.ntotal(), 2);
+
+        // the quantizer must already be trained with nlist centroids
+        let empty = flat::FlatIndexImpl::new_l2(D).unwrap();
+        assert_eq!(
+            IVFScalarQuantizerIndexImpl::new_shared(
+                &empty,
+                D,
+                QuantizerType::QT_fp16,
+                2,
+                MetricType::L2
+            )
+            .unwrap_err(),
+            Error::BadQuantizer
+        );
+    }
+
+    #[test]
+    fn ivf_sq_train_encoder() {
+        let some_data: Vec<f32> = (0..16 * D).map(|i| ((i * 13) % 29) as f32 - 14.).collect();
+        let serialized = |index: &IVFScalarQuantizerIndexImpl<flat::FlatIndexImpl>| {
+            let mut bytes = Vec::new();
+            write_index_to_writer(index, &mut bytes).unwrap();
+            bytes
+        };
+
+        let quantizer = flat::FlatIndexImpl::new_l2(D).unwrap();
+        let mut whole =
+            IVFScalarQuantizerIndexImpl::new_l2(quantizer, D, QuantizerType::QT_8bit, 1).unwrap();
+        whole.train(&some_data).unwrap();
+        let whole = serialized(&whole);
+
+        let quantizer = flat::FlatIndexImpl::new_l2(D).unwrap();
+        let mut index =
+            IVFScalarQuantizerIndexImpl::new_l2(quantizer, D, QuantizerType::QT_8bit, 1).unwrap();
+        index.train_quantizer(&some_data).unwrap();
+        let untrained = serialized(&index);
+        let assign = index.quantizer_assign(&some_data, 1).unwrap().labels;
+        assert_eq!(
+            index.train_encoder(&some_data, &assign[1..]),
+            Err(Error::BadDimension)
+        );
+        index.train_encoder(&some_data, &assign).unwrap();
+        assert!(!index.is_trained());
+
+        // the scalar quantizer ranges are now the same as after `train`,
+        // leaving only the trained flag of the index to differ
+        let trained = serialized(&index);
+        assert!(untrained.len() < whole.len());
+        assert_eq!(trained.len(), whole.len());
+        let differing = trained.iter().zip(&whole).filter(|(a, b)| a != b).count();
+        assert_eq!(differing, 1);
+    }
+
+    #[test]
+    fn ivf_sq_code_size() {
+        let quantizer = flat::FlatIndexImpl::new_l2(D).unwrap();
+        let mut index =
+            IVFScalarQuantizerIndexImpl::new_l2(quantizer, D, QuantizerType::QT_8bit, 2).unwrap();
+        assert_eq!(index.code_size().unwrap(), D as usize);
+
+        let some_data: Vec<f32> = (0..20 * D).map(|i| (i % 11) as f32).collect();
+        index.train(&some_data).unwrap();
+        index.add(&some_data).unwrap();
+        assert_eq!(index.total_codes_bytes().unwrap(), 20 * D as usize);
+    }
 }
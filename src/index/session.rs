@@ -0,0 +1,139 @@
+//! Reusable buffers for searching native indexes without allocating.
+//!
+//! Each call to `Index::search` allocates new vectors for the distances and
+//! labels of the result. A [`SearchSession`] instead keeps these buffers
+//! across searches, and lends the results of each search as a
+//! [`SearchView`] of borrowed slices, valid until the next search.
+//!
+//! [`SearchSession`]: struct.SearchSession.html
+//! [`SearchView`]: struct.SearchView.html
+
+use super::{checked_idx_t, checked_result_len, Idx, NativeIndex, SearchResult};
+use crate::error::{Error, Result};
+use crate::faiss_try;
+use faiss_sys::*;
+
+/// Buffers for the results of searches for the `k` closest vectors, which
+/// are reused by every search made with the session.
+#[derive(Debug, Clone)]
+pub struct SearchSession {
+    k: usize,
+    distances: Vec<f32>,
+    labels: Vec<Idx>,
+}
+
+/// The borrowed outcome of a search made with a `SearchSession`, laid out
+/// as in `SearchResult`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchView<'a> {
+    pub distances: &'a [f32],
+    pub labels: &'a [Idx],
+}
+
+impl SearchView<'_> {
+    /// Copy the borrowed result into a new `SearchResult`.
+    pub fn to_result(&self) -> SearchResult {
+        SearchResult {
+            distances: self.distances.to_vec(),
+            labels: self.labels.to_vec(),
+        }
+    }
+}
+
+impl SearchSession {
+    /// Create a session for searches of the `k` closest vectors, with
+    /// buffers sized for batches of up to `max_queries` queries. Larger
+    /// batches can still be searched, growing the buffers as needed.
+    pub fn new(k: usize, max_queries: usize) -> Result<Self> {
+        let len = checked_result_len(max_queries, k)?;
+        Ok(SearchSession {
+            k,
+            distances: vec![0.; len],
+            labels: vec![Idx::none(); len],
+        })
+    }
+
+    /// The number of neighbors searched for each query.
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Perform a search for the `k` closest vectors to the given query
+    /// vectors, as in `Index::search`, writing the result into the buffers
+    /// of the session.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::BadDimension` if the queries do not match the
+    /// dimensionality of the index.
+    pub fn search<I>(&mut self, index: &mut I, query: &[f32]) -> Result<SearchView<'_>>
+    where
+        I: ?Sized + NativeIndex,
+    {
+        let d = index.d() as usize;
+        if d == 0 || !query.chunks_exact(d).remainder().is_empty() {
+            return Err(Error::BadDimension);
+        }
+        if self.k == 0 {
+            return Ok(SearchView {
+                distances: &[],
+                labels: &[],
+            });
+        }
+        let nq = query.len() / d;
+        let len = checked_result_len(nq, self.k)?;
+        if self.labels.len() < len {
+            self.distances.resize(len, 0.);
+            self.labels.resize(len, Idx::none());
+        }
+        unsafe {
+            faiss_try(faiss_Index_search(
+                index.inner_ptr(),
+                checked_idx_t(nq)?,
+                query.as_ptr(),
+                checked_idx_t(self.k)?,
+                self.distances.as_mut_ptr(),
+                self.labels.as_mut_ptr() as *mut _,
+            ))?;
+        }
+        Ok(SearchView {
+            distances: &self.distances[..len],
+            labels: &self.labels[..len],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SearchSession;
+    use crate::error::Error;
+    use crate::index::flat::FlatIndexImpl;
+    use crate::index::Index;
+
+    const D: u32 = 4;
+
+    #[test]
+    fn search_session_reuse() {
+        let mut index = FlatIndexImpl::new_l2(D).unwrap();
+        let some_data: Vec<f32> = (0..32 * D).map(|i| ((i * 7) % 23) as f32).collect();
+        index.add(&some_data).unwrap();
+
+        let mut session = SearchSession::new(3, 2).unwrap();
+        let ptr = session.labels.as_ptr();
+        for i in 0..1000 {
+            let start = (i % 31) * D as usize;
+            let query = &some_data[start..start + 2 * D as usize];
+            let expected = index.search(query, 3).unwrap();
+            let view = session.search(&mut index, query).unwrap();
+            assert_eq!(view.to_result(), expected);
+        }
+        assert_eq!(session.labels.as_ptr(), ptr);
+
+        // larger batches grow the buffers
+        let view = session.search(&mut index, &some_data[..16]).unwrap();
+        assert_eq!(view.labels.len(), 4 * 3);
+
+        let r = session.search(&mut index, &some_data[..3]);
+        assert_eq!(r, Err(Error::BadDimension));
+    }
+}
@@ -0,0 +1,154 @@
+//! Shared access to any index through a lock.
+//!
+//! Only some index types can be searched concurrently through an immutable
+//! reference, as provided by [`ConcurrentIndex`]. The [`SyncIndex`] wrapper
+//! makes any index shareable across threads, by holding it behind a mutex
+//! which is locked for every operation made through a shared reference.
+//! Operations are therefore serialized, rather than run in parallel.
+//!
+//! [`ConcurrentIndex`]: ../trait.ConcurrentIndex.html
+//! [`SyncIndex`]: struct.SyncIndex.html
+
+use super::*;
+
+use std::sync::{Mutex, MutexGuard};
+
+/// An index wrapper which implements `ConcurrentIndex` for any index, by
+/// locking a mutex around it. Operations made through an exclusive
+/// reference do not need to lock it.
+#[derive(Debug)]
+pub struct SyncIndex<I> {
+    index: Mutex<I>,
+}
+
+impl<I> SyncIndex<I>
+where
+    I: Index,
+{
+    /// Wrap the given index.
+    pub fn new(index: I) -> Self {
+        SyncIndex {
+            index: Mutex::new(index),
+        }
+    }
+
+    /// Lock the wrapped index, waiting for other threads to release it.
+    pub fn lock(&self) -> MutexGuard<'_, I> {
+        // a panic while searching leaves the native index in a usable state
+        self.index.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Obtain a mutable reference to the wrapped index, without locking.
+    pub fn inner_mut(&mut self) -> &mut I {
+        self.index.get_mut().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Discard the wrapper, recovering the wrapped index.
+    pub fn into_inner(self) -> I {
+        self.index.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<I> Index for SyncIndex<I>
+where
+    I: Index,
+{
+    forward_index_methods! {
+        lock, inner_mut;
+        is_trained,
+        ntotal,
+        d,
+        metric_type,
+        add,
+        add_with_ids,
+        train,
+        assign,
+        search,
+        range_search,
+        reset,
+        remove_ids,
+        verbose,
+        set_verbose,
+        sa_code_size,
+        sa_encode,
+        sa_decode_into,
+        reconstruct,
+        reconstruct_n,
+    }
+}
+
+impl<I> ConcurrentIndex for SyncIndex<I>
+where
+    I: Index,
+{
+    fn assign(&self, q: &[f32], k: usize) -> Result<AssignSearchResult> {
+        self.lock().assign(q, k)
+    }
+
+    fn search(&self, q: &[f32], k: usize) -> Result<SearchResult> {
+        self.lock().search(q, k)
+    }
+
+    fn range_search(&self, q: &[f32], radius: f32) -> Result<RangeSearchResult> {
+        self.lock().range_search(q, radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SyncIndex;
+    use crate::index::flat::FlatIndexImpl;
+    use crate::index::ivf_flat::IVFFlatIndexImpl;
+    use crate::index::{ConcurrentIndex, Idx, Index};
+    use std::sync::Arc;
+
+    const D: u32 = 2;
+
+    #[test]
+    fn sync_index_shared_ivf_flat() {
+        let quantizer = FlatIndexImpl::new_l2(D).unwrap();
+        let mut index = SyncIndex::new(IVFFlatIndexImpl::new_l2(quantizer, D, 2).unwrap());
+        let some_data = [0., 0., 1., 0., 100., 0., 101., 0.];
+        index.train(&some_data).unwrap();
+        index.add(&some_data).unwrap();
+        let index = Arc::new(index);
+
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let index = index.clone();
+                std::thread::spawn(move || {
+                    let my_query = [if i % 2 == 0 { 0.2 } else { 100.8 }, 0.];
+                    (0..50)
+                        .map(|_| ConcurrentIndex::search(&*index, &my_query, 1).unwrap())
+                        .map(|result| (i, result.labels))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        for handle in handles {
+            for (i, labels) in handle.join().unwrap() {
+                let expected = if i % 2 == 0 { 0 } else { 3 };
+                assert_eq!(labels, vec![Idx::new(expected)]);
+            }
+        }
+
+        let index = Arc::try_unwrap(index).unwrap().into_inner();
+        assert_eq!(index.ntotal(), 4);
+    }
+
+    #[test]
+    fn sync_index_usable_after_panic() {
+        let index = Arc::new(SyncIndex::new(FlatIndexImpl::new_l2(D).unwrap()));
+        let poisoner = index.clone();
+        let panicked = std::thread::spawn(move || {
+            let _guard = poisoner.lock();
+            panic!("holding the lock");
+        })
+        .join();
+        assert!(panicked.is_err());
+
+        index.lock().add(&[1., 2.]).unwrap();
+        let result = ConcurrentIndex::search(&*index, &[1., 2.], 1).unwrap();
+        assert_eq!(result.labels, vec![Idx::new(0)]);
+    }
+}
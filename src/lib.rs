@@ -24,6 +24,14 @@
 //! "faiss" = {version = "0.12.0", features = ["gpu"]}
 //! ```
 //!
+//! ## ndarray support
+//!
+//! Enable the `ndarray` Cargo feature to add and search vectors in the form
+//! of two-dimensional arrays from the [`ndarray`](https://docs.rs/ndarray)
+//! crate. See the [`index::array`] module for more.
+//!
+//! [`index::array`]: index/array/index.html
+//!
 //! # Examples
 //!
 //! The [`Index`] trait is one of the center-pieces of this library. Index
@@ -102,14 +110,65 @@ pub use index::flat::FlatIndex;
 pub use index::id_map::IdMap;
 pub use index::io::{read_index, write_index};
 pub use index::lsh::LshIndex;
-pub use index::{index_factory, ConcurrentIndex, Idx, Index};
+pub use index::{index_factory, ConcurrentIndex, Idx, Index, IndexExt};
 pub use metric::MetricType;
 
 #[cfg(feature = "gpu")]
-pub use gpu::{GpuResources, StandardGpuResources};
+pub use gpu::{GpuClonerOptions, GpuMultipleClonerOptions, GpuResources, StandardGpuResources};
 #[cfg(feature = "gpu")]
 pub use index::gpu::GpuIndexImpl;
 
+/// Obtain the version of Faiss targeted by this crate, in the form
+/// `"major.minor.patch"`.
+///
+/// The Faiss C API does not provide a way to query the version of the linked
+/// library at run time, so this is the version of the C API which the native
+/// bindings were generated for. Linking against a different version of Faiss
+/// may work, but is not guaranteed to.
+pub fn version() -> String {
+    format!(
+        "{}.{}.{}",
+        faiss_sys::FAISS_VERSION_MAJOR,
+        faiss_sys::FAISS_VERSION_MINOR,
+        faiss_sys::FAISS_VERSION_PATCH
+    )
+}
+
+/// The SIMD instruction sets supported by the host CPU, as reported by
+/// [`simd_support`](fn.simd_support.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SimdInfo {
+    /// Whether the CPU supports AVX2
+    pub avx2: bool,
+    /// Whether the CPU supports the AVX-512 foundation instructions
+    pub avx512f: bool,
+    /// Whether the CPU supports NEON
+    pub neon: bool,
+}
+
+/// Detect the SIMD instruction sets which the host CPU supports.
+///
+/// The Faiss C API does not report which instruction sets the linked
+/// library was compiled for, so this only tells whether an optimized build
+/// of Faiss (such as `libfaiss_avx2`) could be used on this machine. Faiss
+/// built from source by `faiss-sys` uses the generic, non-specialized code
+/// paths.
+pub fn simd_support() -> SimdInfo {
+    #[allow(unused_mut)]
+    let mut info = SimdInfo::default();
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        info.avx2 = is_x86_feature_detected!("avx2");
+        info.avx512f = is_x86_feature_detected!("avx512f");
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        // NEON is mandatory on AArch64
+        info.neon = true;
+    }
+    info
+}
+
 pub(crate) fn faiss_try(code: std::os::raw::c_int) -> Result<(), crate::error::NativeError> {
     if code != 0 {
         Err(crate::error::NativeError::from_last_error(code))
@@ -117,3 +176,22 @@ pub(crate) fn faiss_try(code: std::os::raw::c_int) -> Result<(), crate::error::N
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn version_is_semver() {
+        let version = super::version();
+        assert!(!version.is_empty());
+        let parts: Vec<u32> = version.split('.').map(|p| p.parse().unwrap()).collect();
+        assert_eq!(parts.len(), 3);
+    }
+
+    #[test]
+    fn simd_support_detected() {
+        let info = super::simd_support();
+        // AVX-512 implies AVX2
+        assert!(!info.avx512f || info.avx2);
+        assert!(!(info.avx2 && info.neon));
+    }
+}
@@ -43,40 +43,40 @@ macro_rules! impl_native_index {
     ($t:ty) => {
         impl crate::index::Index for $t {
             fn is_trained(&self) -> bool {
-                unsafe { faiss_Index_is_trained(self.inner_ptr()) != 0 }
+                unsafe { faiss_Index_is_trained(crate::index::live_ptr(self.inner_ptr())) != 0 }
             }
 
             fn ntotal(&self) -> u64 {
-                unsafe { faiss_Index_ntotal(self.inner_ptr()) as u64 }
+                unsafe { faiss_Index_ntotal(crate::index::live_ptr(self.inner_ptr())) as u64 }
             }
 
             fn d(&self) -> u32 {
-                unsafe { faiss_Index_d(self.inner_ptr()) as u32 }
+                unsafe { faiss_Index_d(crate::index::live_ptr(self.inner_ptr())) as u32 }
             }
 
             fn metric_type(&self) -> crate::metric::MetricType {
                 unsafe {
-                    crate::metric::MetricType::from_code(
-                        faiss_Index_metric_type(self.inner_ptr()) as u32
-                    )
+                    crate::metric::MetricType::from_code(faiss_Index_metric_type(
+                        crate::index::live_ptr(self.inner_ptr()),
+                    ) as u32)
                     .unwrap()
                 }
             }
 
             fn add(&mut self, x: &[f32]) -> Result<()> {
                 unsafe {
-                    let n = x.len() / self.d() as usize;
-                    faiss_try(faiss_Index_add(self.inner_ptr(), n as i64, x.as_ptr()))?;
+                    let n = crate::index::checked_idx_t(x.len() / self.d() as usize)?;
+                    faiss_try(faiss_Index_add(self.inner_ptr(), n, x.as_ptr()))?;
                     Ok(())
                 }
             }
 
             fn add_with_ids(&mut self, x: &[f32], xids: &[crate::index::Idx]) -> Result<()> {
                 unsafe {
-                    let n = x.len() / self.d() as usize;
+                    let n = crate::index::checked_idx_t(x.len() / self.d() as usize)?;
                     faiss_try(faiss_Index_add_with_ids(
                         self.inner_ptr(),
-                        n as i64,
+                        n,
                         x.as_ptr(),
                         xids.as_ptr() as *const _,
                     ))?;
@@ -85,8 +85,8 @@ macro_rules! impl_native_index {
             }
             fn train(&mut self, x: &[f32]) -> Result<()> {
                 unsafe {
-                    let n = x.len() / self.d() as usize;
-                    faiss_try(faiss_Index_train(self.inner_ptr(), n as i64, x.as_ptr()))?;
+                    let n = crate::index::checked_idx_t(x.len() / self.d() as usize)?;
+                    faiss_try(faiss_Index_train(self.inner_ptr(), n, x.as_ptr()))?;
                     Ok(())
                 }
             }
@@ -95,29 +95,37 @@ macro_rules! impl_native_index {
                 query: &[f32],
                 k: usize,
             ) -> Result<crate::index::AssignSearchResult> {
+                if k == 0 {
+                    return Ok(Default::default());
+                }
                 unsafe {
                     let nq = query.len() / self.d() as usize;
-                    let mut out_labels = vec![Idx::none(); k * nq];
+                    let mut out_labels =
+                        vec![Idx::none(); crate::index::checked_result_len(nq, k)?];
                     faiss_try(faiss_Index_assign(
                         self.inner_ptr(),
-                        nq as idx_t,
+                        crate::index::checked_idx_t(nq)?,
                         query.as_ptr(),
                         out_labels.as_mut_ptr() as *mut _,
-                        k as i64,
+                        crate::index::checked_idx_t(k)?,
                     ))?;
                     Ok(crate::index::AssignSearchResult { labels: out_labels })
                 }
             }
             fn search(&mut self, query: &[f32], k: usize) -> Result<crate::index::SearchResult> {
+                if k == 0 {
+                    return Ok(Default::default());
+                }
                 unsafe {
                     let nq = query.len() / self.d() as usize;
-                    let mut distances = vec![0_f32; k * nq];
-                    let mut labels = vec![Idx::none(); k * nq];
+                    let len = crate::index::checked_result_len(nq, k)?;
+                    let mut distances = vec![0_f32; len];
+                    let mut labels = vec![Idx::none(); len];
                     faiss_try(faiss_Index_search(
                         self.inner_ptr(),
-                        nq as idx_t,
+                        crate::index::checked_idx_t(nq)?,
                         query.as_ptr(),
-                        k as idx_t,
+                        crate::index::checked_idx_t(k)?,
                         distances.as_mut_ptr(),
                         labels.as_mut_ptr() as *mut _,
                     ))?;
@@ -130,7 +138,7 @@ macro_rules! impl_native_index {
                 radius: f32,
             ) -> Result<crate::index::RangeSearchResult> {
                 unsafe {
-                    let nq = (query.len() / self.d() as usize) as idx_t;
+                    let nq = crate::index::checked_idx_t(query.len() / self.d() as usize)?;
                     let mut p_res: *mut FaissRangeSearchResult = ::std::ptr::null_mut();
                     faiss_try(faiss_RangeSearchResult_new(&mut p_res, nq))?;
                     faiss_try(faiss_Index_range_search(
@@ -172,6 +180,73 @@ macro_rules! impl_native_index {
                     faiss_Index_set_verbose(self.inner_ptr(), std::os::raw::c_int::from(value));
                 }
             }
+
+            fn sa_code_size(&self) -> Result<usize> {
+                unsafe {
+                    let mut size = 0;
+                    faiss_try(faiss_Index_sa_code_size(self.inner_ptr(), &mut size))?;
+                    Ok(size)
+                }
+            }
+
+            fn sa_encode(&self, x: &[f32]) -> Result<Vec<u8>> {
+                unsafe {
+                    let n = x.len() / self.d() as usize;
+                    let mut codes =
+                        vec![0_u8; crate::index::checked_result_len(n, self.sa_code_size()?)?];
+                    faiss_try(faiss_Index_sa_encode(
+                        self.inner_ptr(),
+                        crate::index::checked_idx_t(n)?,
+                        x.as_ptr(),
+                        codes.as_mut_ptr(),
+                    ))?;
+                    Ok(codes)
+                }
+            }
+
+            fn sa_decode_into(&self, codes: &[u8], out: &mut [f32]) -> Result<()> {
+                unsafe {
+                    let n = crate::index::checked_decode_len(
+                        self.sa_code_size()?,
+                        self.d(),
+                        codes.len(),
+                        out.len(),
+                    )?;
+                    faiss_try(faiss_Index_sa_decode(
+                        self.inner_ptr(),
+                        n,
+                        codes.as_ptr(),
+                        out.as_mut_ptr(),
+                    ))?;
+                    Ok(())
+                }
+            }
+
+            fn reconstruct(&self, key: Idx) -> Result<Vec<f32>> {
+                unsafe {
+                    let mut output = vec![0_f32; self.d() as usize];
+                    faiss_try(faiss_Index_reconstruct(
+                        self.inner_ptr(),
+                        key.to_native(),
+                        output.as_mut_ptr(),
+                    ))?;
+                    Ok(output)
+                }
+            }
+
+            fn reconstruct_n(&self, first_key: Idx, count: usize) -> Result<Vec<f32>> {
+                unsafe {
+                    let mut output =
+                        vec![0_f32; crate::index::checked_result_len(count, self.d() as usize)?];
+                    faiss_try(faiss_Index_reconstruct_n(
+                        self.inner_ptr(),
+                        first_key.to_native(),
+                        crate::index::checked_idx_t(count)?,
+                        output.as_mut_ptr(),
+                    ))?;
+                    Ok(output)
+                }
+            }
         }
     };
 }
@@ -184,30 +259,38 @@ macro_rules! impl_concurrent_index {
             Self: crate::index::Index + crate::index::NativeIndex,
         {
             fn assign(&self, query: &[f32], k: usize) -> Result<AssignSearchResult> {
+                if k == 0 {
+                    return Ok(Default::default());
+                }
                 unsafe {
                     let nq = query.len() / self.d() as usize;
-                    let mut out_labels = vec![Idx::none(); k * nq];
+                    let mut out_labels =
+                        vec![Idx::none(); crate::index::checked_result_len(nq, k)?];
                     faiss_try(faiss_Index_assign(
                         self.inner_ptr(),
-                        nq as idx_t,
+                        crate::index::checked_idx_t(nq)?,
                         query.as_ptr(),
                         out_labels.as_mut_ptr() as *mut _,
-                        k as i64,
+                        crate::index::checked_idx_t(k)?,
                     ))?;
                     Ok(AssignSearchResult { labels: out_labels })
                 }
             }
 
             fn search(&self, query: &[f32], k: usize) -> Result<SearchResult> {
+                if k == 0 {
+                    return Ok(Default::default());
+                }
                 unsafe {
                     let nq = query.len() / self.d() as usize;
-                    let mut distances = vec![0_f32; k * nq];
-                    let mut labels = vec![Idx::none(); k * nq];
+                    let len = crate::index::checked_result_len(nq, k)?;
+                    let mut distances = vec![0_f32; len];
+                    let mut labels = vec![Idx::none(); len];
                     faiss_try(faiss_Index_search(
                         self.inner_ptr(),
-                        nq as idx_t,
+                        crate::index::checked_idx_t(nq)?,
                         query.as_ptr(),
-                        k as idx_t,
+                        crate::index::checked_idx_t(k)?,
                         distances.as_mut_ptr(),
                         labels.as_mut_ptr() as *mut _,
                     ))?;
@@ -217,7 +300,7 @@ macro_rules! impl_concurrent_index {
 
             fn range_search(&self, query: &[f32], radius: f32) -> Result<RangeSearchResult> {
                 unsafe {
-                    let nq = (query.len() / self.d() as usize) as idx_t;
+                    let nq = crate::index::checked_idx_t(query.len() / self.d() as usize)?;
                     let mut p_res: *mut FaissRangeSearchResult = ptr::null_mut();
                     faiss_try(faiss_RangeSearchResult_new(&mut p_res, nq))?;
                     faiss_try(faiss_Index_range_search(
@@ -233,3 +316,129 @@ macro_rules! impl_concurrent_index {
         }
     };
 }
+
+/// A macro which implements the named `Index` methods of a wrapper type by
+/// forwarding them to the wrapped index. It is invoked inside the wrapper's
+/// `Index` implementation, next to the methods which the wrapper implements
+/// itself. The wrapped index is obtained through the two given methods of the
+/// wrapper, for shared and exclusive access respectively.
+macro_rules! forward_index_methods {
+    ($get:ident, $get_mut:ident; $($method:ident),* $(,)?) => {
+        $(forward_index_methods!(@method $get, $get_mut, $method);)*
+    };
+    (@method $get:ident, $get_mut:ident, is_trained) => {
+        fn is_trained(&self) -> bool {
+            self.$get().is_trained()
+        }
+    };
+    (@method $get:ident, $get_mut:ident, ntotal) => {
+        fn ntotal(&self) -> u64 {
+            self.$get().ntotal()
+        }
+    };
+    (@method $get:ident, $get_mut:ident, d) => {
+        fn d(&self) -> u32 {
+            self.$get().d()
+        }
+    };
+    (@method $get:ident, $get_mut:ident, metric_type) => {
+        fn metric_type(&self) -> crate::metric::MetricType {
+            self.$get().metric_type()
+        }
+    };
+    (@method $get:ident, $get_mut:ident, add) => {
+        fn add(&mut self, x: &[f32]) -> crate::error::Result<()> {
+            self.$get_mut().add(x)
+        }
+    };
+    (@method $get:ident, $get_mut:ident, add_with_ids) => {
+        fn add_with_ids(
+            &mut self,
+            x: &[f32],
+            xids: &[crate::index::Idx],
+        ) -> crate::error::Result<()> {
+            self.$get_mut().add_with_ids(x, xids)
+        }
+    };
+    (@method $get:ident, $get_mut:ident, train) => {
+        fn train(&mut self, x: &[f32]) -> crate::error::Result<()> {
+            self.$get_mut().train(x)
+        }
+    };
+    (@method $get:ident, $get_mut:ident, assign) => {
+        fn assign(
+            &mut self,
+            q: &[f32],
+            k: usize,
+        ) -> crate::error::Result<crate::index::AssignSearchResult> {
+            self.$get_mut().assign(q, k)
+        }
+    };
+    (@method $get:ident, $get_mut:ident, search) => {
+        fn search(
+            &mut self,
+            q: &[f32],
+            k: usize,
+        ) -> crate::error::Result<crate::index::SearchResult> {
+            self.$get_mut().search(q, k)
+        }
+    };
+    (@method $get:ident, $get_mut:ident, range_search) => {
+        fn range_search(
+            &mut self,
+            q: &[f32],
+            radius: f32,
+        ) -> crate::error::Result<crate::index::RangeSearchResult> {
+            self.$get_mut().range_search(q, radius)
+        }
+    };
+    (@method $get:ident, $get_mut:ident, reset) => {
+        fn reset(&mut self) -> crate::error::Result<()> {
+            self.$get_mut().reset()
+        }
+    };
+    (@method $get:ident, $get_mut:ident, remove_ids) => {
+        fn remove_ids(&mut self, sel: &crate::selector::IdSelector) -> crate::error::Result<usize> {
+            self.$get_mut().remove_ids(sel)
+        }
+    };
+    (@method $get:ident, $get_mut:ident, verbose) => {
+        fn verbose(&self) -> bool {
+            self.$get().verbose()
+        }
+    };
+    (@method $get:ident, $get_mut:ident, set_verbose) => {
+        fn set_verbose(&mut self, value: bool) {
+            self.$get_mut().set_verbose(value)
+        }
+    };
+    (@method $get:ident, $get_mut:ident, sa_code_size) => {
+        fn sa_code_size(&self) -> crate::error::Result<usize> {
+            self.$get().sa_code_size()
+        }
+    };
+    (@method $get:ident, $get_mut:ident, sa_encode) => {
+        fn sa_encode(&self, x: &[f32]) -> crate::error::Result<Vec<u8>> {
+            self.$get().sa_encode(x)
+        }
+    };
+    (@method $get:ident, $get_mut:ident, sa_decode_into) => {
+        fn sa_decode_into(&self, codes: &[u8], out: &mut [f32]) -> crate::error::Result<()> {
+            self.$get().sa_decode_into(codes, out)
+        }
+    };
+    (@method $get:ident, $get_mut:ident, reconstruct) => {
+        fn reconstruct(&self, key: crate::index::Idx) -> crate::error::Result<Vec<f32>> {
+            self.$get().reconstruct(key)
+        }
+    };
+    (@method $get:ident, $get_mut:ident, reconstruct_n) => {
+        fn reconstruct_n(
+            &self,
+            first_key: crate::index::Idx,
+            count: usize,
+        ) -> crate::error::Result<Vec<f32>> {
+            self.$get().reconstruct_n(first_key, count)
+        }
+    };
+}
@@ -7,6 +7,20 @@ pub enum MetricType {
     InnerProduct = 0,
     /// Euclidean L2-distance
     L2 = 1,
+    /// L1 (Manhattan) distance
+    L1 = 2,
+    /// L-infinity (Chebyshev) distance
+    Linf = 3,
+    /// Lp distance, where p is given by the metric argument of the index
+    Lp = 4,
+    /// Canberra distance
+    Canberra = 20,
+    /// Bray-Curtis dissimilarity
+    BrayCurtis = 21,
+    /// Jensen-Shannon divergence, between vectors holding probability
+    /// distributions. The entries are assumed to be positive, since any zero
+    /// entry yields a NaN distance.
+    JensenShannon = 22,
 }
 
 impl MetricType {
@@ -15,11 +29,23 @@ impl MetricType {
         self as u32
     }
 
+    /// Whether larger values of this metric mean closer vectors, as is the
+    /// case for the inner product. Otherwise, smaller values are closer.
+    pub fn is_similarity(self) -> bool {
+        self == MetricType::InnerProduct
+    }
+
     /// Obtain a metric type value from the native code.
     pub fn from_code(v: u32) -> Option<Self> {
         match v {
             0 => Some(MetricType::InnerProduct),
             1 => Some(MetricType::L2),
+            2 => Some(MetricType::L1),
+            3 => Some(MetricType::Linf),
+            4 => Some(MetricType::Lp),
+            20 => Some(MetricType::Canberra),
+            21 => Some(MetricType::BrayCurtis),
+            22 => Some(MetricType::JensenShannon),
             _ => None,
         }
     }
@@ -43,6 +43,11 @@ impl IdSelector {
         })
     }
 
+    /// Check whether the given ID is selected.
+    pub fn is_member(&self, id: Idx) -> bool {
+        unsafe { faiss_IDSelector_is_member(self.inner, id.to_native()) != 0 }
+    }
+
     /// Return the inner pointer
     pub fn inner_ptr(&self) -> *mut FaissIDSelector {
         self.inner
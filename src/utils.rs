@@ -1,15 +1,191 @@
+use crate::error::{Error, Result};
+use crate::index::flat::FlatIndexImpl;
+use crate::index::{Index, SearchResult};
+use crate::metric::MetricType;
+
 /// L2-renormalize a set of vector. Nothing done if the vector is 0-normed
 pub fn fvec_renorm_l2(d: usize, nx: usize, fvec: &mut [f32]) {
     unsafe { faiss_sys::faiss_fvec_renorm_L2(d, nx, fvec.as_mut_ptr()) }
 }
 
+/// L2-renormalize each of the `d`-dimensional vectors in `x` in place,
+/// using the native batched implementation. Vectors with a norm of zero are
+/// left unchanged.
+///
+/// # Errors
+///
+/// Returns `Error::BadDimension` if `d` is zero or the length of `x` is not
+/// a multiple of `d`.
+pub fn renorm_l2(x: &mut [f32], d: usize) -> Result<()> {
+    if d == 0 || !x.chunks_exact(d).remainder().is_empty() {
+        return Err(Error::BadDimension);
+    }
+    fvec_renorm_l2(d, x.len() / d, x);
+    Ok(())
+}
+
+/// Compute the squared L2 distance between two vectors, using the native
+/// optimized implementation.
+///
+/// # Errors
+///
+/// Returns `Error::BadDimension` if the vectors have different lengths.
+pub fn fvec_l2sqr(a: &[f32], b: &[f32]) -> Result<f32> {
+    if a.len() != b.len() {
+        return Err(Error::BadDimension);
+    }
+    let mut dis = 0.;
+    unsafe { faiss_sys::faiss_fvec_L2sqr_ny(&mut dis, a.as_ptr(), b.as_ptr(), a.len(), 1) }
+    Ok(dis)
+}
+
+/// Compute the inner product of two vectors, using the native optimized
+/// implementation.
+///
+/// # Errors
+///
+/// Returns `Error::BadDimension` if the vectors have different lengths.
+pub fn fvec_inner_product(a: &[f32], b: &[f32]) -> Result<f32> {
+    if a.len() != b.len() {
+        return Err(Error::BadDimension);
+    }
+    let mut ip = 0.;
+    unsafe { faiss_sys::faiss_fvec_inner_products_ny(&mut ip, a.as_ptr(), b.as_ptr(), a.len(), 1) }
+    Ok(ip)
+}
+
+/// Perform an exact (brute-force) search for the `k` vectors in `y` closest
+/// to each vector in `x` by the given metric, without building an index
+/// beforehand. Both `x` and `y` are C-contiguous sequences of
+/// `d`-dimensional vectors. Labels in the result are the positions of the
+/// neighbors in `y`.
+///
+/// # Errors
+///
+/// Returns `Error::Unsupported` for the `Lp` metric, whose exponent cannot
+/// be specified here. Returns `Error::BadDimension` if `d` is zero or the
+/// length of either `x` or `y` is not a multiple of `d`.
+pub fn knn(x: &[f32], y: &[f32], d: u32, k: usize, metric: MetricType) -> Result<SearchResult> {
+    if metric == MetricType::Lp {
+        return Err(Error::Unsupported("exact search with the Lp metric"));
+    }
+    let du = d as usize;
+    if du == 0
+        || !x.chunks_exact(du).remainder().is_empty()
+        || !y.chunks_exact(du).remainder().is_empty()
+    {
+        return Err(Error::BadDimension);
+    }
+    let mut index = FlatIndexImpl::new(d, metric)?;
+    index.add(y)?;
+    index.search(x, k)
+}
+
+/// Perform an exact search of `x` against `y` by squared L2 distance.
+/// See [`knn`](fn.knn.html) for details.
+pub fn knn_l2sqr(x: &[f32], y: &[f32], d: u32, k: usize) -> Result<SearchResult> {
+    knn(x, y, d, k, MetricType::L2)
+}
+
+/// Perform an exact search of `x` against `y` by maximum inner product.
+/// See [`knn`](fn.knn.html) for details.
+pub fn knn_inner_product(x: &[f32], y: &[f32], d: u32, k: usize) -> Result<SearchResult> {
+    knn(x, y, d, k, MetricType::InnerProduct)
+}
+
+/// Perform an exact search of `x` against `y` by L1 (Manhattan) distance.
+/// See [`knn`](fn.knn.html) for details.
+pub fn knn_l1(x: &[f32], y: &[f32], d: u32, k: usize) -> Result<SearchResult> {
+    knn(x, y, d, k, MetricType::L1)
+}
+
+/// Perform an exact search of `x` against `y` by L-infinity distance.
+/// See [`knn`](fn.knn.html) for details.
+pub fn knn_linf(x: &[f32], y: &[f32], d: u32, k: usize) -> Result<SearchResult> {
+    knn(x, y, d, k, MetricType::Linf)
+}
+
+/// Compute the exact `k` nearest neighbors in the database vectors `xb`
+/// for each query vector in `xq`, for evaluating approximate indexes.
+/// See [`knn`](fn.knn.html) for details.
+pub fn ground_truth(
+    xb: &[f32],
+    xq: &[f32],
+    d: u32,
+    k: usize,
+    metric: MetricType,
+) -> Result<SearchResult> {
+    knn(xq, xb, d, k, metric)
+}
+
+/// Score the recall of an approximate search result against the ground
+/// truth, both obtained with `k` results per query. This is the fraction of
+/// the true `k` nearest neighbors which were also found by the approximate
+/// search, averaged over all queries, ranging from 0 to 1. Empty labels
+/// padding the ground truth, such as when `k` exceeds the number of vectors
+/// searched, are not counted as neighbors to be found.
+///
+/// # Errors
+///
+/// Returns `Error::BadDimension` if `k` is zero, or if the two results
+/// do not have the same length, consisting of `k` entries per query.
+pub fn recall_at_k(approx: &SearchResult, truth: &SearchResult, k: usize) -> Result<f32> {
+    let len = truth.labels.len();
+    if k == 0 || approx.labels.len() != len || len / k * k != len {
+        return Err(Error::BadDimension);
+    }
+    let relevant = truth.labels.iter().filter(|l| l.is_some()).count();
+    if relevant == 0 {
+        return Ok(1.);
+    }
+    let hits: usize = approx
+        .labels
+        .chunks(k)
+        .zip(truth.labels.chunks(k))
+        .map(|(a, t)| a.iter().filter(|l| l.is_some() && t.contains(l)).count())
+        .sum();
+    Ok(hits as f32 / relevant as f32)
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::index::Idx;
 
     const D: u32 = 8;
 
+    #[test]
+    fn renorm_l2_unit_rows() {
+        let mut data = vec![3., 4., 0., 0., -1., 1., 0., 10.];
+        renorm_l2(&mut data, 2).unwrap();
+        for (i, row) in data.chunks(2).enumerate() {
+            let norm: f32 = row.iter().map(|x| x * x).sum();
+            if i == 1 {
+                // zero vectors are kept as they are
+                assert_eq!(row, &[0., 0.]);
+            } else {
+                assert!((norm - 1.).abs() < 1e-6);
+            }
+        }
+        assert!(data.iter().all(|x| !x.is_nan()));
+        assert!((data[0] - 0.6).abs() < 1e-6 && (data[1] - 0.8).abs() < 1e-6);
+
+        assert_eq!(renorm_l2(&mut data, 3), Err(Error::BadDimension));
+        assert_eq!(renorm_l2(&mut data, 0), Err(Error::BadDimension));
+    }
+
+    #[test]
+    fn fvec_distances() {
+        let a = [1., 2., 3., -1.];
+        let b = [0., 4., 1., 1.];
+        assert_eq!(fvec_l2sqr(&a, &b).unwrap(), 1. + 4. + 4. + 4.);
+        assert_eq!(fvec_inner_product(&a, &b).unwrap(), 8. + 3. - 1.);
+        assert_eq!(fvec_l2sqr(&a, &a).unwrap(), 0.);
+        assert_eq!(fvec_l2sqr(&a, &b[..3]), Err(Error::BadDimension));
+        assert_eq!(fvec_inner_product(&a[..1], &b), Err(Error::BadDimension));
+    }
+
     #[test]
     fn check_fvec_renorm_l2_01() {
         let mut some_data = vec![
@@ -20,4 +196,83 @@ mod tests {
 
         fvec_renorm_l2(D as usize, 5, &mut some_data);
     }
+
+    #[test]
+    fn knn_l1_differs_from_l2() {
+        // (3, 0) is closer to the origin by L1, (2, 2) is closer by L2
+        let data = [3_f32, 0., 2., 2., 10., 10.];
+        let query = [0_f32, 0.];
+
+        let result = knn_l2sqr(&query, &data, 2, 3).unwrap();
+        assert_eq!(result.labels, vec![Idx::new(1), Idx::new(0), Idx::new(2)]);
+        assert_eq!(result.distances, vec![8., 9., 200.]);
+
+        let result = knn_l1(&query, &data, 2, 3).unwrap();
+        assert_eq!(result.labels, vec![Idx::new(0), Idx::new(1), Idx::new(2)]);
+        assert_eq!(result.distances, vec![3., 4., 20.]);
+
+        let result = knn_linf(&query, &data, 2, 2).unwrap();
+        assert_eq!(result.labels, vec![Idx::new(1), Idx::new(0)]);
+    }
+
+    #[test]
+    fn flat_index_recall_at_k() {
+        use crate::index::{index_factory, Index};
+
+        let some_data = [
+            7.5_f32, -7.5, 7.5, -7.5, 7.5, 7.5, 7.5, 7.5, -1., 1., 1., 1., 1., 1., 1., -1., 0., 0.,
+            0., 1., 1., 0., 0., -1., 100., 100., 100., 100., -100., 100., 100., 100., 120., 100.,
+            100., 105., -100., 100., 100., 105.,
+        ];
+        let my_query = [
+            0., 0., 0., 0., 0., 0., 0., 0., 100., 100., 100., 100., 100., 100., 100., 100.,
+        ];
+        let truth = ground_truth(&some_data, &my_query, D, 3, MetricType::L2).unwrap();
+        assert_eq!(truth.labels.len(), 6);
+
+        let mut index = index_factory(D, "Flat", MetricType::L2).unwrap();
+        index.add(&some_data).unwrap();
+        let result = index.search(&my_query, 3).unwrap();
+        assert_eq!(recall_at_k(&result, &truth, 3).unwrap(), 1.);
+
+        // missing one of the six neighbors
+        let mut partial = result.clone();
+        partial.labels[2] = Idx::none();
+        let recall = recall_at_k(&partial, &truth, 3).unwrap();
+        assert!((recall - 5. / 6.).abs() < 1e-6);
+
+        assert_eq!(
+            recall_at_k(&result, &truth, 4).unwrap_err(),
+            Error::BadDimension
+        );
+
+        // the ground truth is padded beyond the five vectors
+        let truth = ground_truth(&some_data, &my_query, D, 6, MetricType::L2).unwrap();
+        let result = index.search(&my_query, 6).unwrap();
+        assert_eq!(recall_at_k(&result, &truth, 6).unwrap(), 1.);
+    }
+
+    #[test]
+    fn knn_bad_dimension() {
+        let data = [3_f32, 0., 2., 2., 10.];
+        let query = [0_f32, 0.];
+        assert_eq!(
+            knn(&query, &data, 2, 1, MetricType::L1).unwrap_err(),
+            Error::BadDimension
+        );
+        assert_eq!(
+            knn(&query, &data, 0, 1, MetricType::L1).unwrap_err(),
+            Error::BadDimension
+        );
+    }
+
+    #[test]
+    fn knn_lp_unsupported() {
+        let data = [3_f32, 0., 2., 2.];
+        let query = [0_f32, 0.];
+        assert!(matches!(
+            knn(&query, &data, 2, 1, MetricType::Lp),
+            Err(Error::Unsupported(_))
+        ));
+    }
 }
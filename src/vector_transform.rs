@@ -1,7 +1,8 @@
 //! Vector transformation implementation
 
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::faiss_try;
+use crate::index::checked_idx_t;
 use faiss_sys::*;
 use std::os::raw::c_int;
 use std::ptr;
@@ -28,9 +29,16 @@ pub trait VectorTransform {
     /// apply transformation and result is pre-allocated
     fn apply_noalloc(&self, x: &[f32]) -> Vec<f32>;
 
-    /// reverse transformation. May not be implemented or may return
-    /// approximate result
-    fn reverse_transform(&self, xt: &[f32]) -> Vec<f32>;
+    /// Reverse the transformation of `n` vectors of dimension `d_out`,
+    /// returning `n * d_in` values. This may not be implemented by the
+    /// transform, or may return an approximate result, such as the
+    /// projection of the original vectors for a dimensionality reduction.
+    ///
+    /// # Error
+    ///
+    /// Returns `Error::BadDimension` if the length of `xt` is not a
+    /// multiple of `d_out`.
+    fn reverse_transform(&self, xt: &[f32]) -> Result<Vec<f32>>;
 }
 
 impl<T> VectorTransform for T
@@ -53,7 +61,7 @@ where
         unsafe {
             faiss_try(faiss_VectorTransform_train(
                 self.inner_ptr(),
-                n as i64,
+                checked_idx_t(n)?,
                 x.as_ptr(),
             ))?;
             Ok(())
@@ -63,7 +71,7 @@ where
     fn apply_noalloc(&self, x: &[f32]) -> Vec<f32> {
         unsafe {
             let n = x.len() / self.d_in() as usize;
-            let mut xt = Vec::with_capacity(n * self.d_out() as usize);
+            let mut xt = vec![0_f32; n * self.d_out() as usize];
             faiss_VectorTransform_apply_noalloc(
                 self.inner_ptr(),
                 n as i64,
@@ -75,19 +83,23 @@ where
         }
     }
 
-    fn reverse_transform(&self, xt: &[f32]) -> Vec<f32> {
+    fn reverse_transform(&self, xt: &[f32]) -> Result<Vec<f32>> {
+        let d_out = self.d_out() as usize;
+        if d_out == 0 || !xt.chunks_exact(d_out).remainder().is_empty() {
+            return Err(Error::BadDimension);
+        }
+        let n = xt.len() / d_out;
+        let len = n.checked_mul(self.d_in() as usize).ok_or(Error::Overflow)?;
+        let mut x = vec![0_f32; len];
         unsafe {
-            let n = xt.len() / self.d_out() as usize;
-            let mut x = Vec::with_capacity(n * self.d_in() as usize);
             faiss_VectorTransform_reverse_transform(
                 self.inner_ptr(),
-                n as i64,
+                checked_idx_t(n)?,
                 xt.as_ptr(),
                 x.as_mut_ptr(),
             );
-
-            x
         }
+        Ok(x)
     }
 }
 
@@ -463,4 +475,28 @@ mod tests {
         assert_eq!(rrt.d_out(), 256);
         assert_eq!(rrt.is_trained(), false);
     }
+
+    #[test]
+    fn pca_matrix_reverse_transform() {
+        // vectors on a plane, which a 4-dimensional PCA preserves
+        let data: Vec<f32> = (0..16)
+            .flat_map(|i| {
+                let (a, b) = (i as f32, (i * i % 7) as f32);
+                vec![a, b, a + b, a - b, 2. * a, 0., b, 1.]
+            })
+            .collect();
+        let mut pca = PCAMatrix::new(8, 4, 0., false).unwrap();
+        pca.train(16, &data).unwrap();
+        assert!(pca.is_trained());
+
+        let xt = pca.apply_noalloc(&data[..8]);
+        assert_eq!(xt.len(), 4);
+        let x = pca.reverse_transform(&xt).unwrap();
+        assert_eq!(x.len(), 8);
+        for (a, b) in x.iter().zip(&data[..8]) {
+            assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+        }
+
+        assert!(pca.reverse_transform(&xt[..3]).is_err());
+    }
 }